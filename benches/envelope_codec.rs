@@ -0,0 +1,163 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Throughput of the envelope codecs for the most frequent realtime messages.
+//!
+//! `full_envelope` is the previous JSON path, which deserialized every message into the whole
+//! `WebSocketMessageEnvelope`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use nakama_rs::envelope_codec::{EnvelopeCodec, Frame, JsonCodec, ProtobufCodec};
+use nakama_rs::rtapi;
+use nakama_rs::socket::{MatchDataSend, WebSocketMessageEnvelope};
+use nanoserde::DeJson;
+use prost::Message;
+
+const MESSAGES: u64 = 1000;
+
+fn match_data_json() -> String {
+    let data: Vec<String> = (0..64).map(|byte| byte.to_string()).collect();
+    format!(
+        r#"{{"match_data":{{"match_id":"2e1c5a36-4b4f-4b4e-9d8e-2f7c1b6a9f00.nakama","presence":{{"user_id":"a3b5c9d1-7e2f-4a6b-8c0d-1e2f3a4b5c6d","session_id":"f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a5b","username":"player","persistence":false,"status":""}},"op_code":3,"data":[{}],"reliable":false}}}}"#,
+        data.join(",")
+    )
+}
+
+fn match_data_protobuf() -> Vec<u8> {
+    rtapi::Envelope {
+        cid: String::new(),
+        message: Some(rtapi::envelope::Message::MatchData(rtapi::MatchData {
+            match_id: "2e1c5a36-4b4f-4b4e-9d8e-2f7c1b6a9f00.nakama".to_owned(),
+            presence: Some(rtapi::UserPresence {
+                user_id: "a3b5c9d1-7e2f-4a6b-8c0d-1e2f3a4b5c6d".to_owned(),
+                session_id: "f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a5b".to_owned(),
+                username: "player".to_owned(),
+                persistence: false,
+                status: None,
+            }),
+            op_code: 3,
+            data: (0..64).collect(),
+            reliable: false,
+        })),
+    }
+    .encode_to_vec()
+}
+
+fn channel_message_json() -> String {
+    r#"{"channel_message":{"channel_id":"2...heroes","message_id":"9b7c2e4a-1d3f-4e5a-8b6c-7d8e9f0a1b2c","code":0,"sender_id":"a3b5c9d1-7e2f-4a6b-8c0d-1e2f3a4b5c6d","username":"player","content":"{\"text\":\"Hello {world}, \\\"quoted\\\"\"}","create_time":"2021-08-04T12:00:00Z","update_time":"2021-08-04T12:00:00Z","persistent":true,"room_name":"heroes","group_id":"","user_id_one":"","user_id_two":""}}"#
+        .to_owned()
+}
+
+fn channel_message_protobuf() -> Vec<u8> {
+    rtapi::Envelope {
+        cid: String::new(),
+        message: Some(rtapi::envelope::Message::ChannelMessage(
+            rtapi::api::ChannelMessage {
+                channel_id: "2...heroes".to_owned(),
+                message_id: "9b7c2e4a-1d3f-4e5a-8b6c-7d8e9f0a1b2c".to_owned(),
+                sender_id: "a3b5c9d1-7e2f-4a6b-8c0d-1e2f3a4b5c6d".to_owned(),
+                username: "player".to_owned(),
+                content: r#"{"text":"Hello {world}, \"quoted\""}"#.to_owned(),
+                persistent: Some(true),
+                room_name: "heroes".to_owned(),
+                ..Default::default()
+            },
+        )),
+    }
+    .encode_to_vec()
+}
+
+fn bench_decode(c: &mut Criterion, name: &str, json: String, protobuf: Vec<u8>) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(MESSAGES));
+
+    group.bench_function("full_envelope", |b| {
+        b.iter(|| {
+            for _ in 0..MESSAGES {
+                let envelope: WebSocketMessageEnvelope =
+                    DeJson::deserialize_json(black_box(&json)).unwrap();
+                black_box(envelope);
+            }
+        })
+    });
+    group.bench_function("json_codec", |b| {
+        b.iter(|| {
+            for _ in 0..MESSAGES {
+                let frame = Frame::Text(black_box(&json).clone());
+                black_box(JsonCodec.decode(frame).unwrap());
+            }
+        })
+    });
+    group.bench_function("protobuf_codec", |b| {
+        b.iter(|| {
+            for _ in 0..MESSAGES {
+                let frame = Frame::Binary(black_box(&protobuf).clone());
+                black_box(ProtobufCodec.decode(frame).unwrap());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn match_data(c: &mut Criterion) {
+    bench_decode(
+        c,
+        "decode_match_data",
+        match_data_json(),
+        match_data_protobuf(),
+    );
+}
+
+fn channel_message(c: &mut Criterion) {
+    bench_decode(
+        c,
+        "decode_channel_message",
+        channel_message_json(),
+        channel_message_protobuf(),
+    );
+}
+
+fn match_data_send(c: &mut Criterion) {
+    let envelope = WebSocketMessageEnvelope {
+        match_data_send: Some(MatchDataSend {
+            match_id: "2e1c5a36-4b4f-4b4e-9d8e-2f7c1b6a9f00.nakama".to_owned(),
+            op_code: 3,
+            data: (0..64).collect(),
+            presences: vec![],
+            reliable: false,
+        }),
+        ..Default::default()
+    };
+
+    let mut group = c.benchmark_group("encode_match_data_send");
+    group.throughput(Throughput::Elements(MESSAGES));
+    group.bench_function("json_codec", |b| {
+        b.iter(|| {
+            for _ in 0..MESSAGES {
+                black_box(JsonCodec.encode(black_box(&envelope)));
+            }
+        })
+    });
+    group.bench_function("protobuf_codec", |b| {
+        b.iter(|| {
+            for _ in 0..MESSAGES {
+                black_box(ProtobufCodec.encode(black_box(&envelope)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, match_data, channel_message, match_data_send);
+criterion_main!(benches);
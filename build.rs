@@ -0,0 +1,4 @@
+fn main() {
+    println!("cargo:rerun-if-changed=proto");
+    prost_build::compile_protos(&["proto/rtapi/realtime.proto"], &["proto"]).unwrap();
+}
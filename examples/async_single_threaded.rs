@@ -74,8 +74,14 @@ fn main() {
                             .await;
                         let session = session.unwrap();
                         let session2 = session2.unwrap();
-                        web_socket.connect(&session, true, -1).await;
-                        web_socket2.connect(&session2, true, -1).await;
+                        web_socket
+                            .connect(&session, true, -1)
+                            .await
+                            .expect("Failed to connect");
+                        web_socket2
+                            .connect(&session2, true, -1)
+                            .await
+                            .expect("Failed to connect");
                         state.replace(Connected);
                     }
                     JoiningChat => {
@@ -41,7 +41,10 @@ fn main() {
                 .expect("Failed to send status presence");
         });
 
-        socket.connect(&session, true, -1).await;
+        socket
+            .connect(&session, true, -1)
+            .await
+            .expect("Failed to connect");
 
         let status_presence = rx_presence
             .recv()
@@ -0,0 +1,213 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Upgrade a guest account to a permanent account.
+//!
+//! Players usually start with a guest account authenticated by device id and link an email, Google or Apple
+//! account later. If that identity already belongs to another account the server rejects the link.
+//! [`DefaultClient::upgrade_account`] detects the conflict and returns both accounts, so the player can
+//! decide which one to keep with [`DefaultClient::resolve_account_conflict`].
+//!
+//! # Example
+//! ```
+//! # #![feature(async_closure)]
+//! # use nakama_rs::test_helpers::*;
+//! use nakama_rs::account_upgrade::{ConflictResolution, UpgradeResult};
+//! use nakama_rs::auth_request::{AuthProvider, AuthRequest};
+//! # run_in_example(async move |client, session| {
+//! let request = AuthRequest::new(AuthProvider::email("email@domain.com", "password"));
+//! match client.upgrade_account(&session, &request).await? {
+//!     UpgradeResult::Linked => println!("Linked email"),
+//!     UpgradeResult::Conflict(conflict) => {
+//!         println!("Email already belongs to {}", conflict.existing_account.user.username);
+//!         // Let the player choose, e.g. ConflictResolution::SwitchToExisting
+//!         let resolution = ConflictResolution::KeepCurrent;
+//!         client.resolve_account_conflict(&session, conflict, resolution).await?;
+//!     }
+//! }
+//! # Ok(())
+//! # });
+//! ```
+use crate::api::ApiAccount;
+use crate::auth_request::{AuthProvider, AuthRequest};
+use crate::client::Client;
+use crate::client_adapter::ClientAdapter;
+use crate::default_client::{DefaultClient, DefaultClientError};
+use crate::session::Session;
+use log::{error, trace};
+
+/// The identity of an upgrade already belongs to another account.
+#[derive(Debug, Clone)]
+pub struct AccountConflict {
+    /// The account of the current session.
+    pub current_account: ApiAccount,
+    /// The account the identity belongs to.
+    pub existing_account: ApiAccount,
+    existing_session: Session,
+}
+
+impl AccountConflict {
+    /// A session of the existing account.
+    pub fn existing_session(&self) -> &Session {
+        &self.existing_session
+    }
+}
+
+/// The result of [`DefaultClient::upgrade_account`].
+#[derive(Debug, Clone)]
+pub enum UpgradeResult {
+    /// The identity was linked to the current account.
+    Linked,
+    /// The identity already belongs to another account.
+    Conflict(AccountConflict),
+}
+
+/// Which account wins an [`AccountConflict`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// Continue with the existing account and move the device ids of the current account to it.
+    SwitchToExisting,
+    /// Continue with the current account. The identity stays linked to the existing account.
+    KeepCurrent,
+}
+
+impl<A: ClientAdapter + Send + Sync> DefaultClient<A> {
+    /// Link the identity of `request` to the account of `session`.
+    ///
+    /// If the identity already belongs to another account, authenticate with it without creating
+    /// an account and return the summaries of both accounts.
+    pub async fn upgrade_account(
+        &self,
+        session: &Session,
+        request: &AuthRequest,
+    ) -> Result<UpgradeResult, DefaultClientError<A>> {
+        match self.link(session, request).await {
+            Ok(()) => return Ok(UpgradeResult::Linked),
+            Err(err) if err.is_already_exists() => {}
+            Err(err) => return Err(err),
+        }
+
+        trace!(
+            "upgrade_account: Identity already belongs to another account than {}",
+            session.user_id()
+        );
+        let mut existing_request = request.clone();
+        existing_request.create(false);
+        let existing_session = self.authenticate(&existing_request).await?;
+
+        let current_account = self.get_account(session).await?;
+        let existing_account = self.get_account(&existing_session).await?;
+        Ok(UpgradeResult::Conflict(AccountConflict {
+            current_account,
+            existing_account,
+            existing_session,
+        }))
+    }
+
+    /// Resolve an [`AccountConflict`] returned by [`DefaultClient::upgrade_account`].
+    ///
+    /// [`ConflictResolution::SwitchToExisting`] unlinks the device ids from the current account, links them
+    /// to the existing account and replaces the tokens of `session` with the ones of the existing account.
+    /// Afterwards authenticating with the device id signs in to the existing account. If a device id can't be
+    /// linked to the existing account it is linked to the current account again and the error is returned.
+    ///
+    /// The server refuses to unlink the last identity of an account. If the current account has no identity
+    /// besides its device ids, e.g. a guest account, a random custom id is linked to it first and returned.
+    /// It is the only way left to sign in to the guest account, e.g. to move its data or to delete it with a
+    /// server function.
+    pub async fn resolve_account_conflict(
+        &self,
+        session: &Session,
+        conflict: AccountConflict,
+        resolution: ConflictResolution,
+    ) -> Result<Option<String>, DefaultClientError<A>> {
+        if resolution == ConflictResolution::KeepCurrent {
+            return Ok(None);
+        }
+
+        let placeholder = if has_identity_besides_devices(&conflict.current_account) {
+            None
+        } else {
+            let custom_id = uuid::Uuid::new_v4().to_string();
+            trace!(
+                "resolve_account_conflict: Linking custom id {} to guest {}",
+                custom_id,
+                session.user_id()
+            );
+            let request = AuthRequest::new(AuthProvider::custom(&custom_id));
+            self.link(session, &request).await?;
+            Some((custom_id, request))
+        };
+
+        if let Err(err) = self.move_devices(session, &conflict).await {
+            if let Some((_, placeholder)) = placeholder {
+                if let Err(unlink_err) = self.unlink(session, &placeholder).await {
+                    error!(
+                        "resolve_account_conflict: Failed to unlink the placeholder custom id: {}",
+                        unlink_err
+                    );
+                }
+            }
+            return Err(err);
+        }
+
+        session.replace(
+            &conflict.existing_session.get_auth_token(),
+            &conflict
+                .existing_session
+                .get_refresh_token()
+                .unwrap_or_default(),
+        );
+        Ok(placeholder.map(|(custom_id, _)| custom_id))
+    }
+
+    async fn move_devices(
+        &self,
+        session: &Session,
+        conflict: &AccountConflict,
+    ) -> Result<(), DefaultClientError<A>> {
+        for device in conflict.current_account.devices.iter() {
+            let request = AuthRequest::new(AuthProvider::device(&device.id));
+            self.unlink(session, &request).await?;
+            if let Err(err) = self.link(&conflict.existing_session, &request).await {
+                // Otherwise the device id belongs to no account and authenticating with it creates a new one
+                if let Err(rollback_err) = self.link(session, &request).await {
+                    error!(
+                        "resolve_account_conflict: Failed to link device {} again: {}",
+                        device.id, rollback_err
+                    );
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the account can still be signed in to once its device ids are unlinked.
+fn has_identity_besides_devices(account: &ApiAccount) -> bool {
+    let user = &account.user;
+    [
+        &account.custom_id,
+        &account.email,
+        &user.apple_id,
+        &user.facebook_id,
+        &user.facebook_instant_game_id,
+        &user.gamecenter_id,
+        &user.google_id,
+        &user.steam_id,
+    ]
+    .iter()
+    .any(|id| !id.is_empty())
+}
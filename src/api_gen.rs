@@ -14,7 +14,9 @@ pub enum Authentication {
   },
   Bearer {
     token: String
-  }
+  },
+  /// No `Authorization` header, e.g. for requests authorized by the `http_key` query parameter.
+  None
 }
 
 #[derive(Debug, Clone, PartialEq, Copy)]
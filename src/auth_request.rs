@@ -0,0 +1,190 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+/// The identity used to authenticate, link or unlink an account.
+#[derive(Debug, Clone)]
+pub enum AuthProvider {
+    Device {
+        id: String,
+    },
+    Email {
+        email: String,
+        password: String,
+    },
+    Custom {
+        id: String,
+    },
+    Apple {
+        token: String,
+    },
+    Google {
+        token: String,
+    },
+    Facebook {
+        token: String,
+    },
+    FacebookInstantGame {
+        signed_player_info: String,
+    },
+    GameCenter {
+        bundle_id: String,
+        player_id: String,
+        public_key_url: String,
+        salt: String,
+        signature: String,
+        timestamp: String,
+    },
+    Steam {
+        token: String,
+    },
+}
+
+impl AuthProvider {
+    pub fn device(id: &str) -> AuthProvider {
+        AuthProvider::Device { id: id.to_owned() }
+    }
+
+    pub fn email(email: &str, password: &str) -> AuthProvider {
+        AuthProvider::Email {
+            email: email.to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    pub fn custom(id: &str) -> AuthProvider {
+        AuthProvider::Custom { id: id.to_owned() }
+    }
+
+    pub fn apple(token: &str) -> AuthProvider {
+        AuthProvider::Apple {
+            token: token.to_owned(),
+        }
+    }
+
+    pub fn google(token: &str) -> AuthProvider {
+        AuthProvider::Google {
+            token: token.to_owned(),
+        }
+    }
+
+    pub fn facebook(token: &str) -> AuthProvider {
+        AuthProvider::Facebook {
+            token: token.to_owned(),
+        }
+    }
+
+    pub fn facebook_instant_game(signed_player_info: &str) -> AuthProvider {
+        AuthProvider::FacebookInstantGame {
+            signed_player_info: signed_player_info.to_owned(),
+        }
+    }
+
+    pub fn game_center(
+        bundle_id: &str,
+        player_id: &str,
+        public_key_url: &str,
+        salt: &str,
+        signature: &str,
+        timestamp: &str,
+    ) -> AuthProvider {
+        AuthProvider::GameCenter {
+            bundle_id: bundle_id.to_owned(),
+            player_id: player_id.to_owned(),
+            public_key_url: public_key_url.to_owned(),
+            salt: salt.to_owned(),
+            signature: signature.to_owned(),
+            timestamp: timestamp.to_owned(),
+        }
+    }
+
+    pub fn steam(token: &str) -> AuthProvider {
+        AuthProvider::Steam {
+            token: token.to_owned(),
+        }
+    }
+}
+
+/// Options to authenticate, link or unlink an account with an [`AuthProvider`].
+///
+/// `username`, `create` and `vars` only apply when authenticating. See [Authentication](crate::default_client#authentication).
+/// `import` imports the Facebook friends and `sync` the Steam friends of the user. The server default
+/// applies if they are not set.
+///
+/// # Example
+/// ```
+/// use nakama_rs::auth_request::{AuthProvider, AuthRequest};
+///
+/// let mut request = AuthRequest::new(AuthProvider::email("email@domain.com", "password"));
+/// request
+///     .username("Username")
+///     .create(true)
+///     .add_var("key", "value");
+/// ```
+#[derive(Debug, Clone)]
+pub struct AuthRequest {
+    pub provider: AuthProvider,
+    pub username: Option<String>,
+    pub create: bool,
+    pub vars: HashMap<String, String>,
+    pub import: Option<bool>,
+    pub sync: Option<bool>,
+}
+
+impl AuthRequest {
+    pub fn new(provider: AuthProvider) -> AuthRequest {
+        AuthRequest {
+            provider,
+            username: None,
+            create: true,
+            vars: HashMap::new(),
+            import: None,
+            sync: None,
+        }
+    }
+
+    pub fn username(&mut self, username: &str) -> &mut Self {
+        self.username = Some(username.to_owned());
+        self
+    }
+
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Session variables of the new session. Ignored when linking or unlinking.
+    pub fn vars(&mut self, vars: HashMap<&str, &str>) -> &mut Self {
+        for (key, value) in vars {
+            self.add_var(key, value);
+        }
+        self
+    }
+
+    pub fn add_var(&mut self, key: &str, value: &str) -> &mut Self {
+        self.vars.insert(key.to_owned(), value.to_owned());
+        self
+    }
+
+    pub fn import(&mut self, import: bool) -> &mut Self {
+        self.import = Some(import);
+        self
+    }
+
+    pub fn sync(&mut self, sync: bool) -> &mut Self {
+        self.sync = Some(sync);
+        self
+    }
+}
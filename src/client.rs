@@ -20,8 +20,11 @@ use crate::api::{
     ApiUserGroupList, ApiUsers, ApiValidatePurchaseResponse, ApiWriteStorageObject,
 };
 use crate::api_gen::ApiAccount;
+use crate::auth_request::AuthRequest;
+use crate::rpc::RpcId;
 use crate::session::Session;
 use async_trait::async_trait;
+use nanoserde::{DeJson, SerJson};
 use std::collections::HashMap;
 use std::error::Error;
 
@@ -43,6 +46,8 @@ pub trait Client {
         ids: &[&str],
     ) -> Result<(), Self::Error>;
 
+    async fn authenticate(&self, request: &AuthRequest) -> Result<Session, Self::Error>;
+
     async fn authenticate_apple(
         &self,
         token: &str,
@@ -85,6 +90,14 @@ pub trait Client {
         import: bool,
     ) -> Result<Session, Self::Error>;
 
+    async fn authenticate_facebook_instant_game(
+        &self,
+        signed_player_info: &str,
+        username: Option<&str>,
+        create: bool,
+        vars: HashMap<&str, &str>,
+    ) -> Result<Session, Self::Error>;
+
     async fn authenticate_game_center(
         &self,
         bundle_id: &str,
@@ -190,6 +203,8 @@ pub trait Client {
         facebook_ids: &[&str],
     ) -> Result<ApiUsers, Self::Error>;
 
+    async fn healthcheck(&self) -> Result<(), Self::Error>;
+
     async fn import_facebook_friends(
         &self,
         session: &Session,
@@ -221,6 +236,8 @@ pub trait Client {
 
     async fn leave_group(&self, session: &Session, group_id: &str) -> Result<(), Self::Error>;
 
+    async fn link(&self, session: &Session, request: &AuthRequest) -> Result<(), Self::Error>;
+
     async fn link_apple(&self, session: &Session, token: &str) -> Result<(), Self::Error>;
 
     async fn link_custom(&self, session: &Session, id: &str) -> Result<(), Self::Error>;
@@ -241,6 +258,12 @@ pub trait Client {
         import: Option<bool>,
     ) -> Result<(), Self::Error>;
 
+    async fn link_facebook_instant_game(
+        &self,
+        session: &Session,
+        signed_player_info: &str,
+    ) -> Result<(), Self::Error>;
+
     async fn link_game_center(
         &self,
         session: &Session,
@@ -408,6 +431,41 @@ pub trait Client {
         payload: Option<&str>,
     ) -> Result<ApiRpc, Self::Error>;
 
+    async fn rpc_bytes(
+        &self,
+        session: &Session,
+        id: &str,
+        payload: &[u8],
+    ) -> Result<ApiRpc, Self::Error>;
+
+    /// Execute a function declared with [`RpcId`] on the server. See [`crate::rpc`] for an example.
+    async fn rpc_call<R: RpcId>(
+        &self,
+        session: &Session,
+        request: &R::Request,
+    ) -> Result<R::Response, Self::Error> {
+        self.rpc_typed(session, R::ID, request).await
+    }
+
+    async fn rpc_typed<Req: SerJson + Sync, Resp: DeJson + Send>(
+        &self,
+        session: &Session,
+        id: &str,
+        request: &Req,
+    ) -> Result<Resp, Self::Error>;
+
+    async fn rpc_with_http_key(
+        &self,
+        id: &str,
+        payload: Option<&str>,
+    ) -> Result<ApiRpc, Self::Error>;
+
+    async fn rpc_with_http_key_query(
+        &self,
+        id: &str,
+        payload: Option<&str>,
+    ) -> Result<ApiRpc, Self::Error>;
+
     async fn session_logout(&self, session: &Session) -> Result<(), Self::Error>;
 
     async fn session_refresh(
@@ -416,6 +474,8 @@ pub trait Client {
         vars: HashMap<&str, &str>,
     ) -> Result<(), Self::Error>;
 
+    async fn unlink(&self, session: &Session, request: &AuthRequest) -> Result<(), Self::Error>;
+
     async fn unlink_apple(&self, session: &Session, token: &str) -> Result<(), Self::Error>;
 
     async fn unlink_custom(&self, session: &Session, id: &str) -> Result<(), Self::Error>;
@@ -431,6 +491,12 @@ pub trait Client {
 
     async fn unlink_facebook(&self, session: &Session, token: &str) -> Result<(), Self::Error>;
 
+    async fn unlink_facebook_instant_game(
+        &self,
+        session: &Session,
+        signed_player_info: &str,
+    ) -> Result<(), Self::Error>;
+
     async fn unlink_game_center(
         &self,
         session: &Session,
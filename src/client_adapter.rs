@@ -14,6 +14,7 @@
 
 use crate::api::RestRequest;
 use async_trait::async_trait;
+use chrono::Duration;
 use nanoserde::DeJson;
 use std::error::Error;
 
@@ -21,4 +22,19 @@ use std::error::Error;
 pub trait ClientAdapter {
     type Error: Error;
     async fn send<T: DeJson + Send>(&self, request: RestRequest<T>) -> Result<T, Self::Error>;
+
+    /// The HTTP status code the server responded with, if `error` was caused by an error response.
+    ///
+    /// Used by the [`DefaultClient`](crate::DefaultClient) to detect e.g. expired credentials.
+    fn status_code(_error: &Self::Error) -> Option<u16> {
+        None
+    }
+
+    /// The estimated offset of the server clock to the local clock, e.g. from the `Date` header of the
+    /// last response.
+    ///
+    /// Used by the [`DefaultClient`](crate::DefaultClient) to check the session expiry against the server time.
+    fn clock_offset(&self) -> Option<Duration> {
+        None
+    }
 }
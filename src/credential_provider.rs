@@ -0,0 +1,156 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Automatic re-authentication.
+//!
+//! A session can't be refreshed anymore once the refresh token has expired. If a [`CredentialProvider`]
+//! is registered with [`DefaultClient::set_credential_provider`], the client authenticates again
+//! when the refresh token has expired or a request fails because the credentials were rejected.
+//! The [`Session`] is updated in place and the failed request is retried once.
+//!
+//! The provider is set for the whole client. Only the sessions of the user it authenticates are updated, the
+//! requests of other sessions fail with the original error.
+//!
+//! # Example
+//! ```
+//! use nakama_rs::credential_provider::DeviceCredentials;
+//! use nakama_rs::DefaultClient;
+//! use std::collections::HashMap;
+//!
+//! let mut client = DefaultClient::new_with_adapter_and_defaults();
+//! client.set_credential_provider(DeviceCredentials::new("usersdeviceid", HashMap::new()));
+//! ```
+use crate::auth_request::AuthRequest;
+use crate::client::Client;
+use crate::client_adapter::ClientAdapter;
+use crate::default_client::{string_map_to_owned_string_map, DefaultClient, DefaultClientError};
+use crate::session::Session;
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+/// Provides the credentials to authenticate a user again.
+#[async_trait]
+pub trait CredentialProvider<A: ClientAdapter>: Send + Sync {
+    /// Authenticate the user and return the new session.
+    async fn authenticate(&self, client: &DefaultClient<A>)
+        -> Result<Session, DefaultClientError<A>>;
+}
+
+fn borrow_vars(vars: &HashMap<String, String>) -> HashMap<&str, &str> {
+    vars.iter()
+        .map(|(key, val)| (key.as_str(), val.as_str()))
+        .collect()
+}
+
+/// Authenticate again with a stored device id.
+pub struct DeviceCredentials {
+    id: String,
+    vars: HashMap<String, String>,
+}
+
+impl DeviceCredentials {
+    pub fn new(id: &str, vars: HashMap<&str, &str>) -> DeviceCredentials {
+        DeviceCredentials {
+            id: id.to_owned(),
+            vars: string_map_to_owned_string_map(vars),
+        }
+    }
+}
+
+#[async_trait]
+impl<A: ClientAdapter + Send + Sync> CredentialProvider<A> for DeviceCredentials {
+    async fn authenticate(
+        &self,
+        client: &DefaultClient<A>,
+    ) -> Result<Session, DefaultClientError<A>> {
+        client
+            .authenticate_device(&self.id, None, false, borrow_vars(&self.vars))
+            .await
+    }
+}
+
+/// Authenticate again with a stored custom id.
+pub struct CustomCredentials {
+    id: String,
+    vars: HashMap<String, String>,
+}
+
+impl CustomCredentials {
+    pub fn new(id: &str, vars: HashMap<&str, &str>) -> CustomCredentials {
+        CustomCredentials {
+            id: id.to_owned(),
+            vars: string_map_to_owned_string_map(vars),
+        }
+    }
+}
+
+#[async_trait]
+impl<A: ClientAdapter + Send + Sync> CredentialProvider<A> for CustomCredentials {
+    async fn authenticate(
+        &self,
+        client: &DefaultClient<A>,
+    ) -> Result<Session, DefaultClientError<A>> {
+        client
+            .authenticate_custom(&self.id, None, false, borrow_vars(&self.vars))
+            .await
+    }
+}
+
+/// Authenticate again with a stored email and password.
+pub struct EmailCredentials {
+    email: String,
+    password: String,
+    vars: HashMap<String, String>,
+}
+
+impl EmailCredentials {
+    pub fn new(email: &str, password: &str, vars: HashMap<&str, &str>) -> EmailCredentials {
+        EmailCredentials {
+            email: email.to_owned(),
+            password: password.to_owned(),
+            vars: string_map_to_owned_string_map(vars),
+        }
+    }
+}
+
+#[async_trait]
+impl<A: ClientAdapter + Send + Sync> CredentialProvider<A> for EmailCredentials {
+    async fn authenticate(
+        &self,
+        client: &DefaultClient<A>,
+    ) -> Result<Session, DefaultClientError<A>> {
+        client
+            .authenticate_email(
+                &self.email,
+                &self.password,
+                None,
+                false,
+                borrow_vars(&self.vars),
+            )
+            .await
+    }
+}
+
+/// Authenticate again with any [`AuthProvider`](crate::auth_request::AuthProvider).
+///
+/// `create` should usually be `false` so that a deleted account isn't silently recreated.
+#[async_trait]
+impl<A: ClientAdapter + Send + Sync> CredentialProvider<A> for AuthRequest {
+    async fn authenticate(
+        &self,
+        client: &DefaultClient<A>,
+    ) -> Result<Session, DefaultClientError<A>> {
+        client.authenticate(self).await
+    }
+}
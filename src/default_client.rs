@@ -31,8 +31,8 @@
 use crate::api;
 use crate::api::{
     ApiAccount, ApiAccountApple, ApiAccountCustom, ApiAccountDevice, ApiAccountEmail,
-    ApiAccountFacebook, ApiAccountGameCenter, ApiAccountGoogle, ApiAccountSteam,
-    ApiChannelMessageList, ApiCreateGroupRequest, ApiDeleteStorageObjectId,
+    ApiAccountFacebook, ApiAccountFacebookInstantGame, ApiAccountGameCenter, ApiAccountGoogle,
+    ApiAccountSteam, ApiChannelMessageList, ApiCreateGroupRequest, ApiDeleteStorageObjectId,
     ApiDeleteStorageObjectsRequest, ApiEvent, ApiFriendList, ApiGroup, ApiGroupList,
     ApiGroupUserList, ApiLeaderboardRecord, ApiLeaderboardRecordList, ApiLinkSteamRequest,
     ApiMatchList, ApiNotificationList, ApiOverrideOperator, ApiReadStorageObjectId,
@@ -45,21 +45,31 @@ use crate::api::{
     WriteTournamentRecordRequestTournamentRecordWrite,
 };
 use crate::api_gen::{ApiSession, ApiWriteStorageObjectsRequest};
+use crate::auth_request::{AuthProvider, AuthRequest};
 use crate::client::Client;
 use crate::client_adapter::ClientAdapter;
 use crate::config::{DEFAULT_HOST, DEFAULT_PORT, DEFAULT_SERVER_KEY, DEFAULT_SERVER_PASSWORD};
+use crate::credential_provider::CredentialProvider;
+use crate::device_id::DeviceIdProvider;
 use crate::http_adapter::RestHttpAdapter;
+use crate::rpc::{decode_response, Base64Codec, PayloadCodec, PayloadDecodeError, RpcDecodeError};
 use crate::session::Session;
 use async_trait::async_trait;
-use nanoserde::DeJson;
+use log::{debug, warn};
+use nanoserde::{DeJson, SerJson};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
 
 pub struct DefaultClient<A: ClientAdapter> {
     adapter: A,
     server_key: String,
     server_password: String,
+    credential_provider: Option<Arc<dyn CredentialProvider<A>>>,
+    device_id_provider: Arc<DeviceIdProvider>,
+    http_key: Option<String>,
+    payload_codec: Arc<dyn PayloadCodec>,
 }
 
 impl<A: ClientAdapter + Clone> Clone for DefaultClient<A> {
@@ -68,6 +78,10 @@ impl<A: ClientAdapter + Clone> Clone for DefaultClient<A> {
             adapter: self.adapter.clone(),
             server_key: self.server_key.clone(),
             server_password: self.server_password.clone(),
+            credential_provider: self.credential_provider.clone(),
+            device_id_provider: self.device_id_provider.clone(),
+            http_key: self.http_key.clone(),
+            payload_codec: self.payload_codec.clone(),
         }
     }
 }
@@ -102,9 +116,72 @@ impl<A: ClientAdapter + Send + Sync> DefaultClient<A> {
             adapter,
             server_key: server_key.to_owned(),
             server_password: server_password.to_owned(),
+            credential_provider: None,
+            device_id_provider: Arc::new(DeviceIdProvider::default()),
+            http_key: None,
+            payload_codec: Arc::new(Base64Codec),
         }
     }
 
+    /// Register a provider to authenticate again once the session can't be refreshed anymore.
+    ///
+    /// See [`crate::credential_provider`] for details.
+    pub fn set_credential_provider<P: CredentialProvider<A> + 'static>(&mut self, provider: P) {
+        self.credential_provider = Some(Arc::new(provider));
+    }
+
+    /// Set the runtime HTTP key of the server used by [`Client::rpc_with_http_key`].
+    ///
+    /// The key grants access to every RPC function without a session. Only use it in trusted
+    /// environments like backend services, never in a game client.
+    pub fn set_http_key(&mut self, http_key: &str) {
+        self.http_key = Some(http_key.to_owned());
+    }
+
+    fn http_key(&self) -> Result<&str, DefaultClientError<A>> {
+        self.http_key
+            .as_deref()
+            .ok_or_else(|| DefaultClientError::ClientError("No http key set".to_owned()))
+    }
+
+    /// Set the codec used to encode the payload of [`Client::rpc_bytes`]. Defaults to base64.
+    pub fn set_payload_codec<C: PayloadCodec + 'static>(&mut self, codec: C) {
+        self.payload_codec = Arc::new(codec);
+    }
+
+    /// Decode the binary payload of an RPC response with the payload codec.
+    pub fn decode_rpc_payload(&self, rpc: &ApiRpc) -> Result<Vec<u8>, PayloadDecodeError> {
+        rpc.decode_payload(self.payload_codec.as_ref())
+    }
+
+    /// Set the provider of the device id used by [`DefaultClient::authenticate_this_device`].
+    ///
+    /// Defaults to a device id stored in the per-user data directory. See [`crate::device_id`] for details.
+    pub fn set_device_id_provider(&mut self, provider: DeviceIdProvider) {
+        self.device_id_provider = Arc::new(provider);
+    }
+
+    /// The device id of this device.
+    pub fn device_id(&self) -> Result<String, DefaultClientError<A>> {
+        self.device_id_provider.device_id().map_err(|err| {
+            DefaultClientError::ClientError(format!("Failed to get device id: {}", err))
+        })
+    }
+
+    /// Authenticate a user with the device id of this device.
+    ///
+    /// The device id is generated on first use and stays the same afterwards.
+    /// See [Authentication](index.html#authentication) for a description of the `create` and `vars` parameters.
+    pub async fn authenticate_this_device(
+        &self,
+        create: bool,
+        vars: HashMap<&str, &str>,
+    ) -> Result<Session, DefaultClientError<A>> {
+        let device_id = self.device_id()?;
+        self.authenticate_device(&device_id, None, create, vars)
+            .await
+    }
+
     #[inline]
     async fn send<T: DeJson + Send>(
         &self,
@@ -116,26 +193,154 @@ impl<A: ClientAdapter + Send + Sync> DefaultClient<A> {
             .map_err(|err| DefaultClientError::HttpAdapterError(err))
     }
 
-    fn map_session(api_session: ApiSession) -> Session {
-        Session::new(&api_session.token, &api_session.refresh_token)
+    fn map_session(&self, api_session: ApiSession) -> Session {
+        let session = Session::new(&api_session.token, &api_session.refresh_token);
+        self.update_clock_offset(&session);
+        session
+    }
+
+    /// Estimate the clock offset of a session with freshly issued tokens.
+    ///
+    /// Prefers the `Date` header of the last response and falls back to the issue time of the auth token.
+    fn update_clock_offset(&self, session: &Session) {
+        match self.adapter.clock_offset() {
+            Some(offset) => session.set_clock_offset(offset),
+            None => session.update_clock_offset_from_token(),
+        }
     }
 
     async fn refresh_session(
         &self,
         session: &Session,
     ) -> Result<(), <DefaultClient<A> as Client>::Error> {
-        let refresh_token = session.get_refresh_token();
-        let vars = session.vars();
-        let vars = vars.iter().map(|(key, val)| (key.as_str(), val.as_str())).collect();
-        if session.get_auto_refresh() && refresh_token.is_some()
-            && session.will_expire_soon() {
-            return self.session_refresh(session, vars).await
+        if !session.get_auto_refresh() {
+            return Ok(());
         }
+        self.refresh_session_with(session, self.credential_provider.as_ref())
+            .await
+    }
 
-        Ok(())
+    /// Refresh `session` if it expires soon.
+    ///
+    /// Uses the refresh token while it is valid and authenticates again with `provider` otherwise, or if the
+    /// server rejects the refresh token.
+    pub(crate) async fn refresh_session_with(
+        &self,
+        session: &Session,
+        provider: Option<&Arc<dyn CredentialProvider<A>>>,
+    ) -> Result<(), <DefaultClient<A> as Client>::Error> {
+        if !session.will_expire_soon() {
+            return Ok(());
+        }
+
+        if session.get_refresh_token().is_some() && !session.is_refresh_expired() {
+            let vars = session.vars();
+            let vars = vars.iter().map(|(key, val)| (key.as_str(), val.as_str())).collect();
+            match self.session_refresh(session, vars).await {
+                Err(err) if provider.is_some() && err.is_unauthenticated() => {
+                    return if self.reauthenticate_with(session, provider).await? {
+                        Ok(())
+                    } else {
+                        Err(err)
+                    };
+                }
+                result => return result,
+            }
+        }
+
+        self.reauthenticate_with(session, provider)
+            .await
+            .map(|_| ())
+    }
+
+    async fn reauthenticate(
+        &self,
+        session: &Session,
+    ) -> Result<bool, <DefaultClient<A> as Client>::Error> {
+        self.reauthenticate_with(session, self.credential_provider.as_ref())
+            .await
+    }
+
+    /// Authenticate the user of `session` again with `provider`.
+    ///
+    /// Returns whether the tokens of the session were replaced. They are kept if the provider authenticates
+    /// another user than the one of the session.
+    async fn reauthenticate_with(
+        &self,
+        session: &Session,
+        provider: Option<&Arc<dyn CredentialProvider<A>>>,
+    ) -> Result<bool, <DefaultClient<A> as Client>::Error> {
+        let provider = match provider {
+            Some(provider) => provider.clone(),
+            None => return Ok(false),
+        };
+
+        debug!("reauthenticate: Authenticating user {} again", session.user_id());
+        let new_session = provider.authenticate(self).await?;
+        if new_session.user_id() != session.user_id() {
+            warn!(
+                "reauthenticate: Credential provider authenticated user {} instead of {}",
+                new_session.user_id(),
+                session.user_id()
+            );
+            return Ok(false);
+        }
+        session.replace(
+            &new_session.get_auth_token(),
+            &new_session.get_refresh_token().unwrap_or("".to_owned()),
+        );
+        session.set_clock_offset(new_session.clock_offset());
+        Ok(true)
+    }
+
+    /// Send a request on behalf of the user of `session`.
+    ///
+    /// Refreshes the session if necessary. If the server rejects the credentials and a credential provider
+    /// is registered, the user is authenticated again and the request is retried once. The request fails with
+    /// the original error if the provider authenticates another user.
+    async fn send_with_session<T: DeJson + Clone + Send>(
+        &self,
+        session: &Session,
+        mut request: RestRequest<T>,
+    ) -> Result<T, <DefaultClient<A> as Client>::Error> {
+        if session.has_ended() {
+            return Err(DefaultClientError::SessionEnded);
+        }
+        if let Some(offset) = self.adapter.clock_offset() {
+            session.set_clock_offset(offset);
+        }
+        self.refresh_session(session).await?;
+        request.authentication = api::Authentication::Bearer {
+            token: session.get_auth_token(),
+        };
+
+        match self.send(request.clone()).await {
+            Err(err) if self.credential_provider.is_some() && err.is_unauthenticated() => {
+                if !self.reauthenticate(session).await? {
+                    return Err(err);
+                }
+                request.authentication = api::Authentication::Bearer {
+                    token: session.get_auth_token(),
+                };
+                self.send(request).await
+            }
+            result => result,
+        }
     }
 }
 
+fn auth_request(
+    provider: AuthProvider,
+    username: Option<&str>,
+    create: bool,
+    vars: HashMap<&str, &str>,
+) -> AuthRequest {
+    let mut request = AuthRequest::new(provider);
+    request.create(create).vars(vars);
+    request.username = username.map(|username| username.to_owned());
+    request
+}
+
 pub fn str_slice_to_owned(slice: &[&str]) -> Vec<String> {
     slice.iter().map(|id| (*id).to_owned()).collect()
 }
@@ -149,6 +354,31 @@ pub fn string_map_to_owned_string_map(vars: HashMap<&str, &str>) -> HashMap<Stri
 pub enum DefaultClientError<A: ClientAdapter> {
     HttpAdapterError(A::Error),
     ClientError(String),
+    /// The session was logged out. See [`Session::end`].
+    SessionEnded,
+    RpcDecodeError(RpcDecodeError),
+}
+
+impl<A: ClientAdapter> DefaultClientError<A> {
+    /// The HTTP status code of the error response, if the server responded with an error.
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            DefaultClientError::HttpAdapterError(err) => A::status_code(err),
+            DefaultClientError::ClientError(_)
+            | DefaultClientError::SessionEnded
+            | DefaultClientError::RpcDecodeError(_) => None,
+        }
+    }
+
+    /// Returns true if the server rejected the credentials, e.g. because the token has expired.
+    pub fn is_unauthenticated(&self) -> bool {
+        self.status_code() == Some(401)
+    }
+
+    /// Returns true if the identity or username already belongs to another account.
+    pub fn is_already_exists(&self) -> bool {
+        self.status_code() == Some(409)
+    }
 }
 
 impl<A: ClientAdapter> Debug for DefaultClientError<A> {
@@ -156,6 +386,8 @@ impl<A: ClientAdapter> Debug for DefaultClientError<A> {
         match self {
             DefaultClientError::HttpAdapterError(err) => std::fmt::Debug::fmt(err, f),
             DefaultClientError::ClientError(err) => std::fmt::Debug::fmt(err, f),
+            DefaultClientError::SessionEnded => std::fmt::Debug::fmt("Session has ended", f),
+            DefaultClientError::RpcDecodeError(err) => std::fmt::Display::fmt(err, f),
         }
     }
 }
@@ -194,8 +426,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         let ids = str_slice_to_owned(ids);
         let usernames = str_slice_to_owned(usernames);
         let request = api::add_friends(&session.get_auth_token(), &ids, &usernames);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Add users to a group.
@@ -217,8 +448,148 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     ) -> Result<(), Self::Error> {
         let ids = str_slice_to_owned(ids);
         let request = api::add_group_users(&session.get_auth_token(), group_id, &ids);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
+    }
+
+    /// Authenticate a user against the server.
+    ///
+    /// This is the common entry point for all `authenticate_*` functions. See [`AuthRequest`] for the available options.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// use nakama_rs::auth_request::{AuthProvider, AuthRequest};
+    /// # run_in_example(async move |client, session| {
+    /// let mut request = AuthRequest::new(AuthProvider::device("sufficientlylongdeviceid"));
+    /// request.username("Username").create(true);
+    /// let session = client.authenticate(&request).await
+    ///     .expect("Failed to authenticate user");
+    /// # Ok(())
+    /// # });
+    /// ```
+    async fn authenticate(&self, request: &AuthRequest) -> Result<Session, Self::Error> {
+        let server_key = &self.server_key;
+        let server_password = &self.server_password;
+        let create = Some(request.create);
+        let username = request.username.as_deref();
+        let vars = request.vars.clone();
+
+        let session = match request.provider {
+            AuthProvider::Device { ref id } => self.send(api::authenticate_device(
+                server_key,
+                server_password,
+                ApiAccountDevice {
+                    id: id.clone(),
+                    vars,
+                },
+                create,
+                username,
+            )),
+            AuthProvider::Email {
+                ref email,
+                ref password,
+            } => self.send(api::authenticate_email(
+                server_key,
+                server_password,
+                ApiAccountEmail {
+                    email: email.clone(),
+                    password: password.clone(),
+                    vars,
+                },
+                create,
+                username,
+            )),
+            AuthProvider::Custom { ref id } => self.send(api::authenticate_custom(
+                server_key,
+                server_password,
+                ApiAccountCustom {
+                    id: id.clone(),
+                    vars,
+                },
+                create,
+                username,
+            )),
+            AuthProvider::Apple { ref token } => self.send(api::authenticate_apple(
+                server_key,
+                server_password,
+                ApiAccountApple {
+                    token: token.clone(),
+                    vars,
+                },
+                create,
+                username,
+            )),
+            AuthProvider::Google { ref token } => self.send(api::authenticate_google(
+                server_key,
+                server_password,
+                ApiAccountGoogle {
+                    token: token.clone(),
+                    vars,
+                },
+                create,
+                username,
+            )),
+            AuthProvider::Facebook { ref token } => self.send(api::authenticate_facebook(
+                server_key,
+                server_password,
+                ApiAccountFacebook {
+                    token: token.clone(),
+                    vars,
+                },
+                create,
+                username,
+                request.import,
+            )),
+            AuthProvider::FacebookInstantGame {
+                ref signed_player_info,
+            } => self.send(api::authenticate_facebook_instant_game(
+                server_key,
+                server_password,
+                ApiAccountFacebookInstantGame {
+                    signed_player_info: signed_player_info.clone(),
+                    vars,
+                },
+                create,
+                username,
+            )),
+            AuthProvider::GameCenter {
+                ref bundle_id,
+                ref player_id,
+                ref public_key_url,
+                ref salt,
+                ref signature,
+                ref timestamp,
+            } => self.send(api::authenticate_game_center(
+                server_key,
+                server_password,
+                ApiAccountGameCenter {
+                    bundle_id: bundle_id.clone(),
+                    player_id: player_id.clone(),
+                    public_key_url: public_key_url.clone(),
+                    salt: salt.clone(),
+                    signature: signature.clone(),
+                    timestamp_seconds: timestamp.clone(),
+                    vars,
+                },
+                create,
+                username,
+            )),
+            AuthProvider::Steam { ref token } => self.send(api::authenticate_steam(
+                server_key,
+                server_password,
+                ApiAccountSteam {
+                    token: token.clone(),
+                    vars,
+                },
+                create,
+                username,
+                request.sync,
+            )),
+        }
+        .await?;
+
+        Ok(self.map_session(session))
     }
 
     /// Authenticate a user with an Apple ID against the server.
@@ -245,20 +616,13 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         create: bool,
         vars: HashMap<&str, &str>,
     ) -> Result<Session, Self::Error> {
-        let request = api::authenticate_apple(
-            &self.server_key,
-            &self.server_password,
-            ApiAccountApple {
-                token: token.to_owned(),
-                vars: string_map_to_owned_string_map(vars),
-            },
-            Some(create),
+        self.authenticate(&auth_request(
+            AuthProvider::apple(token),
             username,
-        );
-
-        self.send(request)
-            .await
-            .map(DefaultClient::<A>::map_session)
+            create,
+            vars,
+        ))
+        .await
     }
 
     /// Authenticate a user with a custom id.
@@ -286,20 +650,13 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         create: bool,
         vars: HashMap<&str, &str>,
     ) -> Result<Session, Self::Error> {
-        let request = api::authenticate_custom(
-            &self.server_key,
-            &self.server_password,
-            ApiAccountCustom {
-                id: id.to_owned(),
-                vars: string_map_to_owned_string_map(vars),
-            },
-            Some(create),
+        self.authenticate(&auth_request(
+            AuthProvider::custom(id),
             username,
-        );
-
-        self.send(request)
-            .await
-            .map(DefaultClient::<A>::map_session)
+            create,
+            vars,
+        ))
+        .await
     }
 
     /// Authenticate a user with a device id.
@@ -326,20 +683,13 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         create: bool,
         vars: HashMap<&str, &str>,
     ) -> Result<Session, Self::Error> {
-        let request = api::authenticate_device(
-            &self.server_key.clone(),
-            &self.server_password,
-            ApiAccountDevice {
-                id: id.to_owned(),
-                vars: string_map_to_owned_string_map(vars),
-            },
-            Some(create),
+        self.authenticate(&auth_request(
+            AuthProvider::device(id),
             username,
-        );
-
-        self.send(request)
-            .await
-            .map(DefaultClient::<A>::map_session)
+            create,
+            vars,
+        ))
+        .await
     }
 
     /// Authenticate a user with an email and password.
@@ -365,21 +715,13 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         create: bool,
         vars: HashMap<&str, &str>,
     ) -> Result<Session, Self::Error> {
-        let request = api::authenticate_email(
-            &self.server_key,
-            &self.server_password,
-            ApiAccountEmail {
-                email: email.to_owned(),
-                password: password.to_owned(),
-                vars: string_map_to_owned_string_map(vars),
-            },
-            Some(create),
+        self.authenticate(&auth_request(
+            AuthProvider::email(email, password),
             username,
-        );
-
-        self.send(request)
-            .await
-            .map(DefaultClient::<A>::map_session)
+            create,
+            vars,
+        ))
+        .await
     }
 
     /// Authenticate a user with a Facebook auth token
@@ -407,21 +749,42 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         vars: HashMap<&str, &str>,
         import: bool,
     ) -> Result<Session, Self::Error> {
-        let request = api::authenticate_facebook(
-            &self.server_key,
-            &self.server_password,
-            ApiAccountFacebook {
-                token: token.to_owned(),
-                vars: string_map_to_owned_string_map(vars),
-            },
-            Some(create),
-            username,
-            Some(import),
-        );
+        let mut request = auth_request(AuthProvider::facebook(token), username, create, vars);
+        request.import(import);
+        self.authenticate(&request).await
+    }
 
-        self.send(request)
-            .await
-            .map(DefaultClient::<A>::map_session)
+    /// Authenticate a user with the signed player info of Facebook Instant Games
+    ///
+    /// The signed player info is returned by `FBInstant.player.getSignedPlayerInfoAsync()` in the Instant Games SDK.
+    ///
+    /// See [Authentication](index.html#authentication) for a description of the `username`, `create` and `vars` parameters.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// # use std::collections::HashMap;
+    /// # run_in_example(async move |client, session| {
+    /// let session = client.authenticate_facebook_instant_game("signedplayerinfo", None, true, HashMap::new()).await
+    ///     .expect("Failed to authenticate user");
+    /// # Ok(())
+    /// # });
+    /// ```
+    async fn authenticate_facebook_instant_game(
+        &self,
+        signed_player_info: &str,
+        username: Option<&str>,
+        create: bool,
+        vars: HashMap<&str, &str>,
+    ) -> Result<Session, Self::Error> {
+        self.authenticate(&auth_request(
+            AuthProvider::facebook_instant_game(signed_player_info),
+            username,
+            create,
+            vars,
+        ))
+        .await
     }
 
     /// Authenticate a user with Apple Game Center
@@ -453,25 +816,20 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         create: bool,
         vars: HashMap<&str, &str>,
     ) -> Result<Session, Self::Error> {
-        let request = api::authenticate_game_center(
-            &self.server_key,
-            &self.server_password,
-            ApiAccountGameCenter {
-                bundle_id: bundle_id.to_owned(),
-                player_id: player_id.to_owned(),
-                public_key_url: public_key_url.to_owned(),
-                salt: salt.to_owned(),
-                signature: signature.to_owned(),
-                timestamp_seconds: timestamp.to_owned(),
-                vars: string_map_to_owned_string_map(vars),
-            },
-            Some(create),
+        self.authenticate(&auth_request(
+            AuthProvider::game_center(
+                bundle_id,
+                player_id,
+                public_key_url,
+                salt,
+                signature,
+                timestamp,
+            ),
             username,
-        );
-
-        self.send(request)
-            .await
-            .map(DefaultClient::<A>::map_session)
+            create,
+            vars,
+        ))
+        .await
     }
 
     /// Authenticate a user with a Google auth token
@@ -496,20 +854,13 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         create: bool,
         vars: HashMap<&str, &str>,
     ) -> Result<Session, Self::Error> {
-        let request = api::authenticate_google(
-            &self.server_key,
-            &self.server_password,
-            ApiAccountGoogle {
-                token: token.to_owned(),
-                vars: string_map_to_owned_string_map(vars),
-            },
-            Some(create),
+        self.authenticate(&auth_request(
+            AuthProvider::google(token),
             username,
-        );
-
-        self.send(request)
-            .await
-            .map(DefaultClient::<A>::map_session)
+            create,
+            vars,
+        ))
+        .await
     }
 
     /// Authenticate a user with a Steam auth token
@@ -534,20 +885,13 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         create: bool,
         vars: HashMap<&str, &str>,
     ) -> Result<Session, Self::Error> {
-        let request = api::authenticate_google(
-            &self.server_key,
-            &self.server_password,
-            ApiAccountGoogle {
-                token: token.to_owned(),
-                vars: string_map_to_owned_string_map(vars),
-            },
-            Some(create),
+        self.authenticate(&auth_request(
+            AuthProvider::steam(token),
             username,
-        );
-
-        self.send(request)
-            .await
-            .map(DefaultClient::<A>::map_session)
+            create,
+            vars,
+        ))
+        .await
     }
 
     /// Ban a set of users from a group.
@@ -574,8 +918,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         let user_ids = str_slice_to_owned(user_ids);
         let request = api::ban_group_users(&session.get_auth_token(), group_id, &user_ids);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Block friends by id or username.
@@ -600,8 +943,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         let usernames = str_slice_to_owned(usernames);
         let request = api::block_friends(&session.get_auth_token(), &ids, &usernames);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Create a group.
@@ -647,8 +989,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Remove friends or friend requests.
@@ -673,8 +1014,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         let usernames = str_slice_to_owned(usernames);
         let request = api::delete_friends(&session.get_auth_token(), &ids, &usernames);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Delete a group.
@@ -691,8 +1031,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// ```
     async fn delete_group(&self, session: &Session, group_id: &str) -> Result<(), Self::Error> {
         let request = api::delete_group(&session.get_auth_token(), group_id);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Delete a leaderboard record.
@@ -713,8 +1052,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         leaderboard_id: &str,
     ) -> Result<(), Self::Error> {
         let request = api::delete_leaderboard_record(&session.get_auth_token(), leaderboard_id);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Delete notifications.
@@ -736,8 +1074,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     ) -> Result<(), Self::Error> {
         let ids = str_slice_to_owned(ids);
         let request = api::delete_notifications(&session.get_auth_token(), &ids);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Delete storage objects.
@@ -777,8 +1114,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
                 object_ids: ids.to_vec(),
             },
         );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Demote users in a group.
@@ -803,8 +1139,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     ) -> Result<(), Self::Error> {
         let user_ids = str_slice_to_owned(user_ids);
         let request = api::demote_group_users(&session.get_auth_token(), group_id, &user_ids);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Submit an event for processing in the server's registered runtime custom events handler.
@@ -835,8 +1170,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
                 properties: string_map_to_owned_string_map(properties),
             },
         );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Fetch the users account
@@ -854,8 +1188,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// ```
     async fn get_account(&self, session: &Session) -> Result<ApiAccount, Self::Error> {
         let request = api::get_account(&session.get_auth_token());
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Fetch users by id, username, or facebook ids
@@ -884,7 +1217,26 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         let usernames = str_slice_to_owned(usernames);
         let facebook_ids = str_slice_to_owned(facebook_ids);
         let request = api::get_users(&session.get_auth_token(), &ids, &usernames, &facebook_ids);
-        self.refresh_session(session).await?;
+        self.send_with_session(session, request).await
+    }
+
+    /// Check whether the server is up.
+    ///
+    /// Doesn't require a session. See [`crate::reachability`] to monitor the server periodically.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// # run_in_example(async move |client, session| {
+    /// client.healthcheck().await
+    ///     .expect("Server is down");
+    /// # Ok(())
+    /// # })
+    /// ```
+    async fn healthcheck(&self) -> Result<(), Self::Error> {
+        let mut request = api::healthcheck("");
+        request.authentication = api::Authentication::None;
         self.send(request).await
     }
 
@@ -919,8 +1271,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
             reset,
         );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Import Steam friends and add them as friends.
@@ -954,8 +1305,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
             reset,
         );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Join a group.
@@ -974,8 +1324,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// ```
     async fn join_group(&self, session: &Session, group_id: &str) -> Result<(), Self::Error> {
         let request = api::join_group(&session.get_auth_token(), group_id);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Join a tournament.
@@ -996,8 +1345,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         tournament_id: &str,
     ) -> Result<(), Self::Error> {
         let request = api::join_tournament(&session.get_auth_token(), tournament_id);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Kick group users.
@@ -1020,8 +1368,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     ) -> Result<(), Self::Error> {
         let ids = str_slice_to_owned(ids);
         let request = api::kick_group_users(&session.get_auth_token(), group_id, &ids);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Leave a group.
@@ -1038,8 +1385,120 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// ```
     async fn leave_group(&self, session: &Session, group_id: &str) -> Result<(), Self::Error> {
         let request = api::leave_group(&session.get_auth_token(), group_id);
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
+    }
+
+    /// Link an identity to the social profiles on the current user's account.
+    ///
+    /// This is the common entry point for all `link_*` functions. Set `import` on the [`AuthRequest`] to import
+    /// Facebook friends and `sync` to import Steam friends. The `username`, `create` and `vars` of the request are
+    /// ignored, session variables can only be set when authenticating.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// use nakama_rs::auth_request::{AuthProvider, AuthRequest};
+    /// # run_in_example(async move |client, session| {
+    /// let request = AuthRequest::new(AuthProvider::email("email@domain.com", "password"));
+    /// client.link(&session, &request).await
+    ///     .expect("Failed to link account");
+    /// # Ok(())
+    /// # });
+    /// ```
+    async fn link(&self, session: &Session, request: &AuthRequest) -> Result<(), Self::Error> {
+        let auth_token = session.get_auth_token();
+        let vars = HashMap::new();
+
+        let request = match request.provider {
+            AuthProvider::Device { ref id } => api::link_device(
+                &auth_token,
+                ApiAccountDevice {
+                    id: id.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Email {
+                ref email,
+                ref password,
+            } => api::link_email(
+                &auth_token,
+                ApiAccountEmail {
+                    email: email.clone(),
+                    password: password.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Custom { ref id } => api::link_custom(
+                &auth_token,
+                ApiAccountCustom {
+                    id: id.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Apple { ref token } => api::link_apple(
+                &auth_token,
+                ApiAccountApple {
+                    token: token.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Google { ref token } => api::link_google(
+                &auth_token,
+                ApiAccountGoogle {
+                    token: token.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Facebook { ref token } => api::link_facebook(
+                &auth_token,
+                ApiAccountFacebook {
+                    token: token.clone(),
+                    vars,
+                },
+                request.import,
+            ),
+            AuthProvider::FacebookInstantGame {
+                ref signed_player_info,
+            } => api::link_facebook_instant_game(
+                &auth_token,
+                ApiAccountFacebookInstantGame {
+                    signed_player_info: signed_player_info.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::GameCenter {
+                ref bundle_id,
+                ref player_id,
+                ref public_key_url,
+                ref salt,
+                ref signature,
+                ref timestamp,
+            } => api::link_game_center(
+                &auth_token,
+                ApiAccountGameCenter {
+                    bundle_id: bundle_id.clone(),
+                    player_id: player_id.clone(),
+                    public_key_url: public_key_url.clone(),
+                    salt: salt.clone(),
+                    signature: signature.clone(),
+                    timestamp_seconds: timestamp.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Steam { ref token } => api::link_steam(
+                &auth_token,
+                ApiLinkSteamRequest {
+                    account: ApiAccountSteam {
+                        token: token.clone(),
+                        vars,
+                    },
+                    sync: request.sync.unwrap_or_default(),
+                },
+            ),
+        };
+
+        self.send_with_session(session, request).await
     }
 
     /// Link an Apple ID to the social profiles on the current user's account.
@@ -1056,15 +1515,8 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # });
     /// ```
     async fn link_apple(&self, session: &Session, token: &str) -> Result<(), Self::Error> {
-        let request = api::link_apple(
-            &session.get_auth_token(),
-            ApiAccountApple {
-                vars: HashMap::new(),
-                token: token.to_owned(),
-            },
-        );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.link(session, &AuthRequest::new(AuthProvider::apple(token)))
+            .await
     }
 
     /// Link an custom ID to the social profiles on the current user's account.
@@ -1081,15 +1533,8 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # });
     /// ```
     async fn link_custom(&self, session: &Session, id: &str) -> Result<(), Self::Error> {
-        let request = api::link_custom(
-            &session.get_auth_token(),
-            ApiAccountCustom {
-                vars: HashMap::new(),
-                id: id.to_owned(),
-            },
-        );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.link(session, &AuthRequest::new(AuthProvider::custom(id)))
+            .await
     }
 
     /// Link an device ID to the social profiles on the current user's account.
@@ -1106,15 +1551,8 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # });
     /// ```
     async fn link_device(&self, session: &Session, id: &str) -> Result<(), Self::Error> {
-        let request = api::link_device(
-            &session.get_auth_token(),
-            ApiAccountDevice {
-                vars: HashMap::new(),
-                id: id.to_owned(),
-            },
-        );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.link(session, &AuthRequest::new(AuthProvider::device(id)))
+            .await
     }
 
     /// Link an email and password to the social profiles on the current user's account.
@@ -1136,16 +1574,11 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         email: &str,
         password: &str,
     ) -> Result<(), Self::Error> {
-        let request = api::link_email(
-            &session.get_auth_token(),
-            ApiAccountEmail {
-                vars: HashMap::new(),
-                email: email.to_owned(),
-                password: password.to_owned(),
-            },
-        );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.link(
+            session,
+            &AuthRequest::new(AuthProvider::email(email, password)),
+        )
+        .await
     }
 
     /// Link a Facebook profile to the social profiles on the current user's account.
@@ -1169,16 +1602,30 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         token: &str,
         import: Option<bool>,
     ) -> Result<(), Self::Error> {
-        let request = api::link_facebook(
-            &session.get_auth_token(),
-            ApiAccountFacebook {
-                vars: HashMap::new(),
-                token: token.to_owned(),
-            },
-            import,
-        );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        let mut request = AuthRequest::new(AuthProvider::facebook(token));
+        request.import = import;
+        self.link(session, &request).await
+    }
+
+    /// Link a Facebook Instant Games profile to the social profiles on the current user's account.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// # run_in_example(async move |client, session| {
+    /// client.link_facebook_instant_game(&session, "signedplayerinfo").await
+    ///     .expect("Failed to link account");
+    /// # Ok(())
+    /// # });
+    /// ```
+    async fn link_facebook_instant_game(
+        &self,
+        session: &Session,
+        signed_player_info: &str,
+    ) -> Result<(), Self::Error> {
+        let provider = AuthProvider::facebook_instant_game(signed_player_info);
+        self.link(session, &AuthRequest::new(provider)).await
     }
 
     /// Link a Game Center profile to the social profiles on the current user's account.
@@ -1206,20 +1653,15 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         signature: &str,
         timestamp: &str,
     ) -> Result<(), Self::Error> {
-        let request = api::link_game_center(
-            &session.get_auth_token(),
-            ApiAccountGameCenter {
-                vars: HashMap::new(),
-                bundle_id: bundle_id.to_owned(),
-                player_id: player_id.to_owned(),
-                public_key_url: public_key_url.to_owned(),
-                salt: salt.to_owned(),
-                signature: signature.to_owned(),
-                timestamp_seconds: timestamp.to_owned(),
-            },
+        let provider = AuthProvider::game_center(
+            bundle_id,
+            player_id,
+            public_key_url,
+            salt,
+            signature,
+            timestamp,
         );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.link(session, &AuthRequest::new(provider)).await
     }
 
     /// Link a Google profile to the social profiles on the current user's account.
@@ -1236,15 +1678,8 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # });
     /// ```
     async fn link_google(&self, session: &Session, token: &str) -> Result<(), Self::Error> {
-        let request = api::link_google(
-            &session.get_auth_token(),
-            ApiAccountGoogle {
-                vars: HashMap::new(),
-                token: token.to_owned(),
-            },
-        );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.link(session, &AuthRequest::new(AuthProvider::google(token)))
+            .await
     }
 
     /// Link a Steam profile to the social profiles on the current user's account.
@@ -1268,18 +1703,9 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         token: &str,
         import: bool,
     ) -> Result<(), Self::Error> {
-        let request = api::link_steam(
-            &session.get_auth_token(),
-            ApiLinkSteamRequest {
-                account: ApiAccountSteam {
-                    vars: HashMap::new(),
-                    token: token.to_owned(),
-                },
-                sync: import,
-            },
-        );
-        self.refresh_session(session).await?;
-        self.send(request).await
+        let mut request = AuthRequest::new(AuthProvider::steam(token));
+        request.sync(import);
+        self.link(session, &request).await
     }
 
     /// List messages from a chat channel.
@@ -1320,8 +1746,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             cursor,
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List friends
@@ -1350,8 +1775,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     ) -> Result<ApiFriendList, Self::Error> {
         let request = api::list_friends(&session.get_auth_token(), limit, state, cursor);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List all users part of the group.
@@ -1383,8 +1807,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         let request =
             api::list_group_users(&session.get_auth_token(), group_id, limit, state, cursor);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List groups on the server.
@@ -1416,8 +1839,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     ) -> Result<ApiGroupList, Self::Error> {
         let request = api::list_groups(&session.get_auth_token(), name, cursor, limit);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List records from a leaderboard
@@ -1458,8 +1880,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             expiry,
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List leaderboard records around owner
@@ -1498,8 +1919,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             expiry,
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Fetch matches active on the server
@@ -1540,8 +1960,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             Some(query),
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List notifications for the user.
@@ -1585,8 +2004,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     ) -> Result<ApiNotificationList, Self::Error> {
         let request = api::list_notifications(&session.get_auth_token(), limit, cacheable_cursor);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List storage objects in a collection which have public read access.
@@ -1617,8 +2035,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         let request =
             api::list_storage_objects(&session.get_auth_token(), collection, None, limit, cursor);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List tournament records around owner
@@ -1660,8 +2077,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             expiry,
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List tournament records
@@ -1706,8 +2122,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             expiry,
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List current or upcoming tournaments
@@ -1755,8 +2170,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             cursor,
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List groups an user is a member of.
@@ -1790,8 +2204,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         let request =
             api::list_user_groups(&session.get_auth_token(), user_id, limit, state, cursor);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// List storage objects in a collection which belong to a specific user and have public read access.
@@ -1828,8 +2241,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             cursor,
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Promote group users.
@@ -1853,8 +2265,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         let ids = str_slice_to_owned(ids);
         let request = api::promote_group_users(&session.get_auth_token(), group_id, &ids);
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Read objects from the storage engine.
@@ -1890,8 +2301,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             ApiReadStorageObjectsRequest { object_ids: ids },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Execute a function on the server
@@ -1915,11 +2325,149 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     ) -> Result<ApiRpc, Self::Error> {
         let request = api::rpc_func2(&session.get_auth_token(), id, payload, None);
 
-        self.refresh_session(session).await?;
+        self.send_with_session(session, request).await
+    }
+
+    /// Execute a function on the server with a binary payload.
+    ///
+    /// The payload is encoded with the payload codec, base64 by default. See [`DefaultClient::set_payload_codec`].
+    /// Use [`DefaultClient::decode_rpc_payload`] to decode a binary response.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// use nanoserde::SerBin;
+    /// #[derive(SerBin)]
+    /// struct Message {
+    ///     content: String,
+    /// }
+    /// # run_in_example(async move |client, session| {
+    /// let data = Message { content: "Hello World!".to_owned() };
+    /// let result = client.rpc_bytes(&session, "rpc_func_name", &data.serialize_bin()).await
+    ///     .expect("Failed to execute rpc function");
+    /// println!("Returned payload: {:?}", client.decode_rpc_payload(&result));
+    /// # Ok(())
+    /// # })
+    /// ```
+    async fn rpc_bytes(
+        &self,
+        session: &Session,
+        id: &str,
+        payload: &[u8],
+    ) -> Result<ApiRpc, Self::Error> {
+        // The server expects the payload as a JSON string
+        let body = self.payload_codec.encode(payload).serialize_json();
+        let request = api::rpc_func(&session.get_auth_token(), id, &body, None);
+
+        self.send_with_session(session, request).await
+    }
+
+    /// Execute a function on the server with a JSON request and response.
+    ///
+    /// Fails with [`DefaultClientError::RpcDecodeError`] if the response can't be deserialized.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// use nanoserde::{DeJson, SerJson};
+    ///
+    /// #[derive(SerJson, DeJson)]
+    /// struct Echo {
+    ///     message: String,
+    /// }
+    ///
+    /// # run_in_example(async move |client, session| {
+    /// let request = Echo { message: "Hello World!".to_owned() };
+    /// let response: Echo = client.rpc_typed(&session, "echo_payload", &request).await?;
+    /// # Ok(())
+    /// # })
+    /// ```
+    async fn rpc_typed<Req: SerJson + Sync, Resp: DeJson + Send>(
+        &self,
+        session: &Session,
+        id: &str,
+        request: &Req,
+    ) -> Result<Resp, Self::Error> {
+        // The server expects the payload as a JSON string
+        let body = request.serialize_json().serialize_json();
+        let request = api::rpc_func(&session.get_auth_token(), id, &body, None);
+        let response = self.send_with_session(session, request).await?;
+
+        decode_response(id, &response.payload).map_err(DefaultClientError::RpcDecodeError)
+    }
+
+    /// Execute a function on the server without a session, authorized by the HTTP key.
+    ///
+    /// The payload is sent as the request body. See [`DefaultClient::set_http_key`].
+    ///
+    /// # Example
+    /// ```
+    /// # use futures::executor::block_on;
+    /// use nakama_rs::config::DEFAULT_HTTP_KEY;
+    /// use nakama_rs::{Client, DefaultClient};
+    ///
+    /// let mut client = DefaultClient::new_with_adapter_and_defaults();
+    /// client.set_http_key(DEFAULT_HTTP_KEY);
+    /// # block_on(async {
+    /// let result = client.rpc_with_http_key("rpc_func_name", Some("Hello World!")).await
+    ///     .expect("Failed to execute rpc function");
+    /// println!("Returned: {}", result.payload);
+    /// # })
+    /// ```
+    async fn rpc_with_http_key(
+        &self,
+        id: &str,
+        payload: Option<&str>,
+    ) -> Result<ApiRpc, Self::Error> {
+        // The server expects the payload as a JSON string
+        let body = payload
+            .map(|payload| payload.to_owned().serialize_json())
+            .unwrap_or_default();
+        let mut request = api::rpc_func("", id, &body, Some(self.http_key()?));
+        // The server checks the Authorization header before the http key
+        request.authentication = api::Authentication::None;
+
+        self.send(request).await
+    }
+
+    /// Execute a function on the server without a session, authorized by the HTTP key.
+    ///
+    /// The payload is sent as the `payload` query parameter. See [`DefaultClient::set_http_key`].
+    ///
+    /// # Example
+    /// ```
+    /// # use futures::executor::block_on;
+    /// use nakama_rs::config::DEFAULT_HTTP_KEY;
+    /// use nakama_rs::{Client, DefaultClient};
+    ///
+    /// let mut client = DefaultClient::new_with_adapter_and_defaults();
+    /// client.set_http_key(DEFAULT_HTTP_KEY);
+    /// # block_on(async {
+    /// let result = client.rpc_with_http_key_query("rpc_func_name", Some("Hello World!")).await
+    ///     .expect("Failed to execute rpc function");
+    /// println!("Returned: {}", result.payload);
+    /// # })
+    /// ```
+    async fn rpc_with_http_key_query(
+        &self,
+        id: &str,
+        payload: Option<&str>,
+    ) -> Result<ApiRpc, Self::Error> {
+        let mut request = api::rpc_func2("", id, payload, Some(self.http_key()?));
+        request.authentication = api::Authentication::None;
+
         self.send(request).await
     }
 
-    /// Log out a session which optionally invalidates the authorization and/or refresh token.
+    /// Log out a session, invalidating both the authorization and the refresh token.
+    ///
+    /// An expired authorization token is refreshed first, so that the refresh token is invalidated as well.
+    /// The session is ended once the server invalidated the tokens or rejected them as invalid already.
+    /// Further requests with it fail with [`DefaultClientError::SessionEnded`] and sockets connected with it
+    /// close on their next tick. The session stays usable if the request failed otherwise, e.g. because the
+    /// server couldn't be reached. Logging out an ended session does nothing.
     ///
     /// # Example
     /// ```
@@ -1932,6 +2480,30 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # })
     /// ```    
     async fn session_logout(&self, session: &Session) -> Result<(), Self::Error> {
+        if session.has_ended() {
+            return Ok(());
+        }
+
+        if session.is_expired()
+            && session.get_refresh_token().is_some()
+            && !session.is_refresh_expired()
+        {
+            let vars = session.vars();
+            let vars = vars
+                .iter()
+                .map(|(key, val)| (key.as_str(), val.as_str()))
+                .collect();
+            match self.session_refresh(session, vars).await {
+                Ok(()) => {}
+                // The refresh token is invalid already
+                Err(ref err) if err.is_unauthenticated() => {
+                    session.end();
+                    return Ok(());
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
         let request = api::session_logout(
             &session.get_auth_token(),
             ApiSessionLogoutRequest {
@@ -1940,7 +2512,18 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.send(request).await
+        match self.send(request).await {
+            Ok(()) => {
+                session.end();
+                Ok(())
+            }
+            // The tokens are invalid already
+            Err(ref err) if err.is_unauthenticated() => {
+                session.end();
+                Ok(())
+            }
+            Err(err) => Err(err),
+        }
     }
 
     /// Refresh the session.
@@ -1964,6 +2547,10 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         session: &Session,
         vars: HashMap<&str, &str>,
     ) -> Result<(), Self::Error> {
+        if session.has_ended() {
+            return Err(DefaultClientError::SessionEnded);
+        }
+
         let refresh_token = session.get_refresh_token().expect("Session refresh can only be called when a refresh token is available");
         let request = api::session_refresh(
             &self.server_key,
@@ -1978,10 +2565,118 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             .await?;
 
         session.replace(&data.token, &data.refresh_token);
+        self.update_clock_offset(session);
 
         Ok(())
     }
 
+    /// Unlink an identity from the users account.
+    ///
+    /// This is the common entry point for all `unlink_*` functions. Only the provider of the request is used.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// use nakama_rs::auth_request::{AuthProvider, AuthRequest};
+    /// # run_in_example(async move |client, session| {
+    /// let request = AuthRequest::new(AuthProvider::email("email@domain.com", "password"));
+    /// client.unlink(&session, &request).await
+    ///     .expect("Failed to unlink account");
+    /// # Ok(())
+    /// # });
+    /// ```
+    async fn unlink(&self, session: &Session, request: &AuthRequest) -> Result<(), Self::Error> {
+        let auth_token = session.get_auth_token();
+        let vars = HashMap::new();
+
+        let request = match request.provider {
+            AuthProvider::Device { ref id } => api::unlink_device(
+                &auth_token,
+                ApiAccountDevice {
+                    id: id.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Email {
+                ref email,
+                ref password,
+            } => api::unlink_email(
+                &auth_token,
+                ApiAccountEmail {
+                    email: email.clone(),
+                    password: password.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Custom { ref id } => api::unlink_custom(
+                &auth_token,
+                ApiAccountCustom {
+                    id: id.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Apple { ref token } => api::unlink_apple(
+                &auth_token,
+                ApiAccountApple {
+                    token: token.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Google { ref token } => api::unlink_google(
+                &auth_token,
+                ApiAccountGoogle {
+                    token: token.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Facebook { ref token } => api::unlink_facebook(
+                &auth_token,
+                ApiAccountFacebook {
+                    token: token.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::FacebookInstantGame {
+                ref signed_player_info,
+            } => api::unlink_facebook_instant_game(
+                &auth_token,
+                ApiAccountFacebookInstantGame {
+                    signed_player_info: signed_player_info.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::GameCenter {
+                ref bundle_id,
+                ref player_id,
+                ref public_key_url,
+                ref salt,
+                ref signature,
+                ref timestamp,
+            } => api::unlink_game_center(
+                &auth_token,
+                ApiAccountGameCenter {
+                    bundle_id: bundle_id.clone(),
+                    player_id: player_id.clone(),
+                    public_key_url: public_key_url.clone(),
+                    salt: salt.clone(),
+                    signature: signature.clone(),
+                    timestamp_seconds: timestamp.clone(),
+                    vars,
+                },
+            ),
+            AuthProvider::Steam { ref token } => api::unlink_steam(
+                &auth_token,
+                ApiAccountSteam {
+                    token: token.clone(),
+                    vars,
+                },
+            ),
+        };
+
+        self.send_with_session(session, request).await
+    }
+
     /// Unlink an Apple ID from the users account.
     ///
     /// # Example
@@ -1995,16 +2690,8 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # })
     /// ```   
     async fn unlink_apple(&self, session: &Session, token: &str) -> Result<(), Self::Error> {
-        let request = api::unlink_apple(
-            &session.get_auth_token(),
-            ApiAccountApple {
-                vars: HashMap::new(),
-                token: token.to_owned(),
-            },
-        );
-
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.unlink(session, &AuthRequest::new(AuthProvider::apple(token)))
+            .await
     }
 
     /// Unlink a custom ID from the users account.
@@ -2020,16 +2707,8 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # })
     /// ```   
     async fn unlink_custom(&self, session: &Session, id: &str) -> Result<(), Self::Error> {
-        let request = api::unlink_custom(
-            &session.get_auth_token(),
-            ApiAccountCustom {
-                vars: HashMap::new(),
-                id: id.to_owned(),
-            },
-        );
-
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.unlink(session, &AuthRequest::new(AuthProvider::custom(id)))
+            .await
     }
 
     /// Unlink a device ID from the users account.
@@ -2045,16 +2724,8 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # })
     /// ```   
     async fn unlink_device(&self, session: &Session, id: &str) -> Result<(), Self::Error> {
-        let request = api::unlink_device(
-            &session.get_auth_token(),
-            ApiAccountDevice {
-                vars: HashMap::new(),
-                id: id.to_owned(),
-            },
-        );
-
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.unlink(session, &AuthRequest::new(AuthProvider::device(id)))
+            .await
     }
 
     /// Unlink an email with password from the users account.
@@ -2075,17 +2746,11 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         email: &str,
         password: &str,
     ) -> Result<(), Self::Error> {
-        let request = api::unlink_email(
-            &session.get_auth_token(),
-            ApiAccountEmail {
-                vars: HashMap::new(),
-                email: email.to_owned(),
-                password: password.to_owned(),
-            },
-        );
-
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.unlink(
+            session,
+            &AuthRequest::new(AuthProvider::email(email, password)),
+        )
+        .await
     }
 
     /// Unlink a Facebook profile from the users account.
@@ -2101,16 +2766,29 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # })
     /// ```   
     async fn unlink_facebook(&self, session: &Session, token: &str) -> Result<(), Self::Error> {
-        let request = api::unlink_facebook(
-            &session.get_auth_token(),
-            ApiAccountFacebook {
-                vars: HashMap::new(),
-                token: token.to_owned(),
-            },
-        );
+        self.unlink(session, &AuthRequest::new(AuthProvider::facebook(token)))
+            .await
+    }
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+    /// Unlink a Facebook Instant Games profile from the users account.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// # run_in_example(async move |client, session| {
+    /// client.unlink_facebook_instant_game(&session, "signedplayerinfo").await
+    ///     .expect("Failed to unlink account");
+    /// # Ok(())
+    /// # })
+    /// ```
+    async fn unlink_facebook_instant_game(
+        &self,
+        session: &Session,
+        signed_player_info: &str,
+    ) -> Result<(), Self::Error> {
+        let provider = AuthProvider::facebook_instant_game(signed_player_info);
+        self.unlink(session, &AuthRequest::new(provider)).await
     }
 
     /// Unlink a Game Center profile from the users account.
@@ -2135,21 +2813,15 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
         signature: &str,
         timestamp: &str,
     ) -> Result<(), Self::Error> {
-        let request = api::unlink_game_center(
-            &session.get_auth_token(),
-            ApiAccountGameCenter {
-                vars: HashMap::new(),
-                bundle_id: bundle_id.to_owned(),
-                player_id: player_id.to_owned(),
-                public_key_url: public_key_url.to_owned(),
-                salt: salt.to_owned(),
-                signature: signature.to_owned(),
-                timestamp_seconds: timestamp.to_owned(),
-            },
+        let provider = AuthProvider::game_center(
+            bundle_id,
+            player_id,
+            public_key_url,
+            salt,
+            signature,
+            timestamp,
         );
-
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.unlink(session, &AuthRequest::new(provider)).await
     }
 
     /// Unlink a Google profile from the users account.
@@ -2165,16 +2837,8 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # })
     /// ```   
     async fn unlink_google(&self, session: &Session, token: &str) -> Result<(), Self::Error> {
-        let request = api::unlink_google(
-            &session.get_auth_token(),
-            ApiAccountGoogle {
-                vars: HashMap::new(),
-                token: token.to_owned(),
-            },
-        );
-
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.unlink(session, &AuthRequest::new(AuthProvider::google(token)))
+            .await
     }
 
     /// Unlink a Steam profile from the users account.
@@ -2190,16 +2854,8 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
     /// # })
     /// ```   
     async fn unlink_steam(&self, session: &Session, token: &str) -> Result<(), Self::Error> {
-        let request = api::unlink_steam(
-            &session.get_auth_token(),
-            ApiAccountSteam {
-                vars: HashMap::new(),
-                token: token.to_owned(),
-            },
-        );
-
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.unlink(session, &AuthRequest::new(AuthProvider::steam(token)))
+            .await
     }
 
     /// Update the user's account.
@@ -2237,8 +2893,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Update a group.
@@ -2277,8 +2932,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Validate a purchase receipt against the Apple App Store.
@@ -2305,8 +2959,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Validate a purchase receipt against the Google Play Store.
@@ -2333,8 +2986,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Validate a purchase receipt against the Huawei AppGallery.
@@ -2363,8 +3015,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Write a leaderboard record.
@@ -2400,8 +3051,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Write objects to the storage engine.
@@ -2443,8 +3093,7 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 
     /// Write a tournament record
@@ -2495,7 +3144,6 @@ impl<A: ClientAdapter + Sync + Send> Client for DefaultClient<A> {
             },
         );
 
-        self.refresh_session(session).await?;
-        self.send(request).await
+        self.send_with_session(session, request).await
     }
 }
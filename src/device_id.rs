@@ -0,0 +1,220 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stable device ids for guest accounts.
+//!
+//! [`DeviceIdProvider`] generates a random UUID the first time a device id is requested and keeps it in a
+//! [`DeviceIdStore`]. Every later launch reads the same id, so authenticating by device id always signs in
+//! to the same guest account.
+//!
+//! By default the id is stored in the file `nakama/device_id` in the per-user data directory, see
+//! [`default_device_id_path`]. Use [`FileDeviceIdStore::new`] to choose another path, or implement
+//! [`DeviceIdStore`] for platforms without a file system.
+//!
+//! # Example
+//! ```
+//! # #![feature(async_closure)]
+//! # use nakama_rs::test_helpers::*;
+//! # use nakama_rs::device_id::*;
+//! # use std::collections::HashMap;
+//! # run_in_example(async move |mut client, session| {
+//! let path = std::env::temp_dir().join("nakama_example_device_id");
+//! client.set_device_id_provider(DeviceIdProvider::new(FileDeviceIdStore::new(path)));
+//! let session = client.authenticate_this_device(true, HashMap::new()).await?;
+//! # Ok(())
+//! # });
+//! ```
+use crate::atomic_write::write_atomically;
+use log::trace;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// The path of the device id file in the per-user data directory.
+///
+/// This is `$XDG_DATA_HOME` or `~/.local/share` on Linux, `~/Library/Application Support` on macOS and
+/// `%APPDATA%` on Windows. Returns `None` if the directory can't be determined from the environment.
+pub fn default_device_id_path() -> Option<PathBuf> {
+    let data_dir = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    data_dir.map(|dir| dir.join("nakama").join("device_id"))
+}
+
+/// Loads and saves the device id.
+pub trait DeviceIdStore: Send + Sync {
+    /// Returns `None` if no device id was saved yet.
+    fn load(&self) -> io::Result<Option<String>>;
+
+    fn save(&self, device_id: &str) -> io::Result<()>;
+}
+
+/// Stores the device id in a file.
+pub struct FileDeviceIdStore {
+    path: Option<PathBuf>,
+}
+
+impl FileDeviceIdStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileDeviceIdStore {
+        FileDeviceIdStore {
+            path: Some(path.into()),
+        }
+    }
+
+    fn path(&self) -> io::Result<&PathBuf> {
+        self.path.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No per-user data directory found, set the path with FileDeviceIdStore::new",
+            )
+        })
+    }
+}
+
+/// Stores the device id at [`default_device_id_path`].
+impl Default for FileDeviceIdStore {
+    fn default() -> Self {
+        FileDeviceIdStore {
+            path: default_device_id_path(),
+        }
+    }
+}
+
+impl DeviceIdStore for FileDeviceIdStore {
+    fn load(&self) -> io::Result<Option<String>> {
+        match fs::read_to_string(self.path()?) {
+            Ok(device_id) if device_id.trim().is_empty() => Ok(None),
+            Ok(device_id) => Ok(Some(device_id.trim().to_owned())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn save(&self, device_id: &str) -> io::Result<()> {
+        write_atomically(self.path()?, device_id)
+    }
+}
+
+/// Keeps the device id in memory. The id changes on every launch.
+#[derive(Default)]
+pub struct MemoryDeviceIdStore {
+    device_id: Mutex<Option<String>>,
+}
+
+impl DeviceIdStore for MemoryDeviceIdStore {
+    fn load(&self) -> io::Result<Option<String>> {
+        Ok(self.device_id.lock().unwrap().clone())
+    }
+
+    fn save(&self, device_id: &str) -> io::Result<()> {
+        *self.device_id.lock().unwrap() = Some(device_id.to_owned());
+        Ok(())
+    }
+}
+
+/// Generates a random device id once and returns the stored id afterwards.
+pub struct DeviceIdProvider {
+    store: Box<dyn DeviceIdStore>,
+    device_id: Mutex<Option<String>>,
+}
+
+impl DeviceIdProvider {
+    pub fn new<S: DeviceIdStore + 'static>(store: S) -> DeviceIdProvider {
+        DeviceIdProvider {
+            store: Box::new(store),
+            device_id: Mutex::new(None),
+        }
+    }
+
+    /// Returns the stored device id, generating and saving a new one on first use.
+    ///
+    /// The id is only cached after it was saved, so a failed save doesn't hand out an id that would
+    /// change on the next launch.
+    pub fn device_id(&self) -> io::Result<String> {
+        let mut device_id = self.device_id.lock().unwrap();
+        if let Some(ref device_id) = *device_id {
+            return Ok(device_id.clone());
+        }
+
+        let id = match self.store.load()? {
+            Some(id) => id,
+            None => {
+                let id = uuid::Uuid::new_v4().to_string();
+                trace!("device_id: Generated new device id {}", id);
+                self.store.save(&id)?;
+                id
+            }
+        };
+
+        *device_id = Some(id.clone());
+        Ok(id)
+    }
+}
+
+impl Default for DeviceIdProvider {
+    fn default() -> Self {
+        DeviceIdProvider::new(FileDeviceIdStore::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::device_id::{
+        DeviceIdProvider, DeviceIdStore, FileDeviceIdStore, MemoryDeviceIdStore,
+    };
+
+    #[test]
+    fn test_device_id_is_stable() {
+        let provider = DeviceIdProvider::new(MemoryDeviceIdStore::default());
+        let device_id = provider.device_id().unwrap();
+        assert_eq!(device_id.len(), 36);
+        assert_eq!(provider.device_id().unwrap(), device_id);
+    }
+
+    #[test]
+    fn test_file_device_id_store() {
+        let path = std::env::temp_dir().join(format!("nakama_device_id_{}", std::process::id()));
+        let device_id = DeviceIdProvider::new(FileDeviceIdStore::new(&path))
+            .device_id()
+            .unwrap();
+
+        assert_eq!(
+            FileDeviceIdStore::new(&path).load().unwrap(),
+            Some(device_id.clone())
+        );
+        assert_eq!(
+            DeviceIdProvider::new(FileDeviceIdStore::new(&path))
+                .device_id()
+                .unwrap(),
+            device_id
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_device_id_store_without_path() {
+        let store = FileDeviceIdStore { path: None };
+        assert!(store.load().is_err());
+        assert!(store.save("device_id").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Control on which thread socket callbacks run.
+//!
+//! The socket resolves request futures as soon as it processes a response, the tasks awaiting them continue
+//! wherever they are polled. The event callbacks are handed to a [`Dispatcher`], set with
+//! [`WebSocket::set_dispatcher`](crate::WebSocket::set_dispatcher):
+//!
+//! - [`ImmediateDispatcher`] processes the received messages on the network thread of the adapter and runs the
+//! callbacks right away. Connection events and adapters without a network thread are processed in
+//! [`WebSocket::tick`](crate::WebSocket::tick).
+//! - [`TickDispatcher`] queues the callbacks and runs them at the end of [`WebSocket::tick`](crate::WebSocket::tick),
+//! on the thread that ticks the socket. This is the default.
+//! - [`ExecutorDispatcher`] posts the callbacks to an executor, e.g. the main thread queue of an engine.
+//!
+//! # Example
+//! ```
+//! # #![feature(async_closure)]
+//! # use nakama_rs::test_helpers::*;
+//! use nakama_rs::dispatcher::ExecutorDispatcher;
+//! use std::sync::mpsc;
+//! # run_in_socket_example(async move |client, session, mut socket| {
+//! let (tx, rx) = mpsc::channel();
+//! let tx = std::sync::Mutex::new(tx);
+//! socket.set_dispatcher(ExecutorDispatcher::new(move |task| {
+//!     tx.lock().unwrap().send(task).expect("Failed to post task");
+//! }));
+//! socket.on_received_channel_message(|message| println!("{}", message.content));
+//!
+//! // On the main thread, e.g. once per frame
+//! while let Ok(task) = rx.try_recv() {
+//!     task();
+//! }
+//! # Ok(())
+//! # });
+//! ```
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// The callbacks of one socket event.
+pub type Task = Box<dyn FnOnce() + Send + 'static>;
+
+pub trait Dispatcher: Send + Sync {
+    /// Run or schedule the callbacks of an event. Tasks must run in the order they are dispatched.
+    fn dispatch(&self, task: Task);
+
+    /// Called at the end of every [`WebSocket::tick`](crate::WebSocket::tick).
+    fn tick(&self) {}
+
+    /// Whether the socket processes the received messages on the network thread of the adapter, see
+    /// [`SocketAdapter::set_network_receiver`](crate::socket_adapter::SocketAdapter::set_network_receiver).
+    fn on_network_thread(&self) -> bool {
+        false
+    }
+}
+
+/// Runs the callbacks as soon as the event is processed, on the network thread if the adapter has one.
+///
+/// The callbacks of one event may run while another thread ticks the socket.
+#[derive(Debug, Default)]
+pub struct ImmediateDispatcher;
+
+impl Dispatcher for ImmediateDispatcher {
+    fn dispatch(&self, task: Task) {
+        task();
+    }
+
+    fn on_network_thread(&self) -> bool {
+        true
+    }
+}
+
+/// Runs the callbacks at the end of [`WebSocket::tick`](crate::WebSocket::tick).
+#[derive(Default)]
+pub struct TickDispatcher {
+    tasks: Mutex<VecDeque<Task>>,
+}
+
+impl Dispatcher for TickDispatcher {
+    fn dispatch(&self, task: Task) {
+        self.tasks.lock().unwrap().push_back(task);
+    }
+
+    fn tick(&self) {
+        // Release the lock before running a task, the callbacks may use the socket
+        loop {
+            let task = self.tasks.lock().unwrap().pop_front();
+            match task {
+                Some(task) => task(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Posts the callbacks to an executor.
+pub struct ExecutorDispatcher<F> {
+    executor: F,
+}
+
+impl<F> ExecutorDispatcher<F>
+where
+    F: Fn(Task) + Send + Sync,
+{
+    pub fn new(executor: F) -> Self {
+        ExecutorDispatcher { executor }
+    }
+}
+
+impl<F> Dispatcher for ExecutorDispatcher<F>
+where
+    F: Fn(Task) + Send + Sync,
+{
+    fn dispatch(&self, task: Task) {
+        (self.executor)(task);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn record(received: &Arc<Mutex<Vec<i32>>>, value: i32) -> Task {
+        let received = received.clone();
+        Box::new(move || received.lock().unwrap().push(value))
+    }
+
+    #[test]
+    fn test_immediate_dispatcher_runs_tasks_right_away() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let dispatcher = ImmediateDispatcher;
+        dispatcher.dispatch(record(&received, 1));
+        assert_eq!(*received.lock().unwrap(), vec![1]);
+        assert!(dispatcher.on_network_thread());
+    }
+
+    #[test]
+    fn test_tick_dispatcher_runs_tasks_in_order_on_tick() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let dispatcher = TickDispatcher::default();
+        dispatcher.dispatch(record(&received, 1));
+        dispatcher.dispatch(record(&received, 2));
+        assert!(received.lock().unwrap().is_empty());
+
+        dispatcher.tick();
+        assert_eq!(*received.lock().unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_executor_dispatcher_posts_tasks() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let dispatcher =
+            ExecutorDispatcher::new(move |task| tx.lock().unwrap().send(task).unwrap());
+        dispatcher.dispatch(record(&received, 1));
+        dispatcher.tick();
+        assert!(received.lock().unwrap().is_empty());
+
+        rx.recv().unwrap()();
+        assert_eq!(*received.lock().unwrap(), vec![1]);
+    }
+
+    fn record_thread(threads: &Arc<Mutex<Vec<thread::ThreadId>>>) -> Task {
+        let threads = threads.clone();
+        Box::new(move || threads.lock().unwrap().push(thread::current().id()))
+    }
+
+    #[test]
+    fn test_tick_dispatcher_runs_tasks_on_ticking_thread() {
+        let threads = Arc::new(Mutex::new(vec![]));
+        let dispatcher = Arc::new(TickDispatcher::default());
+        dispatcher.dispatch(record_thread(&threads));
+
+        let ticking_thread = thread::spawn({
+            let dispatcher = dispatcher.clone();
+            move || dispatcher.tick()
+        });
+        let ticking_thread_id = ticking_thread.thread().id();
+        ticking_thread.join().unwrap();
+        assert_eq!(*threads.lock().unwrap(), vec![ticking_thread_id]);
+    }
+
+    #[test]
+    fn test_executor_dispatcher_runs_tasks_on_executor_thread() {
+        let threads = Arc::new(Mutex::new(vec![]));
+        let (tx, rx) = mpsc::channel::<Task>();
+        let executor = thread::spawn(move || {
+            while let Ok(task) = rx.recv() {
+                task();
+            }
+        });
+        let executor_id = executor.thread().id();
+
+        let tx = Mutex::new(tx);
+        let dispatcher =
+            ExecutorDispatcher::new(move |task| tx.lock().unwrap().send(task).unwrap());
+        dispatcher.dispatch(record_thread(&threads));
+        dispatcher.tick();
+        // Closes the channel, so that the executor thread finishes
+        drop(dispatcher);
+        executor.join().unwrap();
+
+        assert_ne!(executor_id, thread::current().id());
+        assert_eq!(*threads.lock().unwrap(), vec![executor_id]);
+    }
+}
@@ -0,0 +1,470 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encoding and decoding of the socket envelopes.
+//!
+//! An [`EnvelopeCodec`] turns the envelopes sent by the client into frames and the frames received from the
+//! server into a [`ServerMessage`]. Decoding looks at the kind of the message first and only decodes its
+//! payload, instead of the whole [`WebSocketMessageEnvelope`]. The socket dispatches the decoded messages the
+//! same way whatever the codec.
+//!
+//! [`JsonCodec`] is the default, [`ProtobufCodec`] implements the protobuf wire format, see
+//! [`wire_format`](crate::wire_format). A codec is set with
+//! [`WebSocket::set_envelope_codec`](crate::WebSocket::set_envelope_codec) and takes effect on the next
+//! connect.
+use crate::api::{ApiChannelMessage, ApiNotificationList, ApiRpc};
+use crate::socket::{
+    Channel, ChannelMessageAck, ChannelPresenceEvent, Error, Match, MatchData, MatchPresenceEvent,
+    MatchmakerMatched, MatchmakerTicket, Party, PartyClose, PartyData, PartyJoinRequest,
+    PartyLeader, PartyMatchmakerTicket, PartyPresenceEvent, Pong, Status, StatusPresenceEvent,
+    StreamData, StreamPresenceEvent, WebSocketMessageEnvelope,
+};
+use crate::wire_format;
+use nanoserde::{DeJson, DeJsonErr, SerJson};
+use std::error;
+use std::fmt::{Display, Formatter};
+
+/// A websocket frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// The payload of a message sent by the server.
+#[derive(Debug, Clone)]
+pub enum Payload {
+    Channel(Channel),
+    ChannelMessage(ApiChannelMessage),
+    ChannelMessageAck(ChannelMessageAck),
+    ChannelPresenceEvent(ChannelPresenceEvent),
+    Error(Error),
+    Match(Match),
+    MatchData(MatchData),
+    MatchPresenceEvent(MatchPresenceEvent),
+    MatchmakerMatched(MatchmakerMatched),
+    MatchmakerTicket(MatchmakerTicket),
+    Notifications(ApiNotificationList),
+    Rpc(ApiRpc),
+    Status(Status),
+    StatusPresenceEvent(StatusPresenceEvent),
+    StreamData(StreamData),
+    StreamPresenceEvent(StreamPresenceEvent),
+    Pong,
+    Party(Party),
+    PartyClose(PartyClose),
+    PartyData(PartyData),
+    PartyJoinRequest(PartyJoinRequest),
+    PartyLeader(PartyLeader),
+    PartyMatchmakerTicket(PartyMatchmakerTicket),
+    PartyPresenceEvent(PartyPresenceEvent),
+    /// A message without payload, e.g. the acknowledgement of a request, or of a kind the client doesn't handle.
+    Empty,
+}
+
+/// A decoded message sent by the server.
+#[derive(Debug, Clone)]
+pub struct ServerMessage {
+    /// Set if the message is the response to a request.
+    pub cid: Option<String>,
+    pub payload: Payload,
+}
+
+impl ServerMessage {
+    /// The envelope with the payload, returned to the requests.
+    pub(crate) fn into_envelope(self) -> WebSocketMessageEnvelope {
+        let mut envelope = WebSocketMessageEnvelope {
+            cid: self.cid,
+            ..Default::default()
+        };
+        match self.payload {
+            Payload::Channel(message) => envelope.channel = Some(message),
+            Payload::ChannelMessage(message) => envelope.channel_message = Some(message),
+            Payload::ChannelMessageAck(message) => envelope.channel_message_ack = Some(message),
+            Payload::ChannelPresenceEvent(message) => {
+                envelope.channel_presence_event = Some(message)
+            }
+            Payload::Error(message) => envelope.error = Some(message),
+            Payload::Match(message) => envelope.new_match = Some(message),
+            Payload::MatchData(message) => envelope.match_data = Some(message),
+            Payload::MatchPresenceEvent(message) => envelope.match_presence_event = Some(message),
+            Payload::MatchmakerMatched(message) => envelope.matchmaker_matched = Some(message),
+            Payload::MatchmakerTicket(message) => envelope.matchmaker_ticket = Some(message),
+            Payload::Notifications(message) => envelope.notifications = Some(message),
+            Payload::Rpc(message) => envelope.rpc = Some(message),
+            Payload::Status(message) => envelope.status = Some(message),
+            Payload::StatusPresenceEvent(message) => envelope.status_presence_event = Some(message),
+            Payload::StreamData(message) => envelope.stream_data = Some(message),
+            Payload::StreamPresenceEvent(message) => envelope.stream_presence_event = Some(message),
+            Payload::Pong => envelope.pong = Some(Pong {}),
+            Payload::Party(message) => envelope.party = Some(message),
+            Payload::PartyClose(message) => envelope.party_close = Some(message),
+            Payload::PartyData(message) => envelope.party_data = Some(message),
+            Payload::PartyJoinRequest(message) => envelope.party_join_request = Some(message),
+            Payload::PartyLeader(message) => envelope.party_leader = Some(message),
+            Payload::PartyMatchmakerTicket(message) => {
+                envelope.party_matchmaker_ticket = Some(message)
+            }
+            Payload::PartyPresenceEvent(message) => envelope.party_presence_event = Some(message),
+            Payload::Empty => {}
+        }
+        envelope
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The cid is set if it could be read, the request then fails with the error.
+    Json {
+        cid: Option<String>,
+        error: DeJsonErr,
+    },
+    Protobuf(prost::DecodeError),
+    /// A text frame was received by a binary codec or the other way around.
+    UnexpectedFrame,
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Json { error, .. } => write!(f, "Invalid json: {:?}", error),
+            DecodeError::Protobuf(error) => write!(f, "Invalid protobuf: {}", error),
+            DecodeError::UnexpectedFrame => write!(f, "Unexpected frame type"),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+pub trait EnvelopeCodec: Send + Sync {
+    /// The value of the `format` query parameter of the socket url.
+    fn format(&self) -> &'static str;
+
+    /// Encode a message sent by the client.
+    fn encode(&self, envelope: &WebSocketMessageEnvelope) -> Frame;
+
+    /// Decode a message sent by the server.
+    fn decode(&self, frame: Frame) -> Result<ServerMessage, DecodeError>;
+}
+
+/// JSON text frames, the default codec.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+impl EnvelopeCodec for JsonCodec {
+    fn format(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode(&self, envelope: &WebSocketMessageEnvelope) -> Frame {
+        Frame::Text(envelope.serialize_json())
+    }
+
+    fn decode(&self, frame: Frame) -> Result<ServerMessage, DecodeError> {
+        let text = match frame {
+            Frame::Text(text) => text,
+            Frame::Binary(_) => return Err(DecodeError::UnexpectedFrame),
+        };
+
+        let fields = peek_fields(&text).map_err(|error| DecodeError::Json { cid: None, error })?;
+        let payload = match fields.payload {
+            Some((kind, payload)) => decode_json_payload(kind, payload),
+            None => Ok(Payload::Empty),
+        };
+        match payload {
+            Ok(payload) => Ok(ServerMessage {
+                cid: fields.cid,
+                payload,
+            }),
+            Err(error) => Err(DecodeError::Json {
+                cid: fields.cid,
+                error,
+            }),
+        }
+    }
+}
+
+/// Protobuf binary frames, see [`wire_format`](crate::wire_format).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProtobufCodec;
+
+impl EnvelopeCodec for ProtobufCodec {
+    fn format(&self) -> &'static str {
+        "protobuf"
+    }
+
+    fn encode(&self, envelope: &WebSocketMessageEnvelope) -> Frame {
+        Frame::Binary(wire_format::encode_protobuf(envelope))
+    }
+
+    fn decode(&self, frame: Frame) -> Result<ServerMessage, DecodeError> {
+        match frame {
+            Frame::Binary(data) => {
+                wire_format::decode_protobuf(&data).map_err(DecodeError::Protobuf)
+            }
+            Frame::Text(_) => Err(DecodeError::UnexpectedFrame),
+        }
+    }
+}
+
+fn decode_json_payload(kind: &str, json: &str) -> Result<Payload, DeJsonErr> {
+    let payload = match kind {
+        "channel" => Payload::Channel(DeJson::deserialize_json(json)?),
+        "channel_message" => Payload::ChannelMessage(DeJson::deserialize_json(json)?),
+        "channel_message_ack" => Payload::ChannelMessageAck(DeJson::deserialize_json(json)?),
+        "channel_presence_event" => Payload::ChannelPresenceEvent(DeJson::deserialize_json(json)?),
+        "error" => Payload::Error(DeJson::deserialize_json(json)?),
+        "match" => Payload::Match(DeJson::deserialize_json(json)?),
+        "match_data" => Payload::MatchData(DeJson::deserialize_json(json)?),
+        "match_presence_event" => Payload::MatchPresenceEvent(DeJson::deserialize_json(json)?),
+        "matchmaker_matched" => Payload::MatchmakerMatched(DeJson::deserialize_json(json)?),
+        "matchmaker_ticket" => Payload::MatchmakerTicket(DeJson::deserialize_json(json)?),
+        "notifications" => Payload::Notifications(DeJson::deserialize_json(json)?),
+        "rpc" => Payload::Rpc(DeJson::deserialize_json(json)?),
+        "status" => Payload::Status(DeJson::deserialize_json(json)?),
+        "status_presence_event" => Payload::StatusPresenceEvent(DeJson::deserialize_json(json)?),
+        "stream_data" => Payload::StreamData(DeJson::deserialize_json(json)?),
+        "stream_presence_event" => Payload::StreamPresenceEvent(DeJson::deserialize_json(json)?),
+        "pong" => Payload::Pong,
+        "party" => Payload::Party(DeJson::deserialize_json(json)?),
+        "party_close" => Payload::PartyClose(DeJson::deserialize_json(json)?),
+        "party_data" => Payload::PartyData(DeJson::deserialize_json(json)?),
+        "party_join_request" => Payload::PartyJoinRequest(DeJson::deserialize_json(json)?),
+        "party_leader" => Payload::PartyLeader(DeJson::deserialize_json(json)?),
+        "party_matchmaker_ticket" => {
+            Payload::PartyMatchmakerTicket(DeJson::deserialize_json(json)?)
+        }
+        "party_presence_event" => Payload::PartyPresenceEvent(DeJson::deserialize_json(json)?),
+        _ => Payload::Empty,
+    };
+    Ok(payload)
+}
+
+// The top level fields of an envelope, the payload as the name of the field and the unparsed value
+struct Fields<'a> {
+    cid: Option<String>,
+    payload: Option<(&'a str, &'a str)>,
+}
+
+fn json_error(msg: &str) -> DeJsonErr {
+    DeJsonErr {
+        msg: msg.to_owned(),
+        line: 0,
+        col: 0,
+    }
+}
+
+/// Find the cid and the payload of an envelope without parsing the payload.
+fn peek_fields(json: &str) -> Result<Fields<'_>, DeJsonErr> {
+    let bytes = json.as_bytes();
+    let mut fields = Fields {
+        cid: None,
+        payload: None,
+    };
+
+    let mut pos = skip_whitespace(bytes, 0);
+    if bytes.get(pos) != Some(&b'{') {
+        return Err(json_error("Expected an object"));
+    }
+    pos = skip_whitespace(bytes, pos + 1);
+    if bytes.get(pos) == Some(&b'}') {
+        return Ok(fields);
+    }
+
+    loop {
+        let key_start = pos;
+        pos = skip_string(bytes, pos)?;
+        let key = &json[key_start + 1..pos - 1];
+
+        pos = skip_whitespace(bytes, pos);
+        if bytes.get(pos) != Some(&b':') {
+            return Err(json_error("Expected ':'"));
+        }
+        let value_start = skip_whitespace(bytes, pos + 1);
+        pos = skip_value(bytes, value_start)?;
+        let value = &json[value_start..pos];
+
+        if key == "cid" {
+            fields.cid = Option::<String>::deserialize_json(value)?;
+        } else if value != "null" && fields.payload.is_none() {
+            fields.payload = Some((key, value));
+        }
+
+        pos = skip_whitespace(bytes, pos);
+        match bytes.get(pos) {
+            Some(b',') => pos = skip_whitespace(bytes, pos + 1),
+            Some(b'}') => return Ok(fields),
+            _ => return Err(json_error("Expected ',' or '}'")),
+        }
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
+
+/// The position after the closing quote of the string starting at `pos`.
+fn skip_string(bytes: &[u8], mut pos: usize) -> Result<usize, DeJsonErr> {
+    if bytes.get(pos) != Some(&b'"') {
+        return Err(json_error("Expected a string"));
+    }
+    pos += 1;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'"' => return Ok(pos + 1),
+            _ => pos += 1,
+        }
+    }
+    Err(json_error("Unterminated string"))
+}
+
+/// The position after the value starting at `pos`.
+fn skip_value(bytes: &[u8], mut pos: usize) -> Result<usize, DeJsonErr> {
+    match bytes.get(pos) {
+        Some(b'"') => skip_string(bytes, pos),
+        Some(b'{') | Some(b'[') => {
+            let mut depth = 0;
+            while pos < bytes.len() {
+                match bytes[pos] {
+                    b'"' => {
+                        pos = skip_string(bytes, pos)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Ok(pos + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                pos += 1;
+            }
+            Err(json_error("Unterminated object"))
+        }
+        Some(_) => {
+            let start = pos;
+            while pos < bytes.len()
+                && !matches!(bytes[pos], b',' | b'}' | b']')
+                && !bytes[pos].is_ascii_whitespace()
+            {
+                pos += 1;
+            }
+            if pos == start {
+                return Err(json_error("Expected a value"));
+            }
+            Ok(pos)
+        }
+        None => Err(json_error("Unexpected end of input")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode(json: &str) -> ServerMessage {
+        JsonCodec.decode(Frame::Text(json.to_owned())).unwrap()
+    }
+
+    #[test]
+    fn test_decode_event() {
+        let message = decode(
+            r#"{"match_data":{"match_id":"m","presence":{"user_id":"u"},"op_code":1,"data":[1,2],"reliable":true}}"#,
+        );
+        assert!(message.cid.is_none());
+        match message.payload {
+            Payload::MatchData(data) => {
+                assert_eq!(data.match_id, "m");
+                assert_eq!(data.presence.user_id, "u");
+                assert_eq!(data.data, vec![1, 2]);
+            }
+            other => panic!("Expected match data, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_response_after_payload() {
+        let message =
+            decode(r#" { "rpc" : {"id":"a\"}","payload":"{\"x\":[1]}"} , "cid" : "12" } "#);
+        assert_eq!(message.cid.as_deref(), Some("12"));
+        match message.payload {
+            Payload::Rpc(rpc) => {
+                assert_eq!(rpc.id, "a\"}");
+                assert_eq!(rpc.payload, r#"{"x":[1]}"#);
+            }
+            other => panic!("Expected rpc, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_skips_null_fields() {
+        let message = decode(r#"{"cid":"3","channel":null,"status":{"presences":[]}}"#);
+        assert_eq!(message.cid.as_deref(), Some("3"));
+        assert!(matches!(message.payload, Payload::Status(_)));
+    }
+
+    #[test]
+    fn test_decode_acknowledgement() {
+        let message = decode(r#"{"cid":"4"}"#);
+        assert_eq!(message.cid.as_deref(), Some("4"));
+        assert!(matches!(message.payload, Payload::Empty));
+    }
+
+    #[test]
+    fn test_decode_invalid_payload_keeps_cid() {
+        let result = JsonCodec.decode(Frame::Text(r#"{"cid":"5","channel":{"id":1}}"#.to_owned()));
+        match result {
+            Err(DecodeError::Json { cid, .. }) => assert_eq!(cid.as_deref(), Some("5")),
+            other => panic!("Expected a json error, got {:?}", other),
+        }
+        assert!(JsonCodec
+            .decode(Frame::Text(r#"{"cid":"5""#.to_owned()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_json_round_trip_through_envelope() {
+        let message = decode(r#"{"cid":"6","party_close":{"party_id":"p"}}"#);
+        let envelope = message.into_envelope();
+        assert_eq!(envelope.cid.as_deref(), Some("6"));
+        assert_eq!(envelope.party_close.unwrap().party_id, "p");
+    }
+
+    #[test]
+    fn test_decode_agrees_with_full_decode() {
+        let fixtures = include_str!("../tests/fixtures/server_events.jsonl")
+            .lines()
+            .chain(vec![
+                r#"{"cid":"3","channel":null,"status":{"presences":[]}}"#,
+                r#"{"cid":"4"}"#,
+                r#"{"cid":"6","party_close":{"party_id":"p"}}"#,
+            ]);
+        for json in fixtures {
+            let expected = WebSocketMessageEnvelope::deserialize_json(json).unwrap();
+            let envelope = decode(json).into_envelope();
+            assert_eq!(
+                envelope.serialize_json(),
+                expected.serialize_json(),
+                "{}",
+                json
+            );
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Socket events as [`Stream`]s.
+//!
+//! As an alternative to the callbacks, every event of a [`Socket`](crate::Socket) is available as an
+//! [`EventStream`], e.g. [`Socket::channel_messages`](crate::Socket::channel_messages). Async code can then
+//! `select!` over several events and timers.
+//!
+//! Each stream buffers up to [`DEFAULT_EVENT_STREAM_CAPACITY`] events. If the stream isn't polled fast
+//! enough, the oldest events are dropped and the next item is a [`Lagged`] error with the number of dropped
+//! events. Use [`EventStream::new`] for a different capacity.
+//!
+//! The streams of the socket end once the socket has ended, see [`Socket::subscribe_ended`](crate::Socket::subscribe_ended):
+//! it was closed, or the connection was lost and isn't restored by an automatic reconnect. The streams keep
+//! delivering events across a reconnect. Create new streams after connecting again yourself.
+//!
+//! # Example
+//! ```
+//! # #![feature(async_closure)]
+//! # use nakama_rs::test_helpers::*;
+//! use futures::StreamExt;
+//!
+//! # run_in_socket_example(async move |client, session, socket| {
+//! let mut messages = socket.channel_messages();
+//! # if false {
+//! // Ends once the socket is closed for good
+//! while let Some(message) = messages.next().await {
+//!     match message {
+//!         Ok(message) => println!("Chat: {}", message.content),
+//!         Err(lagged) => println!("Missed {} messages", lagged.0),
+//!     }
+//! }
+//! # }
+//! # Ok(())
+//! # });
+//! ```
+use crate::socket_adapter::CloseEvent;
+use crate::subscription::Subscription;
+use futures::Stream;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// The number of events an [`EventStream`] created by the [`Socket`](crate::Socket) buffers.
+pub const DEFAULT_EVENT_STREAM_CAPACITY: usize = 128;
+
+/// The stream dropped this many events because its buffer was full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lagged(pub u64);
+
+impl Display for Lagged {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Lagged behind by {} events", self.0)
+    }
+}
+
+impl Error for Lagged {}
+
+struct Buffer<T> {
+    events: VecDeque<T>,
+    capacity: usize,
+    lagged: u64,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// A stream of socket events. Unsubscribes when dropped.
+pub struct EventStream<T> {
+    buffer: Arc<Mutex<Buffer<T>>>,
+    _subscription: Subscription,
+    _closed_subscription: Option<Subscription>,
+}
+
+impl<T: Send + 'static> EventStream<T> {
+    /// Create a stream buffering up to `capacity` events.
+    ///
+    /// `subscribe` is called with the callback to register, e.g. `|callback| socket.subscribe_match_state(callback)`.
+    pub fn new<S>(capacity: usize, subscribe: S) -> Self
+    where
+        S: FnOnce(Box<dyn Fn(T) + Send + 'static>) -> Subscription,
+    {
+        let capacity = capacity.max(1);
+        let buffer = Arc::new(Mutex::new(Buffer {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            lagged: 0,
+            closed: false,
+            waker: None,
+        }));
+
+        let subscription = subscribe(Box::new({
+            let buffer = buffer.clone();
+            move |event| {
+                let waker = {
+                    let mut buffer = buffer.lock().unwrap();
+                    if buffer.events.len() >= buffer.capacity {
+                        buffer.events.pop_front();
+                        buffer.lagged += 1;
+                    }
+                    buffer.events.push_back(event);
+                    buffer.waker.take()
+                };
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            }
+        }));
+
+        EventStream {
+            buffer,
+            _subscription: subscription,
+            _closed_subscription: None,
+        }
+    }
+
+    /// End the stream once the socket is closed. The events received before are still returned.
+    ///
+    /// `subscribe_closed` is called with the callback to register, e.g. `|callback| socket.subscribe_ended(callback)`.
+    pub fn until_closed<S>(mut self, subscribe_closed: S) -> Self
+    where
+        S: FnOnce(Box<dyn Fn(CloseEvent) + Send + 'static>) -> Subscription,
+    {
+        let buffer = self.buffer.clone();
+        self._closed_subscription = Some(subscribe_closed(Box::new(move |_| {
+            let waker = {
+                let mut buffer = buffer.lock().unwrap();
+                buffer.closed = true;
+                buffer.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        })));
+        self
+    }
+}
+
+impl<T> Stream for EventStream<T> {
+    type Item = Result<T, Lagged>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.lagged > 0 {
+            let lagged = buffer.lagged;
+            buffer.lagged = 0;
+            return Poll::Ready(Some(Err(Lagged(lagged))));
+        }
+
+        match buffer.events.pop_front() {
+            Some(event) => Poll::Ready(Some(Ok(event))),
+            None if buffer.closed => Poll::Ready(None),
+            None => {
+                buffer.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    type Callback = Arc<Mutex<Option<Box<dyn Fn(i32) + Send + 'static>>>>;
+
+    fn stream(capacity: usize) -> (EventStream<i32>, Callback) {
+        let callback: Callback = Arc::new(Mutex::new(None));
+        let stream = EventStream::new(capacity, |subscriber| {
+            *callback.lock().unwrap() = Some(subscriber);
+            Subscription::new(|| {})
+        });
+        (stream, callback)
+    }
+
+    fn dispatch(callback: &Callback, event: i32) {
+        callback.lock().unwrap().as_ref().unwrap()(event);
+    }
+
+    #[test]
+    fn test_events_in_order() {
+        let (mut stream, callback) = stream(4);
+        dispatch(&callback, 1);
+        dispatch(&callback, 2);
+        block_on(async {
+            assert_eq!(stream.next().await, Some(Ok(1)));
+            assert_eq!(stream.next().await, Some(Ok(2)));
+        });
+    }
+
+    #[test]
+    fn test_ends_when_closed() {
+        let closed: Arc<Mutex<Option<Box<dyn Fn(CloseEvent) + Send + 'static>>>> =
+            Arc::new(Mutex::new(None));
+        let (stream, callback) = stream(4);
+        let mut stream = stream.until_closed(|subscriber| {
+            *closed.lock().unwrap() = Some(subscriber);
+            Subscription::new(|| {})
+        });
+        dispatch(&callback, 1);
+        closed.lock().unwrap().as_ref().unwrap()(CloseEvent::new(CloseEvent::NORMAL, "Closed"));
+        block_on(async {
+            assert_eq!(stream.next().await, Some(Ok(1)));
+            assert_eq!(stream.next().await, None);
+        });
+    }
+
+    #[test]
+    fn test_lagged() {
+        let (mut stream, callback) = stream(2);
+        for event in 1..=5 {
+            dispatch(&callback, event);
+        }
+        block_on(async {
+            assert_eq!(stream.next().await, Some(Err(Lagged(3))));
+            assert_eq!(stream.next().await, Some(Ok(4)));
+            assert_eq!(stream.next().await, Some(Ok(5)));
+        });
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Socket heartbeat.
+//!
+//! Half-open TCP connections, e.g. after a network change on a mobile device, aren't noticed until the operating
+//! system gives up on them. With the heartbeat enabled through
+//! [`WebSocket::enable_heartbeat`](crate::WebSocket::enable_heartbeat), the socket sends a `ping` every interval
+//! and measures the round trip time until the `pong`. After [`HeartbeatConfig::max_missed_pongs`] pings without
+//! a pong the connection is considered dead and dropped. If auto reconnect is enabled the socket then
+//! reconnects, see [`reconnect`](crate::reconnect).
+//!
+//! The heartbeat is driven by [`WebSocket::tick`](crate::WebSocket::tick).
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartbeatConfig {
+    /// The time between two pings.
+    pub interval: Duration,
+    /// The number of consecutive pings without a pong after which the connection is dropped.
+    pub max_missed_pongs: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig {
+            interval: Duration::from_secs(10),
+            max_missed_pongs: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HeartbeatAction {
+    Idle,
+    Ping,
+    Dead,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Heartbeat {
+    config: Option<HeartbeatConfig>,
+    pending: Option<(i64, Instant)>,
+    next_ping: Option<Instant>,
+    missed: u32,
+    latest_rtt: Option<Duration>,
+    smoothed_rtt: Option<Duration>,
+}
+
+impl Heartbeat {
+    pub(crate) fn set_config(&mut self, config: Option<HeartbeatConfig>) {
+        self.config = config;
+        self.reset();
+    }
+
+    /// Start over, e.g. after connecting.
+    pub(crate) fn reset(&mut self) {
+        self.pending = None;
+        self.next_ping = None;
+        self.missed = 0;
+    }
+
+    pub(crate) fn latest_rtt(&self) -> Option<Duration> {
+        self.latest_rtt
+    }
+
+    pub(crate) fn smoothed_rtt(&self) -> Option<Duration> {
+        self.smoothed_rtt
+    }
+
+    /// What to do at `now`. A [`HeartbeatAction::Ping`] must be followed by [`Heartbeat::ping_sent`].
+    pub(crate) fn poll(&mut self, now: Instant) -> HeartbeatAction {
+        let config = match self.config {
+            Some(ref config) => config,
+            None => return HeartbeatAction::Idle,
+        };
+
+        let next_ping = *self.next_ping.get_or_insert(now + config.interval);
+        if next_ping > now {
+            return HeartbeatAction::Idle;
+        }
+
+        if self.pending.take().is_some() {
+            self.missed += 1;
+        }
+        if self.missed >= config.max_missed_pongs.max(1) {
+            self.reset();
+            return HeartbeatAction::Dead;
+        }
+
+        self.next_ping = Some(now + config.interval);
+        HeartbeatAction::Ping
+    }
+
+    pub(crate) fn ping_sent(&mut self, cid: i64, now: Instant) {
+        self.pending = Some((cid, now));
+    }
+
+    /// Returns false if `cid` doesn't belong to the last ping.
+    pub(crate) fn pong_received(&mut self, cid: i64, now: Instant) -> bool {
+        match self.pending {
+            Some((pending_cid, sent)) if pending_cid == cid => {
+                let rtt = now - sent;
+                self.pending = None;
+                self.missed = 0;
+                self.latest_rtt = Some(rtt);
+                // Like TCP, see RFC 6298
+                self.smoothed_rtt = Some(match self.smoothed_rtt {
+                    Some(smoothed_rtt) => smoothed_rtt * 7 / 8 + rtt / 8,
+                    None => rtt,
+                });
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn heartbeat(max_missed_pongs: u32) -> Heartbeat {
+        let mut heartbeat = Heartbeat::default();
+        heartbeat.set_config(Some(HeartbeatConfig {
+            interval: Duration::from_secs(1),
+            max_missed_pongs,
+        }));
+        heartbeat
+    }
+
+    #[test]
+    fn test_round_trip_time() {
+        let mut heartbeat = heartbeat(3);
+        let start = Instant::now();
+        assert_eq!(heartbeat.poll(start), HeartbeatAction::Idle);
+
+        let now = start + Duration::from_secs(1);
+        assert_eq!(heartbeat.poll(now), HeartbeatAction::Ping);
+        heartbeat.ping_sent(1, now);
+        assert!(!heartbeat.pong_received(2, now + Duration::from_millis(80)));
+        assert!(heartbeat.pong_received(1, now + Duration::from_millis(80)));
+        assert_eq!(heartbeat.latest_rtt(), Some(Duration::from_millis(80)));
+        assert_eq!(heartbeat.smoothed_rtt(), Some(Duration::from_millis(80)));
+
+        let now = start + Duration::from_secs(2);
+        assert_eq!(heartbeat.poll(now), HeartbeatAction::Ping);
+        heartbeat.ping_sent(3, now);
+        assert!(heartbeat.pong_received(3, now + Duration::from_millis(160)));
+        assert_eq!(heartbeat.latest_rtt(), Some(Duration::from_millis(160)));
+        assert_eq!(heartbeat.smoothed_rtt(), Some(Duration::from_millis(90)));
+    }
+
+    #[test]
+    fn test_missed_pongs() {
+        let mut heartbeat = heartbeat(2);
+        let start = Instant::now();
+        heartbeat.poll(start);
+
+        let now = start + Duration::from_secs(1);
+        assert_eq!(heartbeat.poll(now), HeartbeatAction::Ping);
+        heartbeat.ping_sent(1, now);
+
+        let now = start + Duration::from_secs(2);
+        assert_eq!(heartbeat.poll(now), HeartbeatAction::Ping);
+        heartbeat.ping_sent(2, now);
+
+        let now = start + Duration::from_secs(3);
+        assert_eq!(heartbeat.poll(now), HeartbeatAction::Dead);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut heartbeat = Heartbeat::default();
+        let now = Instant::now();
+        assert_eq!(heartbeat.poll(now), HeartbeatAction::Idle);
+        assert_eq!(
+            heartbeat.poll(now + Duration::from_secs(60)),
+            HeartbeatAction::Idle
+        );
+    }
+}
@@ -19,9 +19,11 @@ use std::fmt::{Display, Formatter};
 use crate::api;
 use crate::client_adapter::ClientAdapter;
 use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
 use isahc::prelude::*;
 use nanoserde::{DeJson, DeJsonErr};
 use std::io;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
 pub enum RestHttpError {
@@ -45,6 +47,7 @@ impl Error for RestHttpError {}
 pub struct RestHttpAdapter {
     server: String,
     port: u32,
+    clock_offset: Arc<Mutex<Option<Duration>>>,
 }
 
 impl RestHttpAdapter {
@@ -52,6 +55,20 @@ impl RestHttpAdapter {
         RestHttpAdapter {
             server: server.to_owned(),
             port,
+            clock_offset: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Store the offset of the server clock from the `Date` header. The header has a resolution of one second.
+    fn update_clock_offset<T>(&self, response: &isahc::Response<T>) {
+        let server_time = response
+            .headers()
+            .get("Date")
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok());
+        if let Some(server_time) = server_time {
+            let offset = server_time.with_timezone(&Utc) - Utc::now();
+            *self.clock_offset.lock().unwrap() = Some(offset);
         }
     }
 }
@@ -61,15 +78,12 @@ impl ClientAdapter for RestHttpAdapter {
     type Error = RestHttpError;
     async fn send<T: DeJson + Send>(&self, request: RestRequest<T>) -> Result<T, RestHttpError> {
         let auth_header = match request.authentication {
-            api::Authentication::Basic { username, password } => {
-                format!(
-                    "Basic {}",
-                    base64::encode(&format!("{}:{}", username, password))
-                )
-            }
-            api::Authentication::Bearer { token } => {
-                format!("Bearer {}", token)
-            }
+            api::Authentication::Basic { username, password } => Some(format!(
+                "Basic {}",
+                base64::encode(&format!("{}:{}", username, password))
+            )),
+            api::Authentication::Bearer { token } => Some(format!("Bearer {}", token)),
+            api::Authentication::None => None,
         };
 
         let url = format!(
@@ -77,8 +91,11 @@ impl ClientAdapter for RestHttpAdapter {
             self.server, self.port, request.urlpath, request.query_params
         );
 
-        let client = isahc::HttpClientBuilder::new()
-            .default_header("Authorization", &auth_header)
+        let mut builder = isahc::HttpClientBuilder::new();
+        if let Some(auth_header) = auth_header {
+            builder = builder.default_header("Authorization", &auth_header);
+        }
+        let client = builder
             .build()
             .map_err(|err| RestHttpError::HttpError(err))?;
 
@@ -90,6 +107,8 @@ impl ClientAdapter for RestHttpAdapter {
         }
         .map_err(|err| RestHttpError::HttpError(err))?;
 
+        self.update_clock_offset(&response);
+
         match response.status().as_u16() {
             status if status >= 200 && status < 300 => {
                 let response = response
@@ -117,4 +136,17 @@ impl ClientAdapter for RestHttpAdapter {
             _ => Err(RestHttpError::OtherError("Unknown status".to_owned())),
         }
     }
+
+    fn status_code(error: &RestHttpError) -> Option<u16> {
+        match error {
+            RestHttpError::ClientError(status, _) | RestHttpError::ServerError(status, _) => {
+                Some(*status)
+            }
+            _ => None,
+        }
+    }
+
+    fn clock_offset(&self) -> Option<Duration> {
+        *self.clock_offset.lock().unwrap()
+    }
 }
@@ -120,8 +120,8 @@
 //! # run_in_example(async |_client, session| {
 //!     let mut socket = WebSocket::new_with_adapter();
 //!     socket.on_connected(|| println!("Socket connected."));
-//!     socket.on_closed(|| println!("Socket closed."));
-//!     socket.connect(&session).await;
+//!     socket.on_closed(|event| println!("Socket closed: {}", event.reason));
+//!     socket.connect(&session, true, 30).await.expect("Failed to connect");
 //! # });
 //! ```
 //!
@@ -166,11 +166,13 @@
 //! ```
 //!
 //! Event handlers only need to be implemented for the features you want to use.
+//! Each event can have several handlers. The `subscribe_*` variants return a
+//! [`Subscription`](subscription::Subscription) which removes the handler when it is dropped.
 //!
 //! | Callbacks | Description |
 //! | --------- | ----------- |
 //! | on_connected | Receive an event when the socket connects. |
-//! | on_closed | Receives an event for when the client is disconnected from the server. |
+//! | on_closed | Receives an event with the close code and reason for when the client is disconnected from the server. |
 //! | on_received_error | Receives events about server errors. |
 //! | on_received_notifiation | Receives live [in-app notifications](social-in-app-notifications.md) sent from the server. |
 //! | on_received_channel_message | Receives [realtime chat](social-realtime-chat.md) messages sent by other users. |
@@ -232,20 +234,46 @@
 
 mod api_gen;
 mod api_gen_enum;
+mod atomic_write;
+mod realtime_gen {
+    pub mod api {
+        include!(concat!(env!("OUT_DIR"), "/nakama.api.rs"));
+    }
+    pub mod realtime {
+        include!(concat!(env!("OUT_DIR"), "/nakama.realtime.rs"));
+    }
+}
 
+pub mod account_upgrade;
+pub mod auth_request;
 pub mod client;
 pub mod client_adapter;
 pub mod config;
+pub mod credential_provider;
 pub mod default_client;
+pub mod device_id;
+pub mod dispatcher;
+pub mod envelope_codec;
+pub mod event_stream;
+pub mod heartbeat;
 pub mod http_adapter;
 pub mod matchmaker;
+pub mod reachability;
+pub mod reconnect;
+pub mod refresh_scheduler;
+pub mod rpc;
 pub mod session;
+pub mod session_manager;
 pub mod socket;
 pub mod socket_adapter;
+pub mod subscription;
 #[cfg(feature = "test")]
 pub mod test_helpers;
+#[cfg(feature = "jwt-verification")]
+pub mod token_verifier;
 pub mod web_socket;
 pub mod web_socket_adapter;
+pub mod wire_format;
 
 pub use client::Client;
 pub use default_client::DefaultClient;
@@ -256,3 +284,9 @@ pub use web_socket::WebSocket;
 pub mod api {
     pub use super::api_gen::*;
 }
+
+/// The protobuf messages of the realtime API, see [`wire_format`].
+pub mod rtapi {
+    pub use super::realtime_gen::api;
+    pub use super::realtime_gen::realtime::*;
+}
@@ -0,0 +1,212 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Server reachability.
+//!
+//! The [`ReachabilityMonitor`] calls [`Client::healthcheck`] periodically, measures the latency and tracks
+//! whether the server is up. A callback is invoked whenever the state changes, e.g. to show a maintenance
+//! message before the player tries to log in.
+//!
+//! Like the [`SessionRefreshScheduler`](crate::refresh_scheduler::SessionRefreshScheduler), the monitor is
+//! driven either by calling [`ReachabilityMonitor::tick`] regularly or by awaiting [`ReachabilityMonitor::run`].
+//!
+//! # Example
+//! ```
+//! use nakama_rs::reachability::{Reachability, ReachabilityMonitor};
+//! use nakama_rs::DefaultClient;
+//! use std::time::Duration;
+//!
+//! let client = DefaultClient::new_with_adapter_and_defaults();
+//! let monitor = ReachabilityMonitor::new(&client, Duration::from_secs(30));
+//! monitor.on_changed(|change| {
+//!     if change.current == Reachability::Down {
+//!         println!("Server maintenance");
+//!     }
+//! });
+//!
+//! loop {
+//!     monitor.tick();
+//!     # break;
+//! }
+//! ```
+use crate::client::Client;
+use futures::task::noop_waker_ref;
+use log::{error, trace};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+type ProbeFuture<E> = Pin<Box<dyn Future<Output = Result<(), E>> + Send>>;
+
+/// Whether the server responds to health checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    /// The server hasn't been probed yet.
+    Unknown,
+    Up,
+    Down,
+}
+
+/// Passed to the [`ReachabilityMonitor::on_changed`] callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReachabilityChange {
+    pub previous: Reachability,
+    pub current: Reachability,
+    /// The latency of the last successful probe.
+    pub latency: Option<Duration>,
+}
+
+struct State<E> {
+    reachability: Reachability,
+    latency: Option<Duration>,
+    pending: Option<(Instant, ProbeFuture<E>)>,
+    next_probe: Option<Instant>,
+}
+
+/// Probes the server periodically.
+pub struct ReachabilityMonitor<C: Client> {
+    client: C,
+    interval: Duration,
+    state: Mutex<State<C::Error>>,
+    on_changed: Mutex<Option<Arc<dyn Fn(ReachabilityChange) + Send + Sync + 'static>>>,
+}
+
+impl<C> ReachabilityMonitor<C>
+where
+    C: Client + Clone + Send + Sync + 'static,
+{
+    /// Create a monitor that probes the server every `interval`. The first probe happens on the first tick.
+    pub fn new(client: &C, interval: Duration) -> Self {
+        ReachabilityMonitor {
+            client: client.clone(),
+            interval,
+            state: Mutex::new(State {
+                reachability: Reachability::Unknown,
+                latency: None,
+                pending: None,
+                next_probe: None,
+            }),
+            on_changed: Mutex::new(None),
+        }
+    }
+
+    /// Set the callback invoked when the server goes up or down.
+    pub fn on_changed<T>(&self, callback: T)
+    where
+        T: Fn(ReachabilityChange) + Send + Sync + 'static,
+    {
+        *self.on_changed.lock().unwrap() = Some(Arc::new(callback));
+    }
+
+    pub fn reachability(&self) -> Reachability {
+        self.state.lock().unwrap().reachability
+    }
+
+    /// The round trip time of the last successful probe.
+    pub fn latency(&self) -> Option<Duration> {
+        self.state.lock().unwrap().latency
+    }
+
+    /// Probe the server once and update the state.
+    pub async fn probe(&self) -> Reachability {
+        let started = Instant::now();
+        let result = self.client.healthcheck().await;
+        self.finish_probe(started, result)
+    }
+
+    /// Start or continue probing the server if it is due.
+    ///
+    /// Needs to be called regularly, e.g. once per frame. The measured latency includes the time until the
+    /// tick after the response arrived.
+    pub fn tick(&self) -> Reachability {
+        let now = Instant::now();
+        let (started, result) = {
+            let mut state = self.state.lock().unwrap();
+            if state.pending.is_none() {
+                if state
+                    .next_probe
+                    .map_or(false, |next_probe| next_probe > now)
+                {
+                    return state.reachability;
+                }
+
+                trace!("tick: Probing server");
+                let client = self.client.clone();
+                state.pending = Some((now, Box::pin(async move { client.healthcheck().await })));
+            }
+
+            let mut context = Context::from_waker(noop_waker_ref());
+            let (started, future) = state.pending.as_mut().unwrap();
+            let started = *started;
+            let poll = future.as_mut().poll(&mut context);
+            let result = match poll {
+                Poll::Pending => return state.reachability,
+                Poll::Ready(result) => result,
+            };
+            state.pending = None;
+            (started, result)
+        };
+
+        self.finish_probe(started, result)
+    }
+
+    /// Probe the server every interval until the returned future is dropped.
+    ///
+    /// `sleep` is called with the interval, e.g. `async_std::task::sleep` or `tokio::time::sleep`.
+    pub async fn run<S, F>(&self, sleep: S)
+    where
+        S: Fn(Duration) -> F,
+        F: Future<Output = ()>,
+    {
+        loop {
+            self.probe().await;
+            sleep(self.interval).await;
+        }
+    }
+
+    fn finish_probe(&self, started: Instant, result: Result<(), C::Error>) -> Reachability {
+        let latency = started.elapsed();
+        let change = {
+            let mut state = self.state.lock().unwrap();
+            let previous = state.reachability;
+            state.next_probe = Some(Instant::now() + self.interval);
+            match result {
+                Ok(()) => {
+                    state.reachability = Reachability::Up;
+                    state.latency = Some(latency);
+                }
+                Err(err) => {
+                    error!("Healthcheck failed: {}", err);
+                    state.reachability = Reachability::Down;
+                }
+            }
+
+            ReachabilityChange {
+                previous,
+                current: state.reachability,
+                latency: state.latency,
+            }
+        };
+
+        if change.previous != change.current {
+            let callback = self.on_changed.lock().unwrap().clone();
+            if let Some(callback) = callback {
+                callback(change);
+            }
+        }
+        change.current
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Automatic socket reconnection.
+//!
+//! Once enabled with [`WebSocket::enable_auto_reconnect`](crate::WebSocket::enable_auto_reconnect), a socket
+//! that loses its connection reconnects with an exponential backoff. The session is refreshed first if it is
+//! about to expire. The chat channels, followed users and parties joined through the socket are joined again
+//! after the reconnect.
+//!
+//! Reconnecting is driven by [`WebSocket::tick`](crate::WebSocket::tick).
+//!
+//! Users followed by username are tracked by user id once the server reported their presence, so that
+//! [`Socket::unfollow_users`](crate::Socket::unfollow_users) stops following them. Users that were offline
+//! when they were followed by username stay tracked by username and are followed again after a reconnect,
+//! even if they were unfollowed by user id in the meantime.
+//!
+//! # Example
+//! ```
+//! # #![feature(async_closure)]
+//! # use nakama_rs::test_helpers::*;
+//! use nakama_rs::reconnect::ReconnectPolicy;
+//! # run_in_socket_example(async move |client, session, socket| {
+//! socket.enable_auto_reconnect(&client, ReconnectPolicy::default());
+//! socket.on_reconnecting(|attempt, delay| println!("Reconnect attempt {} in {:?}", attempt, delay));
+//! socket.on_reconnected(|report| {
+//!     for failure in report.failed {
+//!         println!("Failed to restore {:?}: {}", failure.membership, failure.error);
+//!     }
+//! });
+//! # Ok(())
+//! # });
+//! ```
+use crate::socket::{ChannelJoin, PartyJoin, StatusFollow, UserPresence};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How often and how fast to reconnect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// The delay before the first attempt.
+    pub initial_delay: Duration,
+    /// The delay is multiplied by `multiplier` after every failed attempt, up to `max_delay`.
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    /// Give up after this many failed attempts. `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The delay before the `attempt`th attempt, starting at 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        if delay.is_finite() && delay < self.max_delay.as_secs_f64() {
+            Duration::from_secs_f64(delay)
+        } else {
+            self.max_delay
+        }
+    }
+}
+
+/// Something joined through the socket that is restored after a reconnect.
+#[derive(Debug, Clone)]
+pub enum Membership {
+    Chat(ChannelJoin),
+    Follow(StatusFollow),
+    Party(PartyJoin),
+}
+
+/// A membership that couldn't be restored.
+#[derive(Debug, Clone)]
+pub struct RestoreFailure {
+    pub membership: Membership,
+    pub error: String,
+}
+
+/// Passed to the `on_reconnected` callback.
+#[derive(Debug, Clone)]
+pub struct ReconnectReport {
+    /// The number of attempts it took to reconnect.
+    pub attempts: u32,
+    pub failed: Vec<RestoreFailure>,
+}
+
+/// The memberships tracked from the outgoing socket messages.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Memberships {
+    /// Keyed by channel id
+    pub(crate) chats: HashMap<String, ChannelJoin>,
+    pub(crate) follow_user_ids: Vec<String>,
+    pub(crate) follow_usernames: Vec<String>,
+    pub(crate) parties: Vec<String>,
+}
+
+impl Memberships {
+    /// Track the followed users. The users followed by username that are in `presences` are tracked by user id.
+    pub(crate) fn follow(
+        &mut self,
+        user_ids: &[&str],
+        usernames: &[&str],
+        presences: &[UserPresence],
+    ) {
+        for user_id in user_ids {
+            self.follow_user_id(user_id);
+        }
+        for username in usernames {
+            match presences
+                .iter()
+                .find(|presence| presence.username == *username)
+            {
+                Some(presence) => {
+                    self.follow_usernames.retain(|name| name != username);
+                    self.follow_user_id(&presence.user_id);
+                }
+                None if !self.follow_usernames.iter().any(|name| name == username) => {
+                    self.follow_usernames.push(username.to_string());
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn follow_user_id(&mut self, user_id: &str) {
+        if !self.follow_user_ids.iter().any(|id| id == user_id) {
+            self.follow_user_ids.push(user_id.to_owned());
+        }
+    }
+
+    pub(crate) fn unfollow(&mut self, user_ids: &[&str]) {
+        self.follow_user_ids
+            .retain(|id| !user_ids.contains(&id.as_str()));
+    }
+
+    pub(crate) fn join_party(&mut self, party_id: &str) {
+        if !self.parties.iter().any(|id| id == party_id) {
+            self.parties.push(party_id.to_owned());
+        }
+    }
+
+    pub(crate) fn leave_party(&mut self, party_id: &str) {
+        self.parties.retain(|id| id != party_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = ReconnectPolicy {
+            max_attempts: Some(10),
+            ..ReconnectPolicy::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(4), Duration::from_secs(8));
+        assert_eq!(policy.delay(6), Duration::from_secs(30));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_follows() {
+        let mut memberships = Memberships::default();
+        memberships.follow(&["a", "b"], &["alice"], &[]);
+        memberships.follow(&["b", "c"], &[], &[]);
+        memberships.unfollow(&["a"]);
+        assert_eq!(memberships.follow_user_ids, vec!["b", "c"]);
+        assert_eq!(memberships.follow_usernames, vec!["alice"]);
+    }
+
+    #[test]
+    fn test_follow_username_with_presence() {
+        let mut memberships = Memberships::default();
+        memberships.follow(&[], &["alice", "bob"], &[]);
+        let alice = UserPresence {
+            user_id: "a".to_owned(),
+            username: "alice".to_owned(),
+            ..UserPresence::default()
+        };
+        memberships.follow(&[], &["alice"], &[alice]);
+        assert_eq!(memberships.follow_user_ids, vec!["a"]);
+        assert_eq!(memberships.follow_usernames, vec!["bob"]);
+
+        memberships.unfollow(&["a"]);
+        assert!(memberships.follow_user_ids.is_empty());
+    }
+}
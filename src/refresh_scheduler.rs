@@ -0,0 +1,216 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proactive session refresh.
+//!
+//! The [`DefaultClient`](crate::DefaultClient) only refreshes a session right before the next request is sent.
+//! A player that stays idle for longer than the session lifetime ends up with an expired auth token, which the
+//! socket can't use anymore. The [`SessionRefreshScheduler`] refreshes the session `margin` before it expires instead.
+//!
+//! The scheduler can be driven in two ways:
+//! - Call [`SessionRefreshScheduler::tick`] in the same loop that calls [`WebSocket::tick`](crate::WebSocket::tick).
+//! - Await [`SessionRefreshScheduler::run`] with the sleep function of the async runtime of your choice.
+//!
+//! Once the refresh token has expired the session can't be refreshed anymore and the scheduler stops.
+//!
+//! # Example
+//! ```
+//! # #![feature(async_closure)]
+//! # use nakama_rs::test_helpers::*;
+//! # use nakama_rs::refresh_scheduler::SessionRefreshScheduler;
+//! # use chrono::Duration;
+//! # run_in_example(async move |client, session| {
+//! let scheduler = SessionRefreshScheduler::new(&client, &session, Duration::minutes(5));
+//! loop {
+//!     // socket.tick();
+//!     scheduler.tick()?;
+//!     # break;
+//! }
+//! # Ok(())
+//! # });
+//! ```
+use crate::client::Client;
+use crate::session::Session;
+use chrono::{DateTime, Duration, Utc};
+use futures::task::noop_waker_ref;
+use log::{error, trace};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+type RefreshFuture<E> = Pin<Box<dyn Future<Output = Result<(), E>> + Send>>;
+
+/// The result of a [`SessionRefreshScheduler::tick`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshStatus {
+    /// The session doesn't need to be refreshed yet.
+    Idle,
+    /// A refresh request is in flight.
+    Refreshing,
+    /// The session was refreshed during this tick.
+    Refreshed,
+    /// The session has no refresh token or the refresh token has expired. The session needs to be
+    /// authenticated again.
+    Stopped,
+}
+
+struct State<E> {
+    pending: Option<RefreshFuture<E>>,
+    not_before: Option<DateTime<Utc>>,
+}
+
+/// Refreshes a session a configurable margin before it expires.
+pub struct SessionRefreshScheduler<C: Client> {
+    client: C,
+    session: Session,
+    margin: Duration,
+    min_interval: Duration,
+    state: Mutex<State<C::Error>>,
+}
+
+impl<C> SessionRefreshScheduler<C>
+where
+    C: Client + Clone + Send + Sync + 'static,
+{
+    /// Create a scheduler that refreshes `session` `margin` before the auth token expires.
+    pub fn new(client: &C, session: &Session, margin: Duration) -> Self {
+        SessionRefreshScheduler {
+            client: client.clone(),
+            session: session.clone(),
+            margin,
+            min_interval: Duration::seconds(10),
+            state: Mutex::new(State {
+                pending: None,
+                not_before: None,
+            }),
+        }
+    }
+
+    /// Set the minimum time between two refresh attempts. Defaults to 10 seconds.
+    ///
+    /// This applies after failed refreshes and when the token lifetime configured on the server is
+    /// shorter than the margin.
+    pub fn set_min_interval(&mut self, min_interval: Duration) {
+        self.min_interval = min_interval;
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// The server time at which the next refresh is due. See [`Session::server_time`].
+    ///
+    /// Returns `None` if the session can't be refreshed anymore.
+    pub fn next_refresh_time(&self) -> Option<DateTime<Utc>> {
+        if self.is_stopped(self.session.server_time()) {
+            return None;
+        }
+
+        let due = self.session.expire_time() - self.margin;
+        match self.state.lock().unwrap().not_before {
+            Some(not_before) if not_before > due => Some(not_before),
+            _ => Some(due),
+        }
+    }
+
+    /// Start or continue refreshing the session if it is due.
+    ///
+    /// Needs to be called regularly, e.g. once per frame. Returns an error if the refresh request failed.
+    /// The refresh is retried after the minimum interval.
+    pub fn tick(&self) -> Result<RefreshStatus, C::Error> {
+        let now = self.session.server_time();
+        let mut state = self.state.lock().unwrap();
+
+        if state.pending.is_none() {
+            if self.is_stopped(now) {
+                return Ok(RefreshStatus::Stopped);
+            }
+
+            let due = self.session.expire_time() - self.margin;
+            if due > now || state.not_before.map_or(false, |not_before| not_before > now) {
+                return Ok(RefreshStatus::Idle);
+            }
+
+            trace!("tick: Refreshing session of user {}", self.session.user_id());
+            state.pending = Some(self.refresh_future());
+        }
+
+        let mut context = Context::from_waker(noop_waker_ref());
+        let result = match state.pending.as_mut().unwrap().as_mut().poll(&mut context) {
+            Poll::Pending => return Ok(RefreshStatus::Refreshing),
+            Poll::Ready(result) => result,
+        };
+
+        state.pending = None;
+        state.not_before = Some(now + self.min_interval);
+        match result {
+            Ok(()) => Ok(RefreshStatus::Refreshed),
+            Err(err) => {
+                error!("tick: Failed to refresh session: {}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Refresh the session whenever it is due until the refresh token expires.
+    ///
+    /// `sleep` is called with the time to wait until the next refresh, e.g. `async_std::task::sleep`
+    /// or `tokio::time::sleep`. Returns `Ok(())` once the session can't be refreshed anymore, or the
+    /// error of the first failed refresh.
+    pub async fn run<S, F>(&self, sleep: S) -> Result<(), C::Error>
+    where
+        S: Fn(std::time::Duration) -> F,
+        F: Future<Output = ()>,
+    {
+        loop {
+            let due = match self.next_refresh_time() {
+                Some(due) => due,
+                None => return Ok(()),
+            };
+
+            let wait = (due - self.session.server_time())
+                .to_std()
+                .unwrap_or(std::time::Duration::from_secs(0));
+            sleep(wait).await;
+
+            if self.is_stopped(self.session.server_time()) {
+                return Ok(());
+            }
+
+            trace!("run: Refreshing session of user {}", self.session.user_id());
+            let result = self.refresh_future().await;
+            self.state.lock().unwrap().not_before =
+                Some(self.session.server_time() + self.min_interval);
+            result?;
+        }
+    }
+
+    fn is_stopped(&self, now: DateTime<Utc>) -> bool {
+        self.session.get_refresh_token().is_none() || self.session.has_refresh_expired(now)
+    }
+
+    fn refresh_future(&self) -> RefreshFuture<C::Error> {
+        let client = self.client.clone();
+        let session = self.session.clone();
+        Box::pin(async move {
+            let vars = session.vars();
+            let vars = vars
+                .iter()
+                .map(|(key, val)| (key.as_str(), val.as_str()))
+                .collect();
+            client.session_refresh(&session, vars).await
+        })
+    }
+}
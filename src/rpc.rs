@@ -0,0 +1,136 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed RPC calls.
+//!
+//! [`Client::rpc_typed`](crate::Client::rpc_typed) and [`Socket::rpc_typed`](crate::Socket::rpc_typed)
+//! serialize the request to JSON and deserialize the response payload. Implement [`RpcId`] to declare an
+//! RPC function with its request and response types once and call it with `rpc_call`.
+//!
+//! # Example
+//! ```
+//! # #![feature(async_closure)]
+//! # use nakama_rs::test_helpers::*;
+//! use nakama_rs::rpc::RpcId;
+//! use nanoserde::{DeJson, SerJson};
+//!
+//! #[derive(SerJson, DeJson)]
+//! struct Echo {
+//!     message: String,
+//! }
+//!
+//! struct EchoPayload;
+//!
+//! impl RpcId for EchoPayload {
+//!     const ID: &'static str = "echo_payload";
+//!     type Request = Echo;
+//!     type Response = Echo;
+//! }
+//!
+//! # run_in_example(async move |client, session| {
+//! let request = Echo { message: "Hello World!".to_owned() };
+//! let response = client.rpc_call::<EchoPayload>(&session, &request).await?;
+//! assert_eq!(response.message, "Hello World!");
+//! # Ok(())
+//! # });
+//! ```
+//!
+//! # Binary payloads
+//! RPC payloads are strings. `rpc_bytes` encodes binary payloads with the [`PayloadCodec`] of the client or
+//! socket, base64 by default, and `decode_rpc_payload` decodes the response with the same codec, e.g.
+//! [`DefaultClient::decode_rpc_payload`](crate::DefaultClient::decode_rpc_payload). The server runtime needs
+//! to use the same encoding, e.g. `nk.base64_decode` in Lua or `base64.StdEncoding` in Go.
+use crate::api::ApiRpc;
+use nanoserde::{DeJson, DeJsonErr, SerJson};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Declares an RPC function and the types of its payloads.
+pub trait RpcId {
+    /// The id the function is registered with on the server.
+    const ID: &'static str;
+    type Request: SerJson + Sync;
+    type Response: DeJson + Send;
+}
+
+/// The response payload of an RPC function couldn't be deserialized.
+#[derive(Debug)]
+pub struct RpcDecodeError {
+    pub id: String,
+    pub payload: String,
+    pub error: DeJsonErr,
+}
+
+impl Display for RpcDecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to decode the response of RPC {}: {:?}",
+            self.id, self.error
+        )
+    }
+}
+
+impl Error for RpcDecodeError {}
+
+pub(crate) fn decode_response<T: DeJson>(id: &str, payload: &str) -> Result<T, RpcDecodeError> {
+    T::deserialize_json(payload).map_err(|error| RpcDecodeError {
+        id: id.to_owned(),
+        payload: payload.to_owned(),
+        error,
+    })
+}
+
+/// The payload of an RPC response isn't valid for the codec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadDecodeError(pub String);
+
+impl Display for PayloadDecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to decode payload: {}", self.0)
+    }
+}
+
+impl Error for PayloadDecodeError {}
+
+/// Encodes binary RPC payloads as strings.
+pub trait PayloadCodec: Send + Sync {
+    fn encode(&self, bytes: &[u8]) -> String;
+
+    fn decode(&self, payload: &str) -> Result<Vec<u8>, PayloadDecodeError>;
+}
+
+/// Standard base64 with padding. The default [`PayloadCodec`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Base64Codec;
+
+impl PayloadCodec for Base64Codec {
+    fn encode(&self, bytes: &[u8]) -> String {
+        base64::encode(bytes)
+    }
+
+    fn decode(&self, payload: &str) -> Result<Vec<u8>, PayloadDecodeError> {
+        base64::decode(payload).map_err(|err| PayloadDecodeError(err.to_string()))
+    }
+}
+
+impl ApiRpc {
+    /// Decode a binary payload encoded with `codec`.
+    pub fn decode_payload<C: PayloadCodec + ?Sized>(
+        &self,
+        codec: &C,
+    ) -> Result<Vec<u8>, PayloadDecodeError> {
+        codec.decode(&self.payload)
+    }
+}
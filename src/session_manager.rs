@@ -0,0 +1,629 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sessions of several accounts in one process.
+//!
+//! The [`SessionManager`] holds named sessions together with the [`CredentialProvider`] used to
+//! authenticate them. One of the sessions is the active one. Each session is refreshed on its own, and
+//! authenticated again with its credentials once it can't be refreshed anymore.
+//!
+//! Sessions can be persisted with a [`SessionStore`]. Only the tokens are stored, credentials need to be
+//! registered again with [`SessionManager::set_credentials`] after loading.
+//!
+//! # Example
+//! ```
+//! # use futures::executor::block_on;
+//! use nakama_rs::credential_provider::DeviceCredentials;
+//! use nakama_rs::session_manager::{MemorySessionStore, SessionManager};
+//! use nakama_rs::DefaultClient;
+//! use std::collections::HashMap;
+//!
+//! let client = DefaultClient::new_with_adapter_and_defaults();
+//! let mut manager = SessionManager::new(&client);
+//! manager.set_store(MemorySessionStore::default());
+//! # block_on(async {
+//! manager.add("player", DeviceCredentials::new("playerdeviceid", HashMap::new())).await?;
+//! manager.add("admin", DeviceCredentials::new("admindeviceid", HashMap::new())).await?;
+//!
+//! manager.set_active("admin");
+//! let session = manager.active().unwrap();
+//! # Ok::<(), nakama_rs::default_client::DefaultClientError<_>>(())
+//! # }).unwrap();
+//! ```
+use crate::atomic_write::write_atomically;
+use crate::client::Client;
+use crate::client_adapter::ClientAdapter;
+use crate::credential_provider::CredentialProvider;
+use crate::default_client::{DefaultClient, DefaultClientError};
+use crate::session::{Session, SessionData};
+use crate::socket::Socket;
+use crate::web_socket::{WebSocket, WebSocketError};
+use crate::web_socket_adapter::WebSocketAdapter;
+use futures::FutureExt;
+use log::{error, trace};
+use nanoserde::{DeJson, SerJson};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// The persisted state of a [`SessionManager`].
+#[derive(Debug, Clone, Default, DeJson, SerJson)]
+pub struct StoredSessions {
+    pub active: Option<String>,
+    pub sessions: HashMap<String, SessionData>,
+}
+
+/// Loads and saves the sessions of a [`SessionManager`].
+pub trait SessionStore: Send + Sync {
+    /// Returns `None` if nothing was saved yet.
+    fn load(&self) -> io::Result<Option<StoredSessions>>;
+
+    fn save(&self, sessions: &StoredSessions) -> io::Result<()>;
+}
+
+/// Stores the sessions as JSON in a file.
+///
+/// The auth and refresh tokens are written in plain text. Anyone who can read the file can act as the stored
+/// users until the refresh tokens expire, so keep the file in a directory only the user can read.
+pub struct FileSessionStore {
+    path: PathBuf,
+}
+
+impl FileSessionStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileSessionStore {
+        FileSessionStore { path: path.into() }
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&self) -> io::Result<Option<StoredSessions>> {
+        let json = match fs::read_to_string(&self.path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        StoredSessions::deserialize_json(&json)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+    }
+
+    fn save(&self, sessions: &StoredSessions) -> io::Result<()> {
+        write_atomically(&self.path, sessions.serialize_json())
+    }
+}
+
+/// Keeps the sessions in memory.
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<Option<StoredSessions>>,
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load(&self) -> io::Result<Option<StoredSessions>> {
+        Ok(self.sessions.lock().unwrap().clone())
+    }
+
+    fn save(&self, sessions: &StoredSessions) -> io::Result<()> {
+        *self.sessions.lock().unwrap() = Some(sessions.clone());
+        Ok(())
+    }
+}
+
+struct Account<A: ClientAdapter> {
+    session: Session,
+    credentials: Option<Arc<dyn CredentialProvider<A>>>,
+    /// Only set once the socket is connected.
+    socket: Option<WebSocket<WebSocketAdapter>>,
+    connecting: Option<Connecting>,
+}
+
+struct Connecting {
+    socket: WebSocket<WebSocketAdapter>,
+    /// The calls waiting for the connect, woken by dropping the senders once it finishes or is abandoned.
+    waiters: Vec<oneshot::Sender<()>>,
+}
+
+struct State<A: ClientAdapter> {
+    accounts: HashMap<String, Account<A>>,
+    active: Option<String>,
+}
+
+/// Named sessions of several accounts with one active session.
+pub struct SessionManager<A: ClientAdapter> {
+    client: DefaultClient<A>,
+    store: Option<Box<dyn SessionStore>>,
+    state: Mutex<State<A>>,
+}
+
+impl<A> SessionManager<A>
+where
+    A: ClientAdapter + Clone + Send + Sync + 'static,
+{
+    pub fn new(client: &DefaultClient<A>) -> SessionManager<A> {
+        SessionManager {
+            client: client.clone(),
+            store: None,
+            state: Mutex::new(State {
+                accounts: HashMap::new(),
+                active: None,
+            }),
+        }
+    }
+
+    /// Persist the sessions in `store` whenever they change.
+    pub fn set_store<S: SessionStore + 'static>(&mut self, store: S) {
+        self.store = Some(Box::new(store));
+    }
+
+    /// Restore the sessions saved in the store. Sessions with the same name are replaced.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] without restoring any session if a stored session is invalid.
+    pub fn load(&self) -> io::Result<()> {
+        let stored = match self.store {
+            Some(ref store) => store.load()?,
+            None => None,
+        };
+        let stored = match stored {
+            Some(stored) => stored,
+            None => return Ok(()),
+        };
+
+        let sessions = stored
+            .sessions
+            .into_iter()
+            .map(|(name, data)| {
+                Session::from_data(data)
+                    .map(|session| (name, session))
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut replaced = vec![];
+        {
+            let mut state = self.state.lock().unwrap();
+            for (name, session) in sessions {
+                trace!("load: Restoring session {}", name);
+                let previous = state.accounts.remove(&name);
+                let credentials = previous
+                    .as_ref()
+                    .and_then(|account| account.credentials.clone());
+                replaced.extend(previous.map(|account| (name.clone(), account)));
+                state.accounts.insert(
+                    name,
+                    Account {
+                        session,
+                        credentials,
+                        socket: None,
+                        connecting: None,
+                    },
+                );
+            }
+            if let Some(active) = stored.active {
+                if state.accounts.contains_key(&active) {
+                    state.active = Some(active);
+                }
+            }
+        }
+        for (name, account) in replaced {
+            close_socket(&name, account);
+        }
+        Ok(())
+    }
+
+    /// Save the sessions to the store.
+    pub fn save(&self) -> io::Result<()> {
+        let store = match self.store {
+            Some(ref store) => store,
+            None => return Ok(()),
+        };
+
+        let stored = {
+            let state = self.state.lock().unwrap();
+            StoredSessions {
+                active: state.active.clone(),
+                sessions: state
+                    .accounts
+                    .iter()
+                    .map(|(name, account)| (name.clone(), account.session.to_data()))
+                    .collect(),
+            }
+        };
+        store.save(&stored)
+    }
+
+    /// Authenticate with `credentials` and add the session as `name`.
+    ///
+    /// The first session added becomes the active session.
+    pub async fn add<P: CredentialProvider<A> + 'static>(
+        &self,
+        name: &str,
+        credentials: P,
+    ) -> Result<Session, DefaultClientError<A>> {
+        let credentials: Arc<dyn CredentialProvider<A>> = Arc::new(credentials);
+        let session = credentials.authenticate(&self.client).await?;
+        self.insert_account(name, session.clone(), Some(credentials));
+        Ok(session)
+    }
+
+    /// Add an existing session as `name`. The session can't be authenticated again unless credentials are set.
+    pub fn insert(&self, name: &str, session: Session) {
+        self.insert_account(name, session, None);
+    }
+
+    /// Set the credentials used to authenticate the session `name` again, e.g. after [`SessionManager::load`].
+    ///
+    /// Returns false if there is no session with that name.
+    pub fn set_credentials<P: CredentialProvider<A> + 'static>(
+        &self,
+        name: &str,
+        credentials: P,
+    ) -> bool {
+        match self.state.lock().unwrap().accounts.get_mut(name) {
+            Some(account) => {
+                account.credentials = Some(Arc::new(credentials));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the session `name`. If it was the active session, no session is active afterwards.
+    pub fn remove(&self, name: &str) -> Option<Session> {
+        let account = {
+            let mut state = self.state.lock().unwrap();
+            if state.active.as_deref() == Some(name) {
+                state.active = None;
+            }
+            state.accounts.remove(name)
+        };
+        self.save_or_log();
+        account.map(|account| account.session)
+    }
+
+    /// Log out the session `name` and remove it.
+    ///
+    /// Invalidates the tokens on the server, closes the socket of the session and removes the session from
+    /// the store. The session is removed even if the server request fails.
+    pub async fn logout(&self, name: &str) -> Result<(), DefaultClientError<A>> {
+        let account = {
+            let mut state = self.state.lock().unwrap();
+            if state.active.as_deref() == Some(name) {
+                state.active = None;
+            }
+            state.accounts.remove(name)
+        };
+        let account = match account {
+            Some(account) => account,
+            None => {
+                return Err(DefaultClientError::ClientError(format!(
+                    "No session named {}",
+                    name
+                )))
+            }
+        };
+        self.save_or_log();
+
+        trace!("logout: Logging out session {}", name);
+        if let Some(socket) = account
+            .socket
+            .or(account.connecting.map(|connecting| connecting.socket))
+        {
+            if let Err(err) = socket.close().await {
+                error!(
+                    "logout: Failed to close socket of session {}: {}",
+                    name, err
+                );
+            }
+        }
+        self.client.session_logout(&account.session).await
+    }
+
+    pub fn get(&self, name: &str) -> Option<Session> {
+        self.state
+            .lock()
+            .unwrap()
+            .accounts
+            .get(name)
+            .map(|account| account.session.clone())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .accounts
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Make the session `name` the active session.
+    ///
+    /// Returns false if there is no session with that name.
+    pub fn set_active(&self, name: &str) -> bool {
+        {
+            let mut state = self.state.lock().unwrap();
+            if !state.accounts.contains_key(name) {
+                return false;
+            }
+            state.active = Some(name.to_owned());
+        }
+        self.save_or_log();
+        true
+    }
+
+    pub fn active_name(&self) -> Option<String> {
+        self.state.lock().unwrap().active.clone()
+    }
+
+    pub fn active(&self) -> Option<Session> {
+        let state = self.state.lock().unwrap();
+        let active = state.active.as_ref()?;
+        state
+            .accounts
+            .get(active)
+            .map(|account| account.session.clone())
+    }
+
+    /// Refresh the session `name` if it expires soon.
+    ///
+    /// Uses the refresh token while it is valid and authenticates again with the credentials of the
+    /// session otherwise.
+    pub async fn refresh(&self, name: &str) -> Result<(), DefaultClientError<A>> {
+        let (session, credentials) = match self.state.lock().unwrap().accounts.get(name) {
+            Some(account) => (account.session.clone(), account.credentials.clone()),
+            None => {
+                return Err(DefaultClientError::ClientError(format!(
+                    "No session named {}",
+                    name
+                )))
+            }
+        };
+
+        if !session.will_expire_soon() {
+            return Ok(());
+        }
+
+        if credentials.is_none()
+            && (session.get_refresh_token().is_none() || session.is_refresh_expired())
+        {
+            return Err(DefaultClientError::ClientError(format!(
+                "Session {} can't be refreshed and has no credentials",
+                name
+            )));
+        }
+
+        trace!("refresh: Refreshing session {}", name);
+        let result = self
+            .client
+            .refresh_session_with(&session, credentials.as_ref())
+            .await;
+        self.save_or_log();
+        result
+    }
+
+    /// Refresh every session that expires soon.
+    ///
+    /// A failure doesn't stop the other sessions from being refreshed. Returns the errors by session name.
+    pub async fn refresh_all(&self) -> Vec<(String, DefaultClientError<A>)> {
+        let mut errors = vec![];
+        for name in self.names() {
+            if let Err(err) = self.refresh(&name).await {
+                error!("refresh_all: Failed to refresh session {}: {}", name, err);
+                errors.push((name, err));
+            }
+        }
+        errors
+    }
+
+    /// The socket of the session `name`, connecting it on first use. Returns `None` if there is no such
+    /// session.
+    ///
+    /// The socket is only kept once it is connected. If connecting fails, the next call tries again. A call
+    /// while another call is connecting the socket waits for that connection, and connects itself if that
+    /// call is dropped before it finishes. Call [`SessionManager::tick_sockets`] regularly to receive the
+    /// messages of all sockets.
+    pub async fn socket(
+        &self,
+        name: &str,
+        appear_online: bool,
+        connect_timeout: i32,
+    ) -> Result<Option<WebSocket<WebSocketAdapter>>, WebSocketError<WebSocketAdapter>> {
+        let (session, socket) = loop {
+            let waiter = {
+                let mut state = self.state.lock().unwrap();
+                let account = match state.accounts.get_mut(name) {
+                    Some(account) => account,
+                    None => return Ok(None),
+                };
+                if let Some(ref socket) = account.socket {
+                    return Ok(Some(socket.clone()));
+                }
+                match account.connecting {
+                    Some(ref mut connecting) => {
+                        let (tx, rx) = oneshot::channel();
+                        connecting.waiters.push(tx);
+                        rx
+                    }
+                    None => {
+                        let socket = WebSocket::new_with_adapter();
+                        account.connecting = Some(Connecting {
+                            socket: socket.clone(),
+                            waiters: vec![],
+                        });
+                        break (account.session.clone(), socket);
+                    }
+                }
+            };
+            // The sender is dropped once the connect finishes or is abandoned, check the socket again either way
+            let _ = waiter.await;
+        };
+
+        let guard = ConnectingGuard {
+            state: &self.state,
+            name,
+            socket: &socket,
+            finished: false,
+        };
+        let result = socket
+            .connect(&session, appear_online, connect_timeout)
+            .await;
+        let stored = guard.finish(result.is_ok());
+        result?;
+
+        if !stored {
+            trace!(
+                "socket: Session {} was removed or replaced while connecting",
+                name
+            );
+            if let Err(err) = socket.close().await {
+                error!(
+                    "socket: Failed to close socket of session {}: {}",
+                    name, err
+                );
+            }
+            return Ok(None);
+        }
+        Ok(Some(socket))
+    }
+
+    /// Tick the sockets of all sessions, including sockets that are still connecting.
+    pub fn tick_sockets(&self) {
+        let sockets: Vec<_> = self
+            .state
+            .lock()
+            .unwrap()
+            .accounts
+            .values()
+            .flat_map(|account| {
+                let connecting = account
+                    .connecting
+                    .as_ref()
+                    .map(|connecting| &connecting.socket);
+                account.socket.iter().chain(connecting)
+            })
+            .cloned()
+            .collect();
+        for socket in sockets {
+            socket.tick();
+        }
+    }
+
+    fn insert_account(
+        &self,
+        name: &str,
+        session: Session,
+        credentials: Option<Arc<dyn CredentialProvider<A>>>,
+    ) {
+        let previous = {
+            let mut state = self.state.lock().unwrap();
+            let previous = state.accounts.insert(
+                name.to_owned(),
+                Account {
+                    session,
+                    credentials,
+                    socket: None,
+                    connecting: None,
+                },
+            );
+            if state.active.is_none() {
+                state.active = Some(name.to_owned());
+            }
+            previous
+        };
+        if let Some(previous) = previous {
+            close_socket(name, previous);
+        }
+        self.save_or_log();
+    }
+
+    fn save_or_log(&self) {
+        if let Err(err) = self.save() {
+            error!("Failed to save sessions: {}", err);
+        }
+    }
+}
+
+/// Close the socket of an account that was replaced.
+fn close_socket<A: ClientAdapter>(name: &str, account: Account<A>) {
+    if let Some(socket) = account
+        .socket
+        .or(account.connecting.map(|connecting| connecting.socket))
+    {
+        trace!("close_socket: Closing socket of replaced session {}", name);
+        // Closing doesn't wait for the server, the future is ready right away
+        if let Some(Err(err)) = socket.close().now_or_never() {
+            error!(
+                "close_socket: Failed to close socket of session {}: {}",
+                name, err
+            );
+        }
+    }
+}
+
+/// Clears the connecting socket of an account when the connect finishes, or when the future of
+/// [`SessionManager::socket`] is dropped while connecting, so the waiting calls can connect again.
+struct ConnectingGuard<'a, A: ClientAdapter> {
+    state: &'a Mutex<State<A>>,
+    name: &'a str,
+    socket: &'a WebSocket<WebSocketAdapter>,
+    finished: bool,
+}
+
+impl<'a, A: ClientAdapter> ConnectingGuard<'a, A> {
+    /// Returns false if the session was removed or replaced while connecting. The socket is kept if it
+    /// connected.
+    fn finish(mut self, connected: bool) -> bool {
+        self.finished = true;
+        self.clear(connected)
+    }
+
+    fn clear(&self, connected: bool) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let account = match state.accounts.get_mut(self.name) {
+            Some(account) => account,
+            None => return false,
+        };
+        let is_connecting = account.connecting.as_ref().map_or(false, |connecting| {
+            connecting.socket.is_same_socket(self.socket)
+        });
+        if !is_connecting {
+            return false;
+        }
+        account.connecting = None;
+        if connected {
+            account.socket = Some(self.socket.clone());
+        }
+        true
+    }
+}
+
+impl<'a, A: ClientAdapter> Drop for ConnectingGuard<'a, A> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        trace!("socket: Connecting session {} was abandoned", self.name);
+        self.clear(false);
+        // Closing doesn't wait for the server, the future is ready right away
+        if let Some(Err(err)) = self.socket.close().now_or_never() {
+            error!(
+                "socket: Failed to close abandoned socket of session {}: {}",
+                self.name, err
+            );
+        }
+    }
+}
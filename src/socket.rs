@@ -13,10 +13,14 @@
 // limitations under the License.
 
 use crate::api::{ApiChannelMessage, ApiNotification, ApiNotificationList, ApiRpc};
+use crate::event_stream::{EventStream, DEFAULT_EVENT_STREAM_CAPACITY};
 use crate::matchmaker::Matchmaker;
+use crate::rpc::RpcId;
 use crate::session::Session;
+use crate::socket_adapter::CloseEvent;
+use crate::subscription::Subscription;
 use async_trait::async_trait;
-use nanoserde::{DeJson, DeJsonErr, DeJsonState, SerJson};
+use nanoserde::{DeJson, DeJsonErr, DeJsonState, SerJson, SerJsonState};
 use std::collections::HashMap;
 use std::error;
 use std::str::Chars;
@@ -25,6 +29,12 @@ use std::str::Chars;
 #[nserde(transparent)]
 pub struct Timestamp(String);
 
+impl From<String> for Timestamp {
+    fn from(timestamp: String) -> Self {
+        Timestamp(timestamp)
+    }
+}
+
 #[derive(DeJson, SerJson, Debug, Clone, Default)]
 pub struct Channel {
     pub id: String,
@@ -366,11 +376,47 @@ impl DeJson for PartyData {
     }
 }
 
-#[derive(DeJson, SerJson, Debug, Clone, Default)]
+/// The raw bytes sent to a party, base64 encoded in the JSON envelope.
+#[derive(Debug, Clone, Default)]
 pub struct PartyDataSend {
     pub party_id: String,
     pub op_code: i64,
-    pub data: String,
+    pub data: Vec<u8>,
+}
+
+// The JSON envelope carries the data of a party as base64
+#[derive(DeJson, SerJson)]
+struct PartyDataSendProxy {
+    party_id: String,
+    op_code: i64,
+    data: String,
+}
+
+impl SerJson for PartyDataSend {
+    fn ser_json(&self, d: usize, s: &mut SerJsonState) {
+        PartyDataSendProxy {
+            party_id: self.party_id.clone(),
+            op_code: self.op_code,
+            data: base64::encode(&self.data),
+        }
+        .ser_json(d, s)
+    }
+}
+
+impl DeJson for PartyDataSend {
+    fn de_json(state: &mut DeJsonState, input: &mut Chars) -> Result<Self, DeJsonErr> {
+        let proxy: PartyDataSendProxy = DeJson::de_json(state, input)?;
+        let data = base64::decode(proxy.data).map_err(|err| DeJsonErr {
+            msg: err.to_string(),
+            col: 0,
+            line: 0,
+        })?;
+        Ok(PartyDataSend {
+            party_id: proxy.party_id,
+            op_code: proxy.op_code,
+            data,
+        })
+    }
 }
 
 #[derive(DeJson, SerJson, Debug, Clone, Default)]
@@ -512,6 +558,8 @@ pub struct WebSocketMessageEnvelope {
     pub party_data: Option<PartyData>,
     pub party_data_send: Option<PartyDataSend>,
     pub party_presence_event: Option<PartyPresenceEvent>,
+    pub ping: Option<Ping>,
+    pub pong: Option<Pong>,
 }
 
 #[async_trait]
@@ -521,72 +569,271 @@ pub trait Socket {
     // It would make sense to have a future here
     fn on_closed<T>(&mut self, callback: T)
     where
-        T: Fn() + Send + 'static;
+        T: Fn(CloseEvent) + Send + 'static;
+
+    fn subscribe_closed<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(CloseEvent) + Send + 'static;
+
+    /// Subscribe to the event that is dispatched once the socket won't receive events anymore: it was closed
+    /// by the client, or the connection was lost and is not restored by an automatic reconnect.
+    ///
+    /// Defaults to [`Socket::subscribe_closed`] for sockets that don't reconnect.
+    fn subscribe_ended<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(CloseEvent) + Send + 'static,
+    {
+        self.subscribe_closed(callback)
+    }
 
     fn on_connected<T>(&mut self, callback: T)
     where
         T: Fn() + Send + Send + 'static;
 
+    fn subscribe_connected<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn() + Send + 'static;
+
     fn on_received_channel_message<T>(&mut self, callback: T)
     where
         T: Fn(ApiChannelMessage) + Send + Send + 'static;
 
+    fn subscribe_channel_message<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(ApiChannelMessage) + Send + 'static;
+
     fn on_received_channel_presence<T>(&mut self, callback: T)
     where
         T: Fn(ChannelPresenceEvent) + Send + Send + 'static;
 
+    fn subscribe_channel_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(ChannelPresenceEvent) + Send + 'static;
+
     fn on_received_error<T>(&mut self, callback: T)
     where
         T: Fn(Error) + Send + Send + 'static;
 
+    fn subscribe_error<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(Error) + Send + 'static;
+
     fn on_received_matchmaker_matched<T>(&mut self, callback: T)
     where
         T: Fn(MatchmakerMatched) + Send + Send + 'static;
 
+    fn subscribe_matchmaker_matched<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(MatchmakerMatched) + Send + 'static;
+
     fn on_received_match_state<T>(&mut self, callback: T)
     where
         T: Fn(MatchData) + Send + Send + 'static;
 
+    fn subscribe_match_state<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(MatchData) + Send + 'static;
+
     fn on_received_match_presence<T>(&mut self, callback: T)
     where
         T: Fn(MatchPresenceEvent) + Send + 'static;
 
+    fn subscribe_match_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(MatchPresenceEvent) + Send + 'static;
+
     fn on_received_notification<T>(&mut self, callback: T)
     where
         T: Fn(ApiNotification) + Send + 'static;
 
+    fn subscribe_notification<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(ApiNotification) + Send + 'static;
+
     fn on_received_party_close<T>(&mut self, callback: T)
     where
         T: Fn(PartyClose) + Send + 'static;
 
+    fn subscribe_party_close<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyClose) + Send + 'static;
+
     fn on_received_party_data<T>(&mut self, callback: T)
     where
         T: Fn(PartyData) + Send + 'static;
 
+    fn subscribe_party_data<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyData) + Send + 'static;
+
     fn on_received_party_join_request<T>(&mut self, callback: T)
     where
         T: Fn(PartyJoinRequest) + Send + 'static;
 
+    fn subscribe_party_join_request<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyJoinRequest) + Send + 'static;
+
     fn on_received_party_leader<T>(&mut self, callback: T)
     where
         T: Fn(PartyLeader) + Send + 'static;
 
+    fn subscribe_party_leader<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyLeader) + Send + 'static;
+
     fn on_received_party_presence<T>(&mut self, callback: T)
     where
         T: Fn(PartyPresenceEvent) + Send + 'static;
 
+    fn subscribe_party_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyPresenceEvent) + Send + 'static;
+
     fn on_received_status_presence<T>(&mut self, callback: T)
     where
         T: Fn(StatusPresenceEvent) + Send + 'static;
 
+    fn subscribe_status_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(StatusPresenceEvent) + Send + 'static;
+
     fn on_received_stream_presence<T>(&mut self, callback: T)
     where
         T: Fn(StreamPresenceEvent) + Send + 'static;
 
+    fn subscribe_stream_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(StreamPresenceEvent) + Send + 'static;
+
     fn on_received_stream_state<T>(&mut self, callback: T)
     where
         T: Fn(StreamData) + Send + 'static;
 
+    fn subscribe_stream_state<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(StreamData) + Send + 'static;
+
+    /// A stream of received chat messages, see [`event_stream`](crate::event_stream).
+    fn channel_messages(&self) -> EventStream<ApiChannelMessage> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_channel_message(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of joins and leaves in chat channels, see [`event_stream`](crate::event_stream).
+    fn channel_presences(&self) -> EventStream<ChannelPresenceEvent> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_channel_presence(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of errors sent by the server, see [`event_stream`](crate::event_stream).
+    fn errors(&self) -> EventStream<Error> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_error(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of matchmaker matches, see [`event_stream`](crate::event_stream).
+    fn matchmaker_matches(&self) -> EventStream<MatchmakerMatched> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_matchmaker_matched(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of received match state, see [`event_stream`](crate::event_stream).
+    fn match_state(&self) -> EventStream<MatchData> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_match_state(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of joins and leaves in matches, see [`event_stream`](crate::event_stream).
+    fn match_presences(&self) -> EventStream<MatchPresenceEvent> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_match_presence(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of received notifications, see [`event_stream`](crate::event_stream).
+    fn notifications(&self) -> EventStream<ApiNotification> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_notification(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of closed parties, see [`event_stream`](crate::event_stream).
+    fn party_closes(&self) -> EventStream<PartyClose> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_party_close(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of received party data, see [`event_stream`](crate::event_stream).
+    fn party_data(&self) -> EventStream<PartyData> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_party_data(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of party join requests, see [`event_stream`](crate::event_stream).
+    fn party_join_requests(&self) -> EventStream<PartyJoinRequest> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_party_join_request(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of party leader changes, see [`event_stream`](crate::event_stream).
+    fn party_leaders(&self) -> EventStream<PartyLeader> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_party_leader(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of joins and leaves in parties, see [`event_stream`](crate::event_stream).
+    fn party_presences(&self) -> EventStream<PartyPresenceEvent> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_party_presence(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of online status updates of followed users, see [`event_stream`](crate::event_stream).
+    fn status_presences(&self) -> EventStream<StatusPresenceEvent> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_status_presence(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of joins and leaves in realtime streams, see [`event_stream`](crate::event_stream).
+    fn stream_presences(&self) -> EventStream<StreamPresenceEvent> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_stream_presence(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
+    /// A stream of received realtime stream data, see [`event_stream`](crate::event_stream).
+    fn stream_state(&self) -> EventStream<StreamData> {
+        EventStream::new(DEFAULT_EVENT_STREAM_CAPACITY, |callback| {
+            self.subscribe_stream_state(callback)
+        })
+        .until_closed(|callback| self.subscribe_ended(callback))
+    }
+
     async fn accept_party_member(
         &self,
         party_id: &str,
@@ -621,7 +868,12 @@ pub trait Socket {
 
     async fn close(&self) -> Result<(), Self::Error>;
 
-    async fn connect(&self, session: &Session, appear_online: bool, connect_timeout: i32);
+    async fn connect(
+        &self,
+        session: &Session,
+        appear_online: bool,
+        connect_timeout: i32,
+    ) -> Result<(), Self::Error>;
 
     async fn create_match(&self) -> Result<Match, Self::Error>;
 
@@ -692,6 +944,17 @@ pub trait Socket {
 
     async fn rpc_bytes(&self, func_id: &str, payload: &[u8]) -> Result<ApiRpc, Self::Error>;
 
+    /// Execute a function declared with [`RpcId`] on the server. See [`crate::rpc`] for an example.
+    async fn rpc_call<R: RpcId>(&self, request: &R::Request) -> Result<R::Response, Self::Error> {
+        self.rpc_typed(R::ID, request).await
+    }
+
+    async fn rpc_typed<Req: SerJson + Sync, Resp: DeJson + Send>(
+        &self,
+        func_id: &str,
+        request: &Req,
+    ) -> Result<Resp, Self::Error>;
+
     async fn send_match_state(
         &self,
         match_id: &str,
@@ -12,30 +12,139 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::envelope_codec::Frame;
 use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// The state of the connection of a [`SocketAdapter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+    /// A close was requested and the adapter is waiting for the server to acknowledge it.
+    Closing,
+}
+
+/// Why a connection was closed. Passed to the `on_closed` callbacks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseEvent {
+    /// The WebSocket close code, see [RFC 6455](https://tools.ietf.org/html/rfc6455#section-7.4.1).
+    pub code: u16,
+    pub reason: String,
+}
+
+impl CloseEvent {
+    /// The connection was closed on purpose.
+    pub const NORMAL: u16 = 1000;
+    /// The connection was lost or could not be established without a close frame.
+    pub const ABNORMAL: u16 = 1006;
+
+    pub fn new(code: u16, reason: &str) -> Self {
+        CloseEvent {
+            code,
+            reason: reason.to_owned(),
+        }
+    }
+
+    pub fn is_normal(&self) -> bool {
+        self.code == CloseEvent::NORMAL
+    }
+}
+
+/// The error of [`SocketAdapter::send_binary`].
+#[derive(Debug)]
+pub enum SendBinaryError<E> {
+    /// The adapter doesn't support binary frames.
+    Unsupported,
+    Adapter(E),
+}
+
+impl<E: Display> Display for SendBinaryError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendBinaryError::Unsupported => write!(f, "Binary frames are not supported"),
+            SendBinaryError::Adapter(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl<E: Error> Error for SendBinaryError<E> {}
+
+/// Receives the frames on the network thread of an adapter, see [`SocketAdapter::set_network_receiver`].
+pub type NetworkReceiver = Arc<dyn Fn(Frame) + Send + Sync + 'static>;
 
 pub trait SocketAdapter {
-    type Error: Error;
+    type Error: Error + Send + 'static;
     fn on_connected<T>(&mut self, callback: T)
     where
         T: Fn() + Send + 'static;
     fn on_closed<T>(&mut self, callback: T)
     where
-        T: Fn() + Send + 'static;
+        T: Fn(CloseEvent) + Send + 'static;
 
     // TODO: correct error type
     fn on_received<T>(&mut self, callback: T)
     where
         T: Fn(Result<String, Self::Error>) + Send + 'static;
 
-    fn is_connected(&self) -> bool;
-    fn is_connecting(&self) -> bool;
+    /// Called with the binary frames, e.g. when the socket uses the protobuf wire format.
+    ///
+    /// Adapters that don't support binary frames ignore the callback.
+    fn on_received_binary<T>(&mut self, _callback: T)
+    where
+        T: Fn(Vec<u8>) + Send + 'static,
+    {
+    }
+
+    /// Hand the received frames to `receiver` on the network thread as soon as they arrive, instead of to
+    /// `on_received` and `on_received_binary` in [`tick`](SocketAdapter::tick). `None` delivers them in `tick`
+    /// again. The connection events are still reported by `tick`.
+    ///
+    /// Returns false if the adapter has no network thread, the frames are delivered in `tick` then.
+    fn set_network_receiver(&mut self, _receiver: Option<NetworkReceiver>) -> bool {
+        false
+    }
+
+    /// Called instead of `on_connected` if the connection could not be established, followed by `on_closed`.
+    fn on_connect_failed<T>(&mut self, callback: T)
+    where
+        T: Fn(Self::Error) + Send + 'static;
+
+    /// The state as of the last [`tick`](SocketAdapter::tick).
+    fn connection_state(&self) -> ConnectionState;
+
+    fn is_connected(&self) -> bool {
+        self.connection_state() == ConnectionState::Connected
+    }
+
+    fn is_connecting(&self) -> bool {
+        self.connection_state() == ConnectionState::Connecting
+    }
 
     fn close(&mut self);
 
+    /// Drop the connection without the closing handshake, e.g. because the server stopped responding.
+    /// `on_closed` is called with [`CloseEvent::ABNORMAL`] and `reason`.
+    ///
+    /// Defaults to [`close`](SocketAdapter::close) for adapters that can't drop the connection.
+    fn abort(&mut self, _reason: &str) {
+        self.close();
+    }
+
     fn connect(&mut self, addr: &str, timeout: i32);
 
     fn send(&self, data: &str, reliable: bool) -> Result<(), Self::Error>;
 
+    /// Send a binary frame. Fails with [`SendBinaryError::Unsupported`] unless the adapter implements it.
+    fn send_binary(
+        &self,
+        _data: &[u8],
+        _reliable: bool,
+    ) -> Result<(), SendBinaryError<Self::Error>> {
+        Err(SendBinaryError::Unsupported)
+    }
+
     fn tick(&self);
 }
@@ -0,0 +1,194 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Socket event subscriptions.
+//!
+//! Every socket event can have many subscribers. The `subscribe_*` methods of the [`Socket`](crate::Socket)
+//! return a [`Subscription`] that removes the callback when it is dropped. The `on_*` methods set the single
+//! handler of an event, which is called before the subscribers. Setting it again replaces the previous handler.
+//!
+//! The callbacks are called by the [`Dispatcher`](crate::dispatcher::Dispatcher) of the socket without any lock
+//! of the socket held. They can send messages, subscribe and unsubscribe, close the socket or tick it again.
+//!
+//! # Example
+//! ```
+//! # #![feature(async_closure)]
+//! # use nakama_rs::test_helpers::*;
+//! # run_in_socket_example(async move |client, session, socket| {
+//! let subscription = socket.subscribe_channel_message(|message| {
+//!     println!("Chat: {}", message.content);
+//! });
+//! // ...
+//! drop(subscription);
+//! # Ok(())
+//! # });
+//! ```
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Unsubscribes the callback when dropped.
+#[must_use = "the callback is unsubscribed when the subscription is dropped"]
+pub struct Subscription {
+    unsubscribe: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl Subscription {
+    pub(crate) fn new<F: FnOnce() + Send + 'static>(unsubscribe: F) -> Self {
+        Subscription {
+            unsubscribe: Some(Box::new(unsubscribe)),
+        }
+    }
+
+    /// Keep the callback subscribed for the lifetime of the socket.
+    pub fn detach(mut self) {
+        self.unsubscribe = None;
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(unsubscribe) = self.unsubscribe.take() {
+            unsubscribe();
+        }
+    }
+}
+
+// Each callback has its own mutex so that it can be called without holding the lock of the socket state
+type Callback<T> = Arc<Mutex<Box<dyn Fn(T) + Send + 'static>>>;
+
+/// The handler and the callbacks subscribed to one event.
+pub(crate) struct Subscribers<T> {
+    handler: Option<Callback<T>>,
+    next_id: u64,
+    callbacks: Vec<(u64, Callback<T>)>,
+}
+
+/// A snapshot of the subscribers of an event.
+pub(crate) struct Callbacks<T>(Vec<Callback<T>>);
+
+impl<T: Clone> Callbacks<T> {
+    /// Call every subscriber in the order they subscribed. A subscriber that panicked before is called again.
+    pub(crate) fn call(&self, value: T) {
+        if let Some((last, others)) = self.0.split_last() {
+            for callback in others {
+                (callback.lock().unwrap_or_else(PoisonError::into_inner))(value.clone());
+            }
+            (last.lock().unwrap_or_else(PoisonError::into_inner))(value);
+        }
+    }
+}
+
+impl<T> Default for Subscribers<T> {
+    fn default() -> Self {
+        Subscribers {
+            handler: None,
+            next_id: 0,
+            callbacks: vec![],
+        }
+    }
+}
+
+impl<T: Clone> Subscribers<T> {
+    /// Replace the handler set by the `on_*` method of the event.
+    pub(crate) fn set_handler(&mut self, callback: Box<dyn Fn(T) + Send + 'static>) {
+        self.handler = Some(Arc::new(Mutex::new(callback)));
+    }
+
+    pub(crate) fn add(&mut self, callback: Box<dyn Fn(T) + Send + 'static>) -> u64 {
+        self.next_id += 1;
+        self.callbacks
+            .push((self.next_id, Arc::new(Mutex::new(callback))));
+        self.next_id
+    }
+
+    pub(crate) fn remove(&mut self, id: u64) {
+        self.callbacks.retain(|(callback_id, _)| *callback_id != id);
+    }
+
+    /// The current handler and subscribers. Call them after releasing the lock of the socket state, the
+    /// callbacks may use the socket.
+    pub(crate) fn callbacks(&self) -> Callbacks<T> {
+        Callbacks(
+            self.handler
+                .iter()
+                .chain(self.callbacks.iter().map(|(_, callback)| callback))
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dispatch_to_all_subscribers() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let mut subscribers = Subscribers::default();
+        let first = subscribers.add(Box::new({
+            let received = received.clone();
+            move |value: i32| received.lock().unwrap().push(("first", value))
+        }));
+        subscribers.add(Box::new({
+            let received = received.clone();
+            move |value: i32| received.lock().unwrap().push(("second", value))
+        }));
+
+        subscribers.callbacks().call(1);
+        subscribers.remove(first);
+        subscribers.callbacks().call(2);
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![("first", 1), ("second", 1), ("second", 2)]
+        );
+    }
+
+    #[test]
+    fn test_replace_handler() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let mut subscribers = Subscribers::default();
+        subscribers.add(Box::new({
+            let received = received.clone();
+            move |value: i32| received.lock().unwrap().push(("subscriber", value))
+        }));
+        subscribers.set_handler(Box::new({
+            let received = received.clone();
+            move |value: i32| received.lock().unwrap().push(("first", value))
+        }));
+        subscribers.set_handler(Box::new({
+            let received = received.clone();
+            move |value: i32| received.lock().unwrap().push(("second", value))
+        }));
+
+        subscribers.callbacks().call(1);
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![("second", 1), ("subscriber", 1)]
+        );
+    }
+
+    #[test]
+    fn test_drop_subscription() {
+        let unsubscribed = Arc::new(Mutex::new(false));
+        let subscription = Subscription::new({
+            let unsubscribed = unsubscribed.clone();
+            move || *unsubscribed.lock().unwrap() = true
+        });
+        assert!(!*unsubscribed.lock().unwrap());
+        drop(subscription);
+        assert!(*unsubscribed.lock().unwrap());
+    }
+}
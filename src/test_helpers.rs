@@ -14,19 +14,32 @@
 
 use std::collections::HashMap;
 
-use crate::api::{ApiAccount, ApiGroup};
+use crate::api::{ApiAccount, ApiGroup, Authentication, RestRequest};
 pub use crate::client::Client;
+use crate::client_adapter::ClientAdapter;
 use crate::default_client::{DefaultClient, DefaultClientError};
 use crate::http_adapter::RestHttpAdapter;
 use crate::session::Session;
 pub use crate::socket::Socket;
+use crate::socket_adapter::{
+    CloseEvent, ConnectionState, NetworkReceiver, SendBinaryError, SocketAdapter,
+};
 use crate::web_socket::WebSocket;
-use crate::web_socket_adapter::WebSocketAdapter;
+use crate::web_socket_adapter::{WebSocketAdapter, WebSocketAdapterError};
+use async_trait::async_trait;
 use core::time::Duration;
 use futures::executor::block_on;
+use nanoserde::DeJson;
+use std::cell::RefCell;
+use std::fmt;
 use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::{sleep, spawn};
 
+/// A session token that expires in 2100, for adapters that don't verify it.
+pub const AUTH_TOKEN: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJleHAiOjQxMDI0NDQ4MDAsInVpZCI6ImZha2VfdXNlciIsInVzbiI6ImZha2UiLCJ0aWQiOiJ0dCJ9.signature";
+
 pub fn run_in_example<
     T,
     F: Future<Output = Result<T, DefaultClientError<RestHttpAdapter>>>,
@@ -148,13 +161,19 @@ pub async fn sockets_with_users(
     let account1 = client.get_account(&session).await.unwrap();
     let account2 = client.get_account(&session2).await.unwrap();
 
-    socket.connect(&session, true, -1).await;
-    socket2.connect(&session2, true, -1).await;
+    socket
+        .connect(&session, true, -1)
+        .await
+        .expect("Failed to connect");
+    socket2
+        .connect(&session2, true, -1)
+        .await
+        .expect("Failed to connect");
 
     (socket, socket2, account1, account2)
 }
 
-pub fn tick_socket(socket: &WebSocket<WebSocketAdapter>) {
+pub fn tick_socket<A: SocketAdapter + Send + 'static>(socket: &WebSocket<A>) {
     spawn({
         let socket = socket.clone();
         move || loop {
@@ -163,3 +182,151 @@ pub fn tick_socket(socket: &WebSocket<WebSocketAdapter>) {
         }
     });
 }
+
+#[derive(Debug)]
+pub struct RecordedError;
+
+impl fmt::Display for RecordedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RecordedError")
+    }
+}
+
+impl std::error::Error for RecordedError {}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub authentication: Authentication,
+    pub urlpath: String,
+    pub query_params: String,
+}
+
+/// Records every request and fails it, without a server.
+#[derive(Clone, Default)]
+pub struct RecordingAdapter {
+    pub requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+#[async_trait]
+impl ClientAdapter for RecordingAdapter {
+    type Error = RecordedError;
+
+    async fn send<T: DeJson + Send>(&self, request: RestRequest<T>) -> Result<T, Self::Error> {
+        self.requests.lock().unwrap().push(RecordedRequest {
+            authentication: request.authentication,
+            urlpath: request.urlpath,
+            query_params: request.query_params,
+        });
+        Err(RecordedError)
+    }
+}
+
+/// A [`WebSocketAdapter`] that can connect to another local port and lose its connection on demand.
+pub struct TestSocketAdapter {
+    inner: RefCell<WebSocketAdapter>,
+    port: Option<u16>,
+    drop_connection: Arc<AtomicBool>,
+}
+
+impl TestSocketAdapter {
+    pub fn new() -> TestSocketAdapter {
+        TestSocketAdapter {
+            inner: RefCell::new(WebSocketAdapter::new()),
+            port: None,
+            drop_connection: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Connects to `port` instead of the server, e.g. to a local stand-in for the realtime API.
+    pub fn with_port(port: u16) -> TestSocketAdapter {
+        TestSocketAdapter {
+            port: Some(port),
+            ..TestSocketAdapter::new()
+        }
+    }
+
+    /// Setting the flag closes the connection underneath the socket on the next tick, like a connection loss.
+    pub fn drop_connection_flag(&self) -> Arc<AtomicBool> {
+        self.drop_connection.clone()
+    }
+}
+
+impl SocketAdapter for TestSocketAdapter {
+    type Error = WebSocketAdapterError;
+
+    fn on_connected<T>(&mut self, callback: T)
+    where
+        T: Fn() + Send + 'static,
+    {
+        self.inner.get_mut().on_connected(callback)
+    }
+
+    fn on_closed<T>(&mut self, callback: T)
+    where
+        T: Fn(CloseEvent) + Send + 'static,
+    {
+        self.inner.get_mut().on_closed(callback)
+    }
+
+    fn on_received<T>(&mut self, callback: T)
+    where
+        T: Fn(Result<String, Self::Error>) + Send + 'static,
+    {
+        self.inner.get_mut().on_received(callback)
+    }
+
+    fn on_received_binary<T>(&mut self, callback: T)
+    where
+        T: Fn(Vec<u8>) + Send + 'static,
+    {
+        self.inner.get_mut().on_received_binary(callback)
+    }
+
+    fn on_connect_failed<T>(&mut self, callback: T)
+    where
+        T: Fn(Self::Error) + Send + 'static,
+    {
+        self.inner.get_mut().on_connect_failed(callback)
+    }
+
+    fn set_network_receiver(&mut self, receiver: Option<NetworkReceiver>) -> bool {
+        self.inner.get_mut().set_network_receiver(receiver)
+    }
+
+    fn connection_state(&self) -> ConnectionState {
+        self.inner.borrow().connection_state()
+    }
+
+    fn close(&mut self) {
+        self.inner.get_mut().close()
+    }
+
+    fn abort(&mut self, reason: &str) {
+        self.inner.get_mut().abort(reason)
+    }
+
+    fn connect(&mut self, addr: &str, timeout: i32) {
+        match self.port {
+            Some(port) => {
+                let addr = addr.replace("127.0.0.1:7350", &format!("127.0.0.1:{}", port));
+                self.inner.get_mut().connect(&addr, timeout)
+            }
+            None => self.inner.get_mut().connect(addr, timeout),
+        }
+    }
+
+    fn send(&self, data: &str, reliable: bool) -> Result<(), Self::Error> {
+        self.inner.borrow().send(data, reliable)
+    }
+
+    fn send_binary(&self, data: &[u8], reliable: bool) -> Result<(), SendBinaryError<Self::Error>> {
+        self.inner.borrow().send_binary(data, reliable)
+    }
+
+    fn tick(&self) {
+        if self.drop_connection.swap(false, Ordering::SeqCst) {
+            self.inner.borrow_mut().close();
+        }
+        self.inner.borrow().tick()
+    }
+}
@@ -21,21 +21,39 @@ use crate::socket::{
     MatchmakerMatched, MatchmakerRemove, MatchmakerTicket, Party, PartyAccept, PartyClose,
     PartyCreate, PartyData, PartyDataSend, PartyJoin, PartyJoinRequest, PartyJoinRequestList,
     PartyLeader, PartyLeave, PartyMatchmakerAdd, PartyMatchmakerRemove, PartyMatchmakerTicket,
-    PartyPresenceEvent, PartyPromote, PartyRemove, Socket, Status, StatusFollow,
+    PartyPresenceEvent, PartyPromote, PartyRemove, Ping, Socket, Status, StatusFollow,
     StatusPresenceEvent, StatusUnfollow, StatusUpdate, StreamData, StreamPresenceEvent,
-    UserPresence, WebSocketMessageEnvelope, WebSocketMessageEnvelopeHeader,
+    UserPresence, WebSocketMessageEnvelope,
 };
-use crate::socket_adapter::SocketAdapter;
+use crate::socket_adapter::{
+    CloseEvent, ConnectionState, NetworkReceiver, SendBinaryError, SocketAdapter,
+};
+use crate::subscription::{Subscribers, Subscription};
 use async_trait::async_trait;
+use futures::task::noop_waker_ref;
 use log::{error, trace};
 use nanoserde::{DeJson, DeJsonErr, SerJson};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::error;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
+use crate::client::Client;
 use crate::default_client::str_slice_to_owned;
+use crate::dispatcher::{Dispatcher, Task, TickDispatcher};
+use crate::envelope_codec::{
+    DecodeError, EnvelopeCodec, Frame, JsonCodec, Payload, ProtobufCodec, ServerMessage,
+};
+use crate::heartbeat::{Heartbeat, HeartbeatAction, HeartbeatConfig};
 use crate::matchmaker::Matchmaker;
+use crate::reconnect::{Membership, Memberships, ReconnectPolicy, ReconnectReport, RestoreFailure};
+use crate::rpc::{decode_response, Base64Codec, PayloadCodec, PayloadDecodeError, RpcDecodeError};
 use crate::web_socket_adapter::WebSocketAdapter;
+use crate::wire_format::WireFormat;
 use oneshot;
 use oneshot::RecvError;
 use std::fmt::{Debug, Display, Formatter};
@@ -46,6 +64,11 @@ pub enum WebSocketError<A: SocketAdapter> {
     RecvError(RecvError),
     ApiError(Error),
     DeJsonError(DeJsonErr),
+    RpcDecodeError(RpcDecodeError),
+    /// The socket was closed before the response arrived.
+    Disconnected(CloseEvent),
+    /// The envelope codec encodes binary frames, which the adapter doesn't support.
+    BinaryNotSupported,
 }
 
 impl<A: SocketAdapter> Debug for WebSocketError<A> {
@@ -56,6 +79,11 @@ impl<A: SocketAdapter> Debug for WebSocketError<A> {
             WebSocketError::RecvError(err) => std::fmt::Debug::fmt(err, f),
             WebSocketError::ApiError(err) => std::fmt::Debug::fmt(err, f),
             WebSocketError::DeJsonError(err) => std::fmt::Debug::fmt(err, f),
+            WebSocketError::RpcDecodeError(err) => std::fmt::Display::fmt(err, f),
+            WebSocketError::Disconnected(event) => std::fmt::Debug::fmt(event, f),
+            WebSocketError::BinaryNotSupported => {
+                std::fmt::Debug::fmt("Binary frames are not supported", f)
+            }
         }
     }
 }
@@ -68,35 +96,87 @@ impl<A: SocketAdapter> Display for WebSocketError<A> {
 
 impl<A: SocketAdapter> error::Error for WebSocketError<A> {}
 
+enum ConnectError<E> {
+    AdapterError(E),
+    Disconnected(CloseEvent),
+}
+
+type ConnectSender<E> = oneshot::Sender<Result<(), ConnectError<E>>>;
+
+enum ResponseError {
+    DeJsonError(DeJsonErr),
+    Disconnected(CloseEvent),
+}
+
+// Events received from the adapter, waiting to be dispatched
+enum Incoming {
+    Frame(Frame),
+    Connected,
+    Closed(CloseEvent),
+}
+
 #[derive(Default)]
 struct SharedState {
     cid: i64,
-    connected: Vec<oneshot::Sender<()>>,
-    responses: HashMap<i64, oneshot::Sender<Result<WebSocketMessageEnvelope, DeJsonErr>>>,
+    incoming: VecDeque<Incoming>,
+    // Set while the incoming events are dispatched, a tick from within a callback doesn't dispatch
+    dispatching: bool,
+    responses: HashMap<i64, oneshot::Sender<Result<WebSocketMessageEnvelope, ResponseError>>>,
     timeouts: HashMap<i64, i64>,
-    on_closed: Option<Box<dyn Fn() + Send + 'static>>,
-    on_connected: Option<Box<dyn Fn() + Send + 'static>>,
-    on_received_channel_message: Option<Box<dyn Fn(ApiChannelMessage) + Send + 'static>>,
-    on_received_channel_presence: Option<Box<dyn Fn(ChannelPresenceEvent) + Send + 'static>>,
-    on_received_error: Option<Box<dyn Fn(Error) + Send + 'static>>,
-    on_received_matchmaker_matched: Option<Box<dyn Fn(MatchmakerMatched) + Send + 'static>>,
-    on_received_match_state: Option<Box<dyn Fn(MatchData) + Send + 'static>>,
-    on_received_match_presence: Option<Box<dyn Fn(MatchPresenceEvent) + Send + 'static>>,
-    on_received_notification: Option<Box<dyn Fn(ApiNotification) + Send + 'static>>,
-    on_received_party_close: Option<Box<dyn Fn(PartyClose) + Send + 'static>>,
-    on_received_party_data: Option<Box<dyn Fn(PartyData) + Send + 'static>>,
-    on_received_party_join_request: Option<Box<dyn Fn(PartyJoinRequest) + Send + 'static>>,
-    on_received_party_leader: Option<Box<dyn Fn(PartyLeader) + Send + 'static>>,
-    on_received_party_presence: Option<Box<dyn Fn(PartyPresenceEvent) + Send + 'static>>,
-    on_received_status_presence: Option<Box<dyn Fn(StatusPresenceEvent) + Send + 'static>>,
-    on_received_stream_presence: Option<Box<dyn Fn(StreamPresenceEvent) + Send + 'static>>,
-    on_received_stream_state: Option<Box<dyn Fn(StreamData) + Send + 'static>>,
+    session: Option<Session>,
+    memberships: Memberships,
+    heartbeat: Heartbeat,
+    envelope_codec: Option<Arc<dyn EnvelopeCodec>>,
+    // The codec of the current connection, set on connect
+    connected_codec: Option<Arc<dyn EnvelopeCodec>>,
+    on_closed: Subscribers<CloseEvent>,
+    // Dispatched once the socket won't reconnect anymore
+    on_ended: Subscribers<CloseEvent>,
+    on_connected: Subscribers<()>,
+    on_received_channel_message: Subscribers<ApiChannelMessage>,
+    on_received_channel_presence: Subscribers<ChannelPresenceEvent>,
+    on_received_error: Subscribers<Error>,
+    on_received_matchmaker_matched: Subscribers<MatchmakerMatched>,
+    on_received_match_state: Subscribers<MatchData>,
+    on_received_match_presence: Subscribers<MatchPresenceEvent>,
+    on_received_notification: Subscribers<ApiNotification>,
+    on_received_party_close: Subscribers<PartyClose>,
+    on_received_party_data: Subscribers<PartyData>,
+    on_received_party_join_request: Subscribers<PartyJoinRequest>,
+    on_received_party_leader: Subscribers<PartyLeader>,
+    on_received_party_presence: Subscribers<PartyPresenceEvent>,
+    on_received_status_presence: Subscribers<StatusPresenceEvent>,
+    on_received_stream_presence: Subscribers<StreamPresenceEvent>,
+    on_received_stream_state: Subscribers<StreamData>,
+}
+
+type RefreshFn =
+    Arc<dyn Fn(Session) -> Pin<Box<dyn Future<Output = Result<(), String>> + Send>> + Send + Sync>;
+type ReconnectFuture = Pin<Box<dyn Future<Output = Result<ReconnectReport, String>> + Send>>;
+
+#[derive(Default)]
+struct Reconnect {
+    policy: Option<ReconnectPolicy>,
+    refresh: Option<RefreshFn>,
+    appear_online: bool,
+    connect_timeout: i32,
+    // The socket was connected and wasn't closed on purpose
+    active: bool,
+    attempt: u32,
+    next_attempt: Option<Instant>,
+    pending: Option<ReconnectFuture>,
+    on_reconnecting: Option<Arc<dyn Fn(u32, Duration) + Send + Sync>>,
+    on_reconnected: Option<Arc<dyn Fn(ReconnectReport) + Send + Sync>>,
 }
 
 /// A socket to interact with Nakama realtime engine.
 pub struct WebSocket<A: SocketAdapter> {
     adapter: Arc<Mutex<A>>,
     shared_state: Arc<Mutex<SharedState>>,
+    connecting: Arc<Mutex<Vec<ConnectSender<A::Error>>>>,
+    reconnect: Arc<Mutex<Reconnect>>,
+    dispatcher: Arc<Mutex<Arc<dyn Dispatcher>>>,
+    payload_codec: Arc<Mutex<Arc<dyn PayloadCodec>>>,
 }
 
 impl<A: SocketAdapter> Clone for WebSocket<A> {
@@ -104,145 +184,154 @@ impl<A: SocketAdapter> Clone for WebSocket<A> {
         WebSocket {
             adapter: self.adapter.clone(),
             shared_state: self.shared_state.clone(),
+            connecting: self.connecting.clone(),
+            reconnect: self.reconnect.clone(),
+            dispatcher: self.dispatcher.clone(),
+            payload_codec: self.payload_codec.clone(),
         }
     }
 }
 
-fn handle_message(shared_state: &Arc<Mutex<SharedState>>, msg: &String) {
-    trace!("handle_message: Received message: {:?}", msg);
-    let result: Result<WebSocketMessageEnvelope, DeJsonErr> = DeJson::deserialize_json(&msg);
+fn fail_pending_responses(shared_state: &Arc<Mutex<SharedState>>, event: &CloseEvent) {
     let mut shared_state = shared_state.lock().unwrap();
-    match result {
-        Ok(event) => {
-            if let Some(ref cid) = event.cid {
-                trace!("handle_message: Received message with cid");
-                let cid = cid.parse::<i64>().unwrap();
-                if let Some(response_event) = shared_state.responses.remove(&cid) {
-                    let result = response_event.send(Ok(event));
-                    if let Err(err) = result {
-                        error!("handle_message: send error: {}", err);
-                    }
-                }
-                return;
-            }
-            if let Some(message) = event.channel_message {
-                if let Some(ref cb) = shared_state.on_received_channel_message {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.channel_presence_event {
-                if let Some(ref cb) = shared_state.on_received_channel_presence {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.error {
-                if let Some(ref cb) = shared_state.on_received_error {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.matchmaker_matched {
-                if let Some(ref cb) = shared_state.on_received_matchmaker_matched {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.match_data {
-                if let Some(ref cb) = shared_state.on_received_match_state {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.match_presence_event {
-                if let Some(ref cb) = shared_state.on_received_match_presence {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(mut message) = event.notifications {
-                if let Some(ref cb) = shared_state.on_received_notification {
-                    for message in message.notifications.drain(..) {
-                        cb(message)
-                    }
-                }
-                return;
-            }
-            if let Some(message) = event.party_close {
-                if let Some(ref cb) = shared_state.on_received_party_close {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.party_data {
-                if let Some(ref cb) = shared_state.on_received_party_data {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.party_join_request {
-                if let Some(ref cb) = shared_state.on_received_party_join_request {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.party_leader {
-                if let Some(ref cb) = shared_state.on_received_party_leader {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.party_presence_event {
-                if let Some(ref cb) = shared_state.on_received_party_presence {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.status_presence_event {
-                if let Some(ref cb) = shared_state.on_received_status_presence {
-                    cb(message)
-                }
-                return;
-            }
-            if let Some(message) = event.stream_presence_event {
-                if let Some(ref cb) = shared_state.on_received_stream_presence {
-                    cb(message)
+    shared_state.timeouts.clear();
+    for (_, response_event) in shared_state.responses.drain() {
+        let result = response_event.send(Err(ResponseError::Disconnected(event.clone())));
+        if let Err(err) = result {
+            error!("fail_pending_responses: send error: {}", err);
+        }
+    }
+}
+
+fn finish_connecting<E>(
+    connecting: &Arc<Mutex<Vec<ConnectSender<E>>>>,
+    mut result: impl FnMut() -> Result<(), ConnectError<E>>,
+) {
+    let senders: Vec<_> = connecting.lock().unwrap().drain(..).collect();
+    for sender in senders {
+        if sender.send(result()).is_err() {
+            error!("finish_connecting: Connect future was dropped");
+        }
+    }
+}
+
+fn codec_or_default(codec: &Option<Arc<dyn EnvelopeCodec>>) -> Arc<dyn EnvelopeCodec> {
+    match codec {
+        Some(codec) => codec.clone(),
+        None => Arc::new(JsonCodec),
+    }
+}
+
+/// Decodes a frame with the codec of the connection, see [`handle_server_message`].
+fn handle_frame(shared_state: &Arc<Mutex<SharedState>>, dispatcher: &dyn Dispatcher, frame: Frame) {
+    trace!("handle_frame: Received frame: {:?}", frame);
+    let codec = codec_or_default(&shared_state.lock().unwrap().connected_codec);
+    match codec.decode(frame) {
+        Ok(message) => handle_server_message(shared_state, dispatcher, message),
+        Err(DecodeError::Json {
+            cid: Some(cid),
+            error,
+        }) => {
+            error!("handle_frame: Failed to parse json: {}", error);
+            // Inform the future about the parse error
+            let cid = cid.parse::<i64>().unwrap();
+            if let Some(response_event) = shared_state.lock().unwrap().responses.remove(&cid) {
+                let result = response_event.send(Err(ResponseError::DeJsonError(error)));
+                if let Err(err) = result {
+                    error!("handle_frame: Received send error: {}", err)
                 }
-                return;
             }
-            if let Some(message) = event.stream_data {
-                if let Some(ref cb) = shared_state.on_received_stream_state {
-                    cb(message)
-                }
-                return;
+        }
+        Err(err) => error!("handle_frame: Failed to decode message: {}", err),
+    }
+}
+
+// The task calling the subscribers of an event
+fn event_task<T: Clone + Send + 'static>(subscribers: &Subscribers<T>, message: T) -> Task {
+    let callbacks = subscribers.callbacks();
+    Box::new(move || callbacks.call(message))
+}
+
+/// Resolves the pending request of a message or hands the subscribers of the event to the dispatcher. Must be
+/// called without holding any lock, the dispatcher may call the subscribers right away.
+fn handle_server_message(
+    shared_state: &Arc<Mutex<SharedState>>,
+    dispatcher: &dyn Dispatcher,
+    message: ServerMessage,
+) {
+    let mut shared_state = shared_state.lock().unwrap();
+    if let Some(ref cid) = message.cid {
+        trace!("handle_server_message: Received message with cid");
+        let cid = cid.parse::<i64>().unwrap();
+        if shared_state.heartbeat.pong_received(cid, Instant::now()) {
+            return;
+        }
+        if let Some(response_event) = shared_state.responses.remove(&cid) {
+            let result = response_event.send(Ok(message.into_envelope()));
+            if let Err(err) = result {
+                error!("handle_server_message: send error: {}", err);
             }
         }
-        Err(err) => {
-            error!("handle_message: Failed to parse json: {}", err);
-            let result: Result<WebSocketMessageEnvelopeHeader, DeJsonErr> =
-                DeJson::deserialize_json(&msg);
-            match result {
-                Ok(event) => {
-                    // Inform the future about the API error
-                    if let Some(ref cid) = event.cid {
-                        trace!("handle_message: Received error message with cid");
-                        let cid = cid.parse::<i64>().unwrap();
-                        if let Some(response_event) = shared_state.responses.remove(&cid) {
-                            // Send DeJsonErr
-                            let result = response_event.send(Err(err));
-                            if let Err(err) = result {
-                                error!("handle_message: Received send error: {}", err)
-                            }
-                        }
-                        return;
-                    }
-                }
-                Err(_) => {
-                    // We can't parse more information. Forward the json parse error
-                    error!("{:?}", err)
+        return;
+    }
+
+    let task = match message.payload {
+        Payload::ChannelMessage(message) => {
+            event_task(&shared_state.on_received_channel_message, message)
+        }
+        Payload::ChannelPresenceEvent(message) => {
+            event_task(&shared_state.on_received_channel_presence, message)
+        }
+        Payload::Error(message) => event_task(&shared_state.on_received_error, message),
+        Payload::MatchmakerMatched(message) => {
+            event_task(&shared_state.on_received_matchmaker_matched, message)
+        }
+        Payload::MatchData(message) => event_task(&shared_state.on_received_match_state, message),
+        Payload::MatchPresenceEvent(message) => {
+            event_task(&shared_state.on_received_match_presence, message)
+        }
+        Payload::Notifications(mut message) => {
+            let callbacks = shared_state.on_received_notification.callbacks();
+            Box::new(move || {
+                for message in message.notifications.drain(..) {
+                    callbacks.call(message);
                 }
-            }
+            })
+        }
+        Payload::PartyClose(message) => {
+            shared_state.memberships.leave_party(&message.party_id);
+            event_task(&shared_state.on_received_party_close, message)
+        }
+        Payload::PartyData(message) => event_task(&shared_state.on_received_party_data, message),
+        Payload::PartyJoinRequest(message) => {
+            event_task(&shared_state.on_received_party_join_request, message)
+        }
+        Payload::PartyLeader(message) => {
+            event_task(&shared_state.on_received_party_leader, message)
+        }
+        Payload::PartyPresenceEvent(message) => {
+            event_task(&shared_state.on_received_party_presence, message)
+        }
+        Payload::StatusPresenceEvent(message) => {
+            event_task(&shared_state.on_received_status_presence, message)
+        }
+        Payload::StreamPresenceEvent(message) => {
+            event_task(&shared_state.on_received_stream_presence, message)
+        }
+        Payload::StreamData(message) => event_task(&shared_state.on_received_stream_state, message),
+        _ => return,
+    };
+    drop(shared_state);
+    dispatcher.dispatch(task);
+}
+
+// Clears the dispatching flag when dropped, also if a callback panics
+struct DispatchingGuard<'a>(&'a Mutex<SharedState>);
+
+impl Drop for DispatchingGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut shared_state) = self.0.lock() {
+            shared_state.dispatching = false;
         }
     }
 }
@@ -254,13 +343,17 @@ impl WebSocket<WebSocketAdapter> {
     }
 }
 
-impl<A: SocketAdapter + Send> WebSocket<A> {
+impl<A: SocketAdapter + Send + 'static> WebSocket<A> {
     pub fn new(adapter: A) -> Self {
         let web_socket = WebSocket {
             adapter: Arc::new(Mutex::new(adapter)),
             shared_state: Arc::new(Mutex::new(SharedState {
                 ..Default::default()
             })),
+            connecting: Arc::new(Mutex::new(vec![])),
+            reconnect: Arc::new(Mutex::new(Reconnect::default())),
+            dispatcher: Arc::new(Mutex::new(Arc::new(TickDispatcher::default()))),
+            payload_codec: Arc::new(Mutex::new(Arc::new(Base64Codec))),
         };
 
         web_socket
@@ -276,40 +369,68 @@ impl<A: SocketAdapter + Send> WebSocket<A> {
                     }
                     Ok(msg) => {
                         trace!("on_received: {}", msg);
-                        handle_message(&shared_state, &msg);
+                        shared_state
+                            .lock()
+                            .unwrap()
+                            .incoming
+                            .push_back(Incoming::Frame(Frame::Text(msg)));
                     }
                 }
             });
 
+        web_socket
+            .adapter
+            .lock()
+            .expect("panic inside other mutex!")
+            .on_received_binary({
+                let shared_state = web_socket.shared_state.clone();
+                move |data| {
+                    shared_state
+                        .lock()
+                        .unwrap()
+                        .incoming
+                        .push_back(Incoming::Frame(Frame::Binary(data)));
+                }
+            });
+
         {
             let mut adapter = web_socket.adapter.lock().unwrap();
+            // The adapter calls these while it is locked, the events are dispatched once it has been released
             adapter.on_closed({
                 let shared_state = web_socket.shared_state.clone();
-                move || {
-                    if let Some(ref cb) = shared_state.lock().unwrap().on_closed {
-                        cb()
-                    }
+                move |event| {
+                    shared_state
+                        .lock()
+                        .unwrap()
+                        .incoming
+                        .push_back(Incoming::Closed(event));
                 }
             });
 
             adapter.on_connected({
                 let shared_state = web_socket.shared_state.clone();
                 move || {
-                    if let Some(ref cb) = shared_state.lock().unwrap().on_connected {
-                        cb()
-                    }
-
                     shared_state
                         .lock()
                         .unwrap()
-                        .connected
-                        .drain(..)
-                        .for_each(|sender| {
-                            let result = sender.send(());
-                            if let Err(err) = result {
-                                error!("on_connected: Received send error: {}", err)
-                            }
-                        });
+                        .incoming
+                        .push_back(Incoming::Connected);
+                }
+            });
+
+            adapter.on_connect_failed({
+                let connecting = web_socket.connecting.clone();
+                move |err| {
+                    error!("on_connect_failed: {}", err);
+                    // Only one caller can receive the error, the others see the close event
+                    let mut err = Some(err);
+                    finish_connecting(&connecting, || match err.take() {
+                        Some(err) => Err(ConnectError::AdapterError(err)),
+                        None => Err(ConnectError::Disconnected(CloseEvent::new(
+                            CloseEvent::ABNORMAL,
+                            "Failed to connect",
+                        ))),
+                    });
                 }
             });
         }
@@ -317,26 +438,536 @@ impl<A: SocketAdapter + Send> WebSocket<A> {
         web_socket
     }
 
+    /// Send pings to detect dead connections and measure the round trip time, see
+    /// [`heartbeat`](crate::heartbeat).
+    pub fn enable_heartbeat(&self, config: HeartbeatConfig) {
+        self.shared_state
+            .lock()
+            .unwrap()
+            .heartbeat
+            .set_config(Some(config));
+    }
+
+    pub fn disable_heartbeat(&self) {
+        self.shared_state.lock().unwrap().heartbeat.set_config(None);
+    }
+
+    /// The round trip time of the last answered ping.
+    pub fn latest_rtt(&self) -> Option<Duration> {
+        self.shared_state.lock().unwrap().heartbeat.latest_rtt()
+    }
+
+    /// The exponentially smoothed round trip time of the answered pings.
+    pub fn smoothed_rtt(&self) -> Option<Duration> {
+        self.shared_state.lock().unwrap().heartbeat.smoothed_rtt()
+    }
+
+    /// Set the [`Dispatcher`] that runs the callbacks, see [`dispatcher`](crate::dispatcher). Callbacks that
+    /// were already handed to the previous dispatcher still run there.
+    pub fn set_dispatcher<D: Dispatcher + 'static>(&self, dispatcher: D) {
+        let dispatcher: Arc<dyn Dispatcher> = Arc::new(dispatcher);
+        let receiver: Option<NetworkReceiver> = if dispatcher.on_network_thread() {
+            let shared_state = self.shared_state.clone();
+            let dispatcher = dispatcher.clone();
+            Some(Arc::new(move |frame: Frame| {
+                // A panicking callback must not take the connection down
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    handle_frame(&shared_state, dispatcher.as_ref(), frame)
+                }));
+                if result.is_err() {
+                    error!("set_dispatcher: A callback panicked on the network thread");
+                }
+            }))
+        } else {
+            None
+        };
+        let on_network_thread = self
+            .adapter
+            .lock()
+            .expect("panic inside other mutex!")
+            .set_network_receiver(receiver);
+        if dispatcher.on_network_thread() && !on_network_thread {
+            trace!(
+                "set_dispatcher: The adapter has no network thread, messages are processed in tick"
+            );
+        }
+        *self.dispatcher.lock().unwrap() = dispatcher;
+    }
+
+    /// Whether both handles are clones of the same socket.
+    pub(crate) fn is_same_socket(&self, other: &WebSocket<A>) -> bool {
+        Arc::ptr_eq(&self.shared_state, &other.shared_state)
+    }
+
+    fn dispatcher(&self) -> Arc<dyn Dispatcher> {
+        self.dispatcher.lock().unwrap().clone()
+    }
+
+    /// Process the events received from the adapter without holding any lock, so that the callbacks can use
+    /// the socket.
+    fn dispatch_incoming(&self) {
+        {
+            let mut shared_state = self.shared_state.lock().unwrap();
+            if shared_state.dispatching {
+                return;
+            }
+            shared_state.dispatching = true;
+        }
+        let _guard = DispatchingGuard(&self.shared_state);
+
+        let dispatcher = self.dispatcher();
+        loop {
+            let incoming = self.shared_state.lock().unwrap().incoming.pop_front();
+            match incoming {
+                None => break,
+                Some(Incoming::Frame(frame)) => {
+                    handle_frame(&self.shared_state, dispatcher.as_ref(), frame)
+                }
+                Some(Incoming::Connected) => {
+                    let callbacks = {
+                        let mut shared_state = self.shared_state.lock().unwrap();
+                        shared_state.heartbeat.reset();
+                        shared_state.on_connected.callbacks()
+                    };
+                    finish_connecting(&self.connecting, || Ok(()));
+                    dispatcher.dispatch(Box::new(move || callbacks.call(())));
+                }
+                Some(Incoming::Closed(event)) => {
+                    finish_connecting(&self.connecting, || {
+                        Err(ConnectError::Disconnected(event.clone()))
+                    });
+                    fail_pending_responses(&self.shared_state, &event);
+                    let (callbacks, ended_callbacks) = {
+                        let shared_state = self.shared_state.lock().unwrap();
+                        (
+                            shared_state.on_closed.callbacks(),
+                            shared_state.on_ended.callbacks(),
+                        )
+                    };
+                    dispatcher.dispatch(Box::new({
+                        let event = event.clone();
+                        move || callbacks.call(event)
+                    }));
+                    if !self.will_reconnect() {
+                        dispatcher.dispatch(Box::new(move || ended_callbacks.call(event)));
+                    }
+                }
+            }
+        }
+    }
+
+    // The socket reconnects once the connection is lost
+    fn will_reconnect(&self) -> bool {
+        let reconnect = self.reconnect.lock().unwrap();
+        reconnect.policy.is_some() && reconnect.active
+    }
+
+    /// Dispatch the ended event if the socket stopped reconnecting after the connection was lost. No close
+    /// event follows in that case.
+    fn end_if_disconnected(&self, was_reconnecting: bool, event: CloseEvent) {
+        if !was_reconnecting || self.connection_state() != ConnectionState::Disconnected {
+            return;
+        }
+        let callbacks = self.shared_state.lock().unwrap().on_ended.callbacks();
+        self.dispatcher()
+            .dispatch(Box::new(move || callbacks.call(event)));
+    }
+
+    fn subscribe<T, F>(
+        &self,
+        subscribers: fn(&mut SharedState) -> &mut Subscribers<T>,
+        callback: F,
+    ) -> Subscription
+    where
+        T: Clone + 'static,
+        F: Fn(T) + Send + 'static,
+    {
+        let id = subscribers(&mut self.shared_state.lock().unwrap()).add(Box::new(callback));
+        let shared_state = Arc::downgrade(&self.shared_state);
+        Subscription::new(move || {
+            if let Some(shared_state) = shared_state.upgrade() {
+                subscribers(&mut shared_state.lock().unwrap()).remove(id);
+            }
+        })
+    }
+
+    fn set_handler<T, F>(
+        &self,
+        subscribers: fn(&mut SharedState) -> &mut Subscribers<T>,
+        callback: F,
+    ) where
+        T: Clone + 'static,
+        F: Fn(T) + Send + 'static,
+    {
+        subscribers(&mut self.shared_state.lock().unwrap()).set_handler(Box::new(callback));
+    }
+
+    fn tick_heartbeat(&self) {
+        if self.connection_state() != ConnectionState::Connected {
+            return;
+        }
+
+        let now = Instant::now();
+        let action = self.shared_state.lock().unwrap().heartbeat.poll(now);
+        match action {
+            HeartbeatAction::Idle => {}
+            HeartbeatAction::Ping => {
+                let (mut envelope, cid) = self.make_envelope_with_cid();
+                envelope.ping = Some(Ping {});
+                self.shared_state
+                    .lock()
+                    .unwrap()
+                    .heartbeat
+                    .ping_sent(cid, now);
+                if let Err(err) = self.send(&envelope, false) {
+                    error!("tick_heartbeat: Failed to send ping: {}", err);
+                }
+            }
+            HeartbeatAction::Dead => {
+                error!("tick_heartbeat: No pong received, dropping the connection");
+                self.adapter
+                    .lock()
+                    .expect("panic inside other mutex!")
+                    .abort("Heartbeat timed out");
+            }
+        }
+    }
+
+    /// Reconnect automatically if the connection is lost, see [`reconnect`](crate::reconnect).
+    ///
+    /// The `client` is used to refresh the session before reconnecting if it is about to expire.
+    pub fn enable_auto_reconnect<C>(&self, client: &C, policy: ReconnectPolicy)
+    where
+        C: Client + Clone + Send + Sync + 'static,
+    {
+        let client = client.clone();
+        let refresh: RefreshFn = Arc::new(move |session| {
+            let client = client.clone();
+            Box::pin(async move {
+                client
+                    .session_refresh(&session, HashMap::new())
+                    .await
+                    .map_err(|err| err.to_string())
+            })
+        });
+
+        let mut reconnect = self.reconnect.lock().unwrap();
+        reconnect.policy = Some(policy);
+        reconnect.refresh = Some(refresh);
+    }
+
+    /// Stop reconnecting, including a reconnect that is in progress.
+    pub fn disable_auto_reconnect(&self) {
+        let was_reconnecting = {
+            let mut reconnect = self.reconnect.lock().unwrap();
+            let was_reconnecting = reconnect.policy.is_some() && reconnect.active;
+            reconnect.policy = None;
+            reconnect.refresh = None;
+            reconnect.attempt = 0;
+            reconnect.next_attempt = None;
+            reconnect.pending = None;
+            was_reconnecting
+        };
+        self.end_if_disconnected(
+            was_reconnecting,
+            CloseEvent::new(CloseEvent::ABNORMAL, "Auto reconnect disabled"),
+        );
+    }
+
+    /// Register a callback that is dispatched with the attempt number and the delay before a reconnect
+    /// attempt is started.
+    pub fn on_reconnecting<T>(&self, callback: T)
+    where
+        T: Fn(u32, Duration) + Send + Sync + 'static,
+    {
+        self.reconnect.lock().unwrap().on_reconnecting = Some(Arc::new(callback));
+    }
+
+    /// Register a callback that is dispatched once the socket has reconnected and restored its chat
+    /// channels, followed users and parties.
+    pub fn on_reconnected<T>(&self, callback: T)
+    where
+        T: Fn(ReconnectReport) + Send + Sync + 'static,
+    {
+        self.reconnect.lock().unwrap().on_reconnected = Some(Arc::new(callback));
+    }
+
+    fn tick_reconnect(&self) {
+        let pending = {
+            let mut reconnect = self.reconnect.lock().unwrap();
+            if reconnect.policy.is_none() || !reconnect.active {
+                return;
+            }
+            reconnect.pending.take()
+        };
+
+        // Poll without holding the lock, the reconnect uses the socket itself
+        if let Some(mut pending) = pending {
+            let mut context = Context::from_waker(noop_waker_ref());
+            let poll = pending.as_mut().poll(&mut context);
+            let mut reconnect = self.reconnect.lock().unwrap();
+            if reconnect.policy.is_none() {
+                return;
+            }
+            match poll {
+                Poll::Pending => reconnect.pending = Some(pending),
+                Poll::Ready(Ok(report)) => {
+                    trace!(
+                        "tick_reconnect: Reconnected after {} attempts",
+                        report.attempts
+                    );
+                    reconnect.attempt = 0;
+                    let callback = reconnect.on_reconnected.clone();
+                    drop(reconnect);
+                    if let Some(callback) = callback {
+                        self.dispatcher()
+                            .dispatch(Box::new(move || callback(report)));
+                    }
+                }
+                Poll::Ready(Err(err)) => {
+                    error!(
+                        "tick_reconnect: Attempt {} failed: {}",
+                        reconnect.attempt, err
+                    );
+                }
+            }
+            return;
+        }
+
+        if self.connection_state() != ConnectionState::Disconnected {
+            return;
+        }
+
+        let mut reconnect = self.reconnect.lock().unwrap();
+        let next_attempt = reconnect.next_attempt;
+        match next_attempt {
+            None => {
+                let policy = reconnect.policy.clone().unwrap();
+                if policy
+                    .max_attempts
+                    .map_or(false, |max_attempts| reconnect.attempt >= max_attempts)
+                {
+                    error!(
+                        "tick_reconnect: Giving up after {} attempts",
+                        reconnect.attempt
+                    );
+                    reconnect.active = false;
+                    reconnect.attempt = 0;
+                    drop(reconnect);
+                    self.end_if_disconnected(
+                        true,
+                        CloseEvent::new(CloseEvent::ABNORMAL, "Reconnect gave up"),
+                    );
+                    return;
+                }
+
+                reconnect.attempt += 1;
+                let delay = policy.delay(reconnect.attempt);
+                reconnect.next_attempt = Some(Instant::now() + delay);
+                let attempt = reconnect.attempt;
+                let callback = reconnect.on_reconnecting.clone();
+                drop(reconnect);
+                if let Some(callback) = callback {
+                    self.dispatcher()
+                        .dispatch(Box::new(move || callback(attempt, delay)));
+                }
+            }
+            Some(next_attempt) if next_attempt <= Instant::now() => {
+                let session = self.shared_state.lock().unwrap().session.clone();
+                let session = match session {
+                    Some(session) => session,
+                    None => {
+                        reconnect.active = false;
+                        drop(reconnect);
+                        self.end_if_disconnected(
+                            true,
+                            CloseEvent::new(CloseEvent::ABNORMAL, "No session to reconnect with"),
+                        );
+                        return;
+                    }
+                };
+                reconnect.next_attempt = None;
+                reconnect.pending = Some(Box::pin(self.clone().reconnect_and_restore(
+                    session,
+                    reconnect.refresh.clone(),
+                    reconnect.appear_online,
+                    reconnect.connect_timeout,
+                    reconnect.attempt,
+                )));
+            }
+            Some(_) => {}
+        }
+    }
+
+    async fn reconnect_and_restore(
+        self,
+        session: Session,
+        refresh: Option<RefreshFn>,
+        appear_online: bool,
+        connect_timeout: i32,
+        attempts: u32,
+    ) -> Result<ReconnectReport, String> {
+        if let Some(refresh) = refresh {
+            if session.will_expire_soon()
+                && session.get_refresh_token().is_some()
+                && !session.is_refresh_expired()
+            {
+                trace!("reconnect_and_restore: Refreshing session");
+                refresh(session.clone()).await?;
+            }
+        }
+
+        self.connect(&session, appear_online, connect_timeout)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        // Joining again tracks the memberships that could be restored
+        let memberships = std::mem::take(&mut self.shared_state.lock().unwrap().memberships);
+        let mut failed = vec![];
+
+        for (_, join) in memberships.chats {
+            let result = self
+                .join_chat(
+                    &join.target,
+                    join.channel_type,
+                    join.persistence,
+                    join.hidden,
+                )
+                .await;
+            if let Err(err) = result {
+                failed.push(RestoreFailure {
+                    membership: Membership::Chat(join),
+                    error: err.to_string(),
+                });
+            }
+        }
+
+        if !memberships.follow_user_ids.is_empty() || !memberships.follow_usernames.is_empty() {
+            let user_ids: Vec<&str> = memberships
+                .follow_user_ids
+                .iter()
+                .map(|id| id.as_str())
+                .collect();
+            let usernames: Vec<&str> = memberships
+                .follow_usernames
+                .iter()
+                .map(|name| name.as_str())
+                .collect();
+            if let Err(err) = self.follow_users(&user_ids, &usernames).await {
+                failed.push(RestoreFailure {
+                    membership: Membership::Follow(StatusFollow {
+                        user_ids: memberships.follow_user_ids.clone(),
+                        usernames: memberships.follow_usernames.clone(),
+                    }),
+                    error: err.to_string(),
+                });
+            }
+        }
+
+        for party_id in memberships.parties {
+            if let Err(err) = self.join_party(&party_id).await {
+                failed.push(RestoreFailure {
+                    membership: Membership::Party(PartyJoin { party_id }),
+                    error: err.to_string(),
+                });
+            }
+        }
+
+        Ok(ReconnectReport { attempts, failed })
+    }
+
+    /// Set the codec used to encode the payload of [`Socket::rpc_bytes`]. Defaults to base64.
+    pub fn set_payload_codec<C: PayloadCodec + 'static>(&self, codec: C) {
+        *self.payload_codec.lock().unwrap() = Arc::new(codec);
+    }
+
+    /// Decode the binary payload of an RPC response with the payload codec.
+    pub fn decode_rpc_payload(&self, rpc: &ApiRpc) -> Result<Vec<u8>, PayloadDecodeError> {
+        let codec = self.payload_codec.lock().unwrap().clone();
+        rpc.decode_payload(codec.as_ref())
+    }
+
+    /// Set the format of the messages exchanged with the server, see [`wire_format`](crate::wire_format).
+    /// Takes effect on the next connect.
+    ///
+    /// Shorthand for [`WebSocket::set_envelope_codec`] with the codec of the format, [`JsonCodec`] or
+    /// [`ProtobufCodec`].
+    pub fn set_wire_format(&self, wire_format: WireFormat) {
+        match wire_format {
+            WireFormat::Json => self.set_envelope_codec(JsonCodec),
+            WireFormat::Protobuf => self.set_envelope_codec(ProtobufCodec),
+        }
+    }
+
+    /// Set the codec of the messages exchanged with the server, see [`envelope_codec`](crate::envelope_codec).
+    /// Takes effect on the next connect. Defaults to [`JsonCodec`].
+    pub fn set_envelope_codec<C: EnvelopeCodec + 'static>(&self, codec: C) {
+        self.shared_state.lock().unwrap().envelope_codec = Some(Arc::new(codec));
+    }
+
+    /// The state of the connection as of the last [`tick`](WebSocket::tick).
+    pub fn connection_state(&self) -> ConnectionState {
+        self.adapter.lock().unwrap().connection_state()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection_state() == ConnectionState::Connected
+    }
+
+    /// Process received messages and resolve the pending requests on the calling thread, unless the
+    /// [`Dispatcher`] processes them on the network thread. The callbacks run where the dispatcher runs them, by
+    /// default at the end of the tick.
+    ///
+    /// Closes the socket once the session it connected with has ended, e.g. after a logout.
     pub fn tick(&self) {
         self.adapter
             .lock()
             .expect("panic inside other mutex!")
             .tick();
 
-        let mut shared_state = self.shared_state.lock().unwrap();
+        let session_ended = {
+            let mut shared_state = self.shared_state.lock().unwrap();
+            match shared_state.session {
+                Some(ref session) if session.has_ended() => shared_state.session.take().is_some(),
+                _ => false,
+            }
+        };
+        if session_ended {
+            trace!("tick: Closing socket because the session has ended");
+            let was_reconnecting = self.will_reconnect();
+            self.reconnect.lock().unwrap().active = false;
+            self.end_if_disconnected(
+                was_reconnecting,
+                CloseEvent::new(CloseEvent::NORMAL, "Session ended"),
+            );
+            self.adapter
+                .lock()
+                .expect("panic inside other mutex!")
+                .close();
+        }
 
-        // TODO: Use a clock!
-        let (timeout_finished, timeouts) = shared_state
-            .timeouts
-            .iter()
-            .map(|(k, v)| (*k, *v - 16))
-            .partition(|&(_, timeout)| {
-                return timeout <= 0;
-            });
-        shared_state.timeouts = timeouts;
-        timeout_finished.iter().for_each(|(k, _)| {
-            shared_state.responses.remove(k);
-        })
+        self.tick_heartbeat();
+        self.dispatch_incoming();
+        self.tick_reconnect();
+
+        {
+            let mut shared_state = self.shared_state.lock().unwrap();
+
+            // TODO: Use a clock!
+            let (timeout_finished, timeouts) = shared_state
+                .timeouts
+                .iter()
+                .map(|(k, v)| (*k, *v - 16))
+                .partition(|&(_, timeout)| {
+                    return timeout <= 0;
+                });
+            shared_state.timeouts = timeouts;
+            timeout_finished.iter().for_each(|(k, _)| {
+                shared_state.responses.remove(k);
+            })
+        }
+
+        self.dispatcher().tick();
     }
 
     fn make_envelope_with_cid(&self) -> (WebSocketMessageEnvelope, i64) {
@@ -362,20 +993,39 @@ impl<A: SocketAdapter + Send> WebSocket<A> {
     }
 
     #[inline]
-    fn send(&self, data: &str, reliable: bool) -> Result<(), WebSocketError<A>> {
-        trace!("send: Sending message: {:?}", data);
-        self.adapter
-            .lock()
-            .expect("panic inside other mutex!")
-            .send(data, reliable)
-            .map_err(|err| WebSocketError::AdapterError(err))
+    /// Send an envelope with the codec the socket connected with.
+    fn send(
+        &self,
+        envelope: &WebSocketMessageEnvelope,
+        reliable: bool,
+    ) -> Result<(), WebSocketError<A>> {
+        let codec = codec_or_default(&self.shared_state.lock().unwrap().connected_codec);
+        let frame = codec.encode(envelope);
+        let adapter = self.adapter.lock().expect("panic inside other mutex!");
+        match frame {
+            Frame::Text(data) => {
+                trace!("send: Sending message: {:?}", data);
+                adapter
+                    .send(&data, reliable)
+                    .map_err(WebSocketError::AdapterError)
+            }
+            Frame::Binary(data) => {
+                trace!("send: Sending {} bytes", data.len());
+                adapter
+                    .send_binary(&data, reliable)
+                    .map_err(|err| match err {
+                        SendBinaryError::Unsupported => WebSocketError::BinaryNotSupported,
+                        SendBinaryError::Adapter(err) => WebSocketError::AdapterError(err),
+                    })
+            }
+        }
     }
 
     async fn wait_response(
         &self,
         cid: i64,
     ) -> Result<WebSocketMessageEnvelope, <Self as Socket>::Error> {
-        let (tx, rx) = oneshot::channel::<Result<WebSocketMessageEnvelope, DeJsonErr>>();
+        let (tx, rx) = oneshot::channel::<Result<WebSocketMessageEnvelope, ResponseError>>();
 
         {
             let mut shared_state = self.shared_state.lock().unwrap();
@@ -391,23 +1041,42 @@ impl<A: SocketAdapter + Send> WebSocket<A> {
                 }
                 return Ok(message);
             }
-            Err(error) => {
+            Err(ResponseError::DeJsonError(error)) => {
                 return Err(WebSocketError::DeJsonError(error));
             }
+            Err(ResponseError::Disconnected(event)) => {
+                return Err(WebSocketError::Disconnected(event));
+            }
         }
     }
 }
 
 #[async_trait]
-impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
+impl<A: SocketAdapter + Send + 'static> Socket for WebSocket<A> {
     type Error = WebSocketError<A>;
 
     /// Register a callback that is dispatched when the socket is closed.
     fn on_closed<T>(&mut self, callback: T)
     where
-        T: Fn() + Send + 'static,
+        T: Fn(CloseEvent) + Send + 'static,
+    {
+        self.set_handler(|state| &mut state.on_closed, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when the socket is closed.
+    fn subscribe_closed<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(CloseEvent) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_closed = Some(Box::new(callback));
+        self.subscribe(|state| &mut state.on_closed, callback)
+    }
+
+    /// Subscribe to the event that is dispatched once the socket won't reconnect anymore.
+    fn subscribe_ended<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(CloseEvent) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_ended, callback)
     }
 
     /// Register a callback that is dispatched when the socket is connected
@@ -415,7 +1084,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn() + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_connected = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_connected, move |()| callback());
+    }
+
+    /// Subscribe to the event that is dispatched when the socket is connected.
+    fn subscribe_connected<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn() + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_connected, move |()| callback())
     }
 
     /// Register a callback that is dispatched when a chat message was received
@@ -423,10 +1100,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(ApiChannelMessage) + Send + 'static,
     {
-        self.shared_state
-            .lock()
-            .unwrap()
-            .on_received_channel_message = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_channel_message, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when a chat message was received.
+    fn subscribe_channel_message<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(ApiChannelMessage) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_channel_message, callback)
     }
 
     /// Register a callback that is dispatched when a presence change for joins and leaves in a chat channel was received.
@@ -434,10 +1116,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(ChannelPresenceEvent) + Send + 'static,
     {
-        self.shared_state
-            .lock()
-            .unwrap()
-            .on_received_channel_presence = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_channel_presence, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when a presence change for joins and leaves in a chat channel was received.
+    fn subscribe_channel_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(ChannelPresenceEvent) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_channel_presence, callback)
     }
 
     /// Register a callback that is dispatched when an error is received.
@@ -445,7 +1132,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(Error) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_received_error = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_error, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when an error is received.
+    fn subscribe_error<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(Error) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_error, callback)
     }
 
     /// Register a callback that is dispatched when a matchmaker matched the user.
@@ -453,10 +1148,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(MatchmakerMatched) + Send + 'static,
     {
-        self.shared_state
-            .lock()
-            .unwrap()
-            .on_received_matchmaker_matched = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_matchmaker_matched, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when a matchmaker matched the user.
+    fn subscribe_matchmaker_matched<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(MatchmakerMatched) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_matchmaker_matched, callback)
     }
 
     /// Register a callback that is dispatched when receiving a match state message
@@ -464,7 +1164,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(MatchData) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_received_match_state = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_match_state, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when receiving a match state message.
+    fn subscribe_match_state<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(MatchData) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_match_state, callback)
     }
 
     /// Register a callback that is dispatched when players join or leave a match.
@@ -472,7 +1180,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(MatchPresenceEvent) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_received_match_presence = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_match_presence, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when players join or leave a match.
+    fn subscribe_match_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(MatchPresenceEvent) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_match_presence, callback)
     }
 
     /// Register a callback that is dispatched when a notification is received
@@ -480,7 +1196,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(ApiNotification) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_received_notification = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_notification, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when a notification is received.
+    fn subscribe_notification<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(ApiNotification) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_notification, callback)
     }
 
     /// Register a callback that is dispatched when a party is closed.
@@ -488,7 +1212,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(PartyClose) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_received_party_close = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_party_close, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when a party is closed.
+    fn subscribe_party_close<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyClose) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_party_close, callback)
     }
 
     /// Register a callback that is dispatched when a party data is received.
@@ -496,7 +1228,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(PartyData) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_received_party_data = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_party_data, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when a party data is received.
+    fn subscribe_party_data<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyData) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_party_data, callback)
     }
 
     /// Register a callback that is dispatched when a party join request is received.
@@ -504,10 +1244,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(PartyJoinRequest) + Send + 'static,
     {
-        self.shared_state
-            .lock()
-            .unwrap()
-            .on_received_party_join_request = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_party_join_request, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when a party join request is received.
+    fn subscribe_party_join_request<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyJoinRequest) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_party_join_request, callback)
     }
 
     /// Register a callback that is dispatched when a party leader message is received.
@@ -515,7 +1260,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(PartyLeader) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_received_party_leader = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_party_leader, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when a party leader message is received.
+    fn subscribe_party_leader<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyLeader) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_party_leader, callback)
     }
 
     /// Register a callback that is dispatched when users join or leave a party.
@@ -523,7 +1276,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(PartyPresenceEvent) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_received_party_presence = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_party_presence, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when users join or leave a party.
+    fn subscribe_party_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(PartyPresenceEvent) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_party_presence, callback)
     }
 
     /// Register a callback that is dispatched when users update their online status.
@@ -531,10 +1292,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(StatusPresenceEvent) + Send + 'static,
     {
-        self.shared_state
-            .lock()
-            .unwrap()
-            .on_received_status_presence = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_status_presence, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when users update their online status.
+    fn subscribe_status_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(StatusPresenceEvent) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_status_presence, callback)
     }
 
     /// Register a callback that is dispatched when users join or leave a realtime stream.
@@ -542,10 +1308,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(StreamPresenceEvent) + Send + 'static,
     {
-        self.shared_state
-            .lock()
-            .unwrap()
-            .on_received_stream_presence = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_stream_presence, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when users join or leave a realtime stream.
+    fn subscribe_stream_presence<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(StreamPresenceEvent) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_stream_presence, callback)
     }
 
     /// Register a callback that is dispatched when realtime stream data is received.
@@ -553,7 +1324,15 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     where
         T: Fn(StreamData) + Send + 'static,
     {
-        self.shared_state.lock().unwrap().on_received_stream_state = Some(Box::new(callback));
+        self.set_handler(|state| &mut state.on_received_stream_state, callback);
+    }
+
+    /// Subscribe to the event that is dispatched when realtime stream data is received.
+    fn subscribe_stream_state<T>(&self, callback: T) -> Subscription
+    where
+        T: Fn(StreamData) + Send + 'static,
+    {
+        self.subscribe(|state| &mut state.on_received_stream_state, callback)
     }
 
     /// Accept a join request.
@@ -584,8 +1363,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             presence: user_presence.clone(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         self.wait_response(cid).await?;
         Ok(())
@@ -609,8 +1387,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             string_properties,
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let envelope = self.wait_response(cid).await?;
 
@@ -652,8 +1429,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             party_id: party_id.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let envelope = self.wait_response(cid).await?;
 
@@ -678,8 +1454,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             party_id: party_id.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         self.wait_response(cid).await?;
 
@@ -687,13 +1462,35 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     }
 
     /// Close the socket connection to the server.
+    ///
+    /// Requests that are still waiting for a response fail with [`WebSocketError::Disconnected`].
     async fn close(&self) -> Result<(), Self::Error> {
-        todo!()
+        let was_reconnecting = {
+            let mut reconnect = self.reconnect.lock().unwrap();
+            let was_reconnecting = reconnect.policy.is_some() && reconnect.active;
+            reconnect.active = false;
+            reconnect.next_attempt = None;
+            reconnect.pending = None;
+            was_reconnecting
+        };
+        {
+            let mut shared_state = self.shared_state.lock().unwrap();
+            shared_state.session = None;
+            shared_state.memberships = Memberships::default();
+        }
+        let event = CloseEvent::new(CloseEvent::NORMAL, "Closed by client");
+        self.end_if_disconnected(was_reconnecting, event.clone());
+        self.adapter.lock().unwrap().close();
+        fail_pending_responses(&self.shared_state, &event);
+        Ok(())
     }
 
     /// Connect to the server.
     ///
-    /// If `appear_online` is false, no status updates will be sent to other clients.
+    /// If `appear_online` is false, no status updates will be sent to other clients. Fails with
+    /// [`WebSocketError::AdapterError`] if the connection isn't established within `connect_timeout`
+    /// seconds or the server rejects the handshake, e.g. because the session token is invalid. A
+    /// `connect_timeout` of zero or less waits indefinitely.
     ///
     /// # Example
     /// ```
@@ -701,34 +1498,54 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     /// # use nakama_rs::test_helpers::*;
     /// # use std::collections::HashMap;
     /// # run_in_socket_example(async move |client, session, socket| {
-    /// socket.connect(&session, true, -1).await;
+    /// socket.connect(&session, true, 30).await
+    ///     .expect("Failed to connect");
     /// # Ok(())
     /// # });
     /// ```
-    async fn connect(&self, session: &Session, appear_online: bool, connect_timeout: i32) {
+    async fn connect(
+        &self,
+        session: &Session,
+        appear_online: bool,
+        connect_timeout: i32,
+    ) -> Result<(), Self::Error> {
         let ws_url = "ws://127.0.0.1";
         let port = 7350;
+        let codec = codec_or_default(&self.shared_state.lock().unwrap().envelope_codec);
 
         let ws_addr = format!(
-            "{}:{}/ws?lang=en&status={}&token={}",
+            "{}:{}/ws?lang=en&status={}&format={}&token={}",
             ws_url,
             port,
             appear_online,
+            codec.format(),
             session.get_auth_token(),
         );
 
         let (tx, rx) = oneshot::channel();
 
-        self.shared_state.lock().unwrap().connected.push(tx);
+        self.connecting.lock().unwrap().push(tx);
+        {
+            let mut shared_state = self.shared_state.lock().unwrap();
+            shared_state.session = Some(session.clone());
+            shared_state.connected_codec = Some(codec);
+        }
 
         self.adapter
             .lock()
             .unwrap()
             .connect(&ws_addr, connect_timeout);
 
-        let result = rx.await;
-        if let Err(err) = result {
-            error!("connect: RecvError: {}", err);
+        match rx.await.map_err(|err| WebSocketError::RecvError(err))? {
+            Ok(()) => {
+                let mut reconnect = self.reconnect.lock().unwrap();
+                reconnect.active = true;
+                reconnect.appear_online = appear_online;
+                reconnect.connect_timeout = connect_timeout;
+                Ok(())
+            }
+            Err(ConnectError::AdapterError(err)) => Err(WebSocketError::AdapterError(err)),
+            Err(ConnectError::Disconnected(event)) => Err(WebSocketError::Disconnected(event)),
         }
     }
 
@@ -750,8 +1567,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
         let (mut envelope, cid) = self.make_envelope_with_cid();
         envelope.match_create = Some(MatchCreate {});
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let envelope = self.wait_response(cid).await?;
 
@@ -778,8 +1594,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
         let (mut envelope, cid) = self.make_envelope_with_cid();
         envelope.party_create = Some(PartyCreate { max_size, open });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
         Ok(result_envelope.party.unwrap())
@@ -808,11 +1623,16 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             usernames: str_slice_to_owned(usernames),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
-        Ok(result_envelope.status.unwrap())
+        let status = result_envelope.status.unwrap();
+        self.shared_state.lock().unwrap().memberships.follow(
+            user_ids,
+            usernames,
+            &status.presences,
+        );
+        Ok(status)
     }
 
     /// Join a chat channel on the server.
@@ -853,11 +1673,20 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             target: room_name.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
-        Ok(result_envelope.channel.unwrap())
+        let channel = result_envelope.channel.unwrap();
+        self.shared_state.lock().unwrap().memberships.chats.insert(
+            channel.id.clone(),
+            ChannelJoin {
+                channel_type,
+                hidden,
+                persistence,
+                target: room_name.to_owned(),
+            },
+        );
+        Ok(channel)
     }
 
     /// Join a party on the server.
@@ -879,10 +1708,14 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             party_id: party_id.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         self.wait_response(cid).await?;
+        self.shared_state
+            .lock()
+            .unwrap()
+            .memberships
+            .join_party(party_id);
         Ok(())
     }
 
@@ -912,8 +1745,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             metadata: HashMap::new(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
         Ok(result_envelope.new_match.unwrap())
@@ -943,8 +1775,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             metadata,
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
         Ok(result_envelope.new_match.unwrap())
@@ -968,8 +1799,14 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             channel_id: channel_id.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)
+        self.send(&envelope, false)?;
+        self.shared_state
+            .lock()
+            .unwrap()
+            .memberships
+            .chats
+            .remove(channel_id);
+        Ok(())
     }
 
     /// Leave a match
@@ -990,8 +1827,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             match_id: match_id.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)
+        self.send(&envelope, false)
     }
 
     /// Leave a party
@@ -1012,10 +1848,14 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             party_id: party_id.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         self.wait_response(cid).await?;
+        self.shared_state
+            .lock()
+            .unwrap()
+            .memberships
+            .leave_party(party_id);
         Ok(())
     }
 
@@ -1043,8 +1883,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             party_id: party_id.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
         Ok(result_envelope.party_join_request.unwrap())
@@ -1080,8 +1919,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             presence: party_member,
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         self.wait_response(cid).await?;
         Ok(())
@@ -1110,8 +1948,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             message_id: message_id.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
         Ok(result_envelope.channel_message_ack.unwrap())
@@ -1138,8 +1975,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             ticket: ticket.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)
+        self.send(&envelope, false)
     }
 
     /// Leave the party matchmaker pool with the ticket
@@ -1168,8 +2004,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             ticket: ticket.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)
+        self.send(&envelope, false)
     }
 
     /// Remove a party member
@@ -1195,8 +2030,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             presence,
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         self.wait_response(cid).await?;
         Ok(())
@@ -1223,8 +2057,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             payload: payload.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
         Ok(result_envelope.rpc.unwrap())
@@ -1232,7 +2065,8 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
 
     /// Execute RPC on the server.
     ///
-    /// The payload is passed as a byte slice.
+    /// The payload is passed as a byte slice and encoded with the payload codec, base64 by default.
+    /// See [`WebSocket::set_payload_codec`]. Use [`WebSocket::decode_rpc_payload`] to decode a binary response.
     ///
     /// # Example
     /// ```
@@ -1249,24 +2083,47 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
     /// let mut bin_data = vec![];
     /// data.ser_bin(&mut bin_data);
     /// let result = socket.rpc_bytes("rpc_func_name", bin_data.as_ref()).await.expect("Failed to execute rpc");
-    /// println!("Returned payload: {}", result.payload);
+    /// println!("Returned payload: {:?}", socket.decode_rpc_payload(&result));
     /// # Ok(())
     /// # });
     /// ```
-    async fn rpc_bytes(&self, func_id: &str, _payload: &[u8]) -> Result<ApiRpc, Self::Error> {
-        let (mut envelope, cid) = self.make_envelope_with_cid();
-        envelope.rpc = Some(ApiRpc {
-            id: func_id.to_owned(),
-            http_key: "".to_owned(),
-            // TODO: How to convert to string
-            payload: "".to_owned(),
-        });
+    async fn rpc_bytes(&self, func_id: &str, payload: &[u8]) -> Result<ApiRpc, Self::Error> {
+        let payload = self.payload_codec.lock().unwrap().encode(payload);
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.rpc(func_id, &payload).await
+    }
 
-        let result_envelope = self.wait_response(cid).await?;
-        Ok(result_envelope.rpc.unwrap())
+    /// Execute RPC on the server with a JSON request and response.
+    ///
+    /// Fails with [`WebSocketError::RpcDecodeError`] if the response can't be deserialized.
+    ///
+    /// # Example
+    /// ```
+    /// # #![feature(async_closure)]
+    /// # use nakama_rs::test_helpers::*;
+    /// use nanoserde::{DeJson, SerJson};
+    ///
+    /// #[derive(SerJson, DeJson)]
+    /// struct Echo {
+    ///     message: String,
+    /// }
+    ///
+    /// # run_in_socket_example(async move |client, session, socket| {
+    /// socket.connect(&session, true, -1).await.expect("Failed to connect");
+    /// let request = Echo { message: "Hello World!".to_owned() };
+    /// let response: Echo = socket.rpc_typed("echo_payload", &request).await
+    ///     .expect("Failed to execute rpc function");
+    /// # Ok(())
+    /// # });
+    /// ```
+    async fn rpc_typed<Req: SerJson + Sync, Resp: DeJson + Send>(
+        &self,
+        func_id: &str,
+        request: &Req,
+    ) -> Result<Resp, Self::Error> {
+        let response = self.rpc(func_id, &request.serialize_json()).await?;
+
+        decode_response(func_id, &response.payload).map_err(WebSocketError::RpcDecodeError)
     }
 
     /// Send data to a multiplayer match on the server.
@@ -1309,8 +2166,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             reliable: false,
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)
+        self.send(&envelope, false)
     }
 
     /// Send data to a party.
@@ -1343,11 +2199,10 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
         envelope.party_data_send = Some(PartyDataSend {
             party_id: party_id.to_owned(),
             op_code,
-            data: base64::encode(data),
+            data: data.to_vec(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)
+        self.send(&envelope, false)
     }
 
     /// Unfollow users to stop receiving status updates.
@@ -1368,8 +2223,13 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             user_ids: str_slice_to_owned(user_ids),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)
+        self.send(&envelope, false)?;
+        self.shared_state
+            .lock()
+            .unwrap()
+            .memberships
+            .unfollow(user_ids);
+        Ok(())
     }
 
     /// Update a chat message on a chat channel
@@ -1404,8 +2264,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             content: content.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
         Ok(result_envelope.channel_message_ack.unwrap())
@@ -1428,8 +2287,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             status: status.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)
+        self.send(&envelope, false)
     }
 
     /// Send a chat message on a chat channel
@@ -1462,8 +2320,7 @@ impl<A: SocketAdapter + Send> Socket for WebSocket<A> {
             content: content.to_owned(),
         });
 
-        let json = envelope.serialize_json();
-        self.send(&json, false)?;
+        self.send(&envelope, false)?;
 
         let result_envelope = self.wait_response(cid).await?;
         Ok(result_envelope.channel_message_ack.unwrap())
@@ -12,33 +12,54 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::socket_adapter::SocketAdapter;
+use crate::envelope_codec::Frame;
+use crate::socket_adapter::{
+    CloseEvent, ConnectionState, NetworkReceiver, SendBinaryError, SocketAdapter,
+};
 use log::{debug, error, trace};
 use qws;
 use qws::{CloseCode, Handshake};
+use std::cell::{Cell, RefCell};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, SendError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use url::Url;
 
 enum Message {
     StringMessage(String),
-    Connected,
+    BinaryMessage(Vec<u8>),
+    Connected(qws::Sender),
+    Closed(CloseEvent),
     Error(qws::Error),
+    ConnectFailed(WebSocketAdapterError),
 }
 
 pub struct WebSocketAdapter {
     on_connected: Option<Box<dyn Fn() + Send + 'static>>,
-    on_closed: Option<Box<dyn Fn() + Send + 'static>>,
+    on_closed: Option<Box<dyn Fn(CloseEvent) + Send + 'static>>,
     on_received: Option<Box<dyn Fn(Result<String, WebSocketAdapterError>) + Send + 'static>>,
+    on_received_binary: Option<Box<dyn Fn(Vec<u8>) + Send + 'static>>,
+    on_connect_failed: Option<Box<dyn Fn(WebSocketAdapterError) + Send + 'static>>,
+
+    // Only changed on the calling thread so that it is consistent with the callbacks.
+    state: Cell<ConnectionState>,
+    connect_deadline: Cell<Option<Instant>>,
 
     rx_message: Option<Receiver<Message>>,
-    tx_message: Option<qws::Sender>,
+    // Set by `tick` once the connection is established
+    tx_message: RefCell<Option<qws::Sender>>,
+    // Shared with the client of the current connection only, see `connect`
+    network_receiver: Arc<Mutex<Option<NetworkReceiver>>>,
 }
 
 // Client on the websocket thread
 struct WebSocketClient {
     tx: Sender<Message>,
+    out: qws::Sender,
+    network_receiver: Arc<Mutex<Option<NetworkReceiver>>>,
 }
 
 impl WebSocketClient {
@@ -54,13 +75,15 @@ impl qws::Handler for WebSocketClient {
 
     fn on_open(&mut self, shake: Handshake) -> qws::Result<()> {
         if let Some(addr) = shake.remote_addr()? {
-            let result = self.send(Message::Connected);
+            let result = self.send(Message::Connected(self.out.clone()));
             match result {
                 Ok(_) => {
                     debug!("Connection with {} now open", addr);
                 }
                 Err(err) => {
+                    // The adapter connected again meanwhile and dropped the receiver
                     error!("Failed to send {}", err);
+                    return self.out.close(CloseCode::Normal);
                 }
             }
         }
@@ -68,6 +91,16 @@ impl qws::Handler for WebSocketClient {
     }
 
     fn on_message(&mut self, msg: qws::Message) -> qws::Result<()> {
+        // Release the lock before calling the receiver, so that it can replace itself
+        let receiver = self.network_receiver.lock().unwrap().clone();
+        if let Some(receiver) = receiver {
+            receiver(match msg {
+                qws::Message::Text(data) => Frame::Text(data),
+                qws::Message::Binary(data) => Frame::Binary(data),
+            });
+            return Ok(());
+        }
+
         match msg {
             qws::Message::Text(data) => {
                 let result = self.send(Message::StringMessage(data));
@@ -75,8 +108,11 @@ impl qws::Handler for WebSocketClient {
                     error!("Handler::on_message: {}", err);
                 }
             }
-            qws::Message::Binary(_) => {
-                trace!("Handler::on_message: Received binary data");
+            qws::Message::Binary(data) => {
+                let result = self.send(Message::BinaryMessage(data));
+                if let Err(err) = result {
+                    error!("Handler::on_message: {}", err);
+                }
             }
         }
         Ok(())
@@ -84,6 +120,10 @@ impl qws::Handler for WebSocketClient {
 
     fn on_close(&mut self, code: CloseCode, reason: &str) {
         debug!("Connection closing due to ({:?}) {}", code, reason);
+        let event = CloseEvent::new(code.into(), reason);
+        if let Err(err) = self.send(Message::Closed(event)) {
+            error!("on_close: SendError: {}", err);
+        }
     }
 
     // Copied from trait for now
@@ -104,14 +144,47 @@ impl qws::Handler for WebSocketClient {
 }
 
 impl WebSocketAdapter {
+    fn closed(&self, event: CloseEvent) {
+        if self.state.replace(ConnectionState::Disconnected) == ConnectionState::Disconnected {
+            return;
+        }
+
+        debug!("closed: {:?}", event);
+        if let Some(ref cb) = self.on_closed {
+            cb(event);
+        }
+    }
+
+    fn connect_failed(&self, err: WebSocketAdapterError) {
+        if self.state.get() != ConnectionState::Connecting {
+            return;
+        }
+
+        let err = match err {
+            WebSocketAdapterError::WebSocketError(err) => connect_error(err),
+            err => err,
+        };
+        let event = CloseEvent::new(CloseEvent::ABNORMAL, &err.to_string());
+        if let Some(ref cb) = self.on_connect_failed {
+            cb(err);
+        }
+        self.closed(event);
+    }
+
     pub fn new() -> WebSocketAdapter {
         WebSocketAdapter {
             on_connected: None,
             on_closed: None,
             on_received: None,
+            on_received_binary: None,
+            on_connect_failed: None,
+
+            state: Cell::new(ConnectionState::Disconnected),
+            connect_deadline: Cell::new(None),
 
             rx_message: None,
-            tx_message: None,
+            tx_message: RefCell::new(None),
+            network_receiver: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -120,6 +193,16 @@ impl WebSocketAdapter {
 pub enum WebSocketAdapterError {
     IOError,
     WebSocketError(qws::Error),
+    /// The address is not a valid WebSocket URL.
+    InvalidAddress(String),
+    /// The host name could not be resolved.
+    AddressResolution(String),
+    /// The connection was not established within the connect timeout.
+    ConnectTimeout,
+    /// The server answered the handshake with an HTTP status other than 101, e.g. 401 for an invalid token.
+    HandshakeRejected(u16),
+    /// The TLS handshake failed.
+    Tls(String),
 }
 
 impl From<qws::Error> for WebSocketAdapterError {
@@ -128,6 +211,48 @@ impl From<qws::Error> for WebSocketAdapterError {
     }
 }
 
+// qws reports a rejected handshake as protocol error with this message followed by the status
+const HANDSHAKE_FAILED: &str = "Handshake failed with status ";
+
+fn handshake_status(details: &str) -> Option<u16> {
+    details
+        .strip_prefix(HANDSHAKE_FAILED)?
+        .parse::<u16>()
+        .ok()
+        .filter(|status| (100..600).contains(status))
+}
+
+// Only used while connecting, other errors with the same details aren't handshake failures
+fn connect_error(err: qws::Error) -> WebSocketAdapterError {
+    match err.kind {
+        qws::ErrorKind::Ssl(_) => WebSocketAdapterError::Tls(err.to_string()),
+        qws::ErrorKind::Protocol => match handshake_status(&err.details) {
+            Some(status) => WebSocketAdapterError::HandshakeRejected(status),
+            None => WebSocketAdapterError::WebSocketError(err),
+        },
+        _ => WebSocketAdapterError::WebSocketError(err),
+    }
+}
+
+// Resolve the host up front, qws doesn't distinguish resolution errors from other IO errors
+fn resolve(addr: &str) -> Result<(), WebSocketAdapterError> {
+    let url =
+        Url::parse(addr).map_err(|err| WebSocketAdapterError::InvalidAddress(err.to_string()))?;
+    let addrs = url
+        .socket_addrs(|| match url.scheme() {
+            "wss" => Some(443),
+            _ => Some(80),
+        })
+        .map_err(|err| WebSocketAdapterError::AddressResolution(err.to_string()))?;
+    if addrs.is_empty() {
+        return Err(WebSocketAdapterError::AddressResolution(format!(
+            "No address found for {}",
+            url.host_str().unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
 impl Display for WebSocketAdapterError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         std::fmt::Debug::fmt(self, f)
@@ -148,7 +273,7 @@ impl SocketAdapter for WebSocketAdapter {
 
     fn on_closed<T>(&mut self, callback: T)
     where
-        T: Fn() + Send + 'static,
+        T: Fn(CloseEvent) + Send + 'static,
     {
         self.on_closed = Some(Box::new(callback))
     }
@@ -160,44 +285,118 @@ impl SocketAdapter for WebSocketAdapter {
         self.on_received = Some(Box::new(callback));
     }
 
-    fn is_connected(&self) -> bool {
-        todo!()
+    fn on_received_binary<T>(&mut self, callback: T)
+    where
+        T: Fn(Vec<u8>) + Send + 'static,
+    {
+        self.on_received_binary = Some(Box::new(callback));
+    }
+
+    fn on_connect_failed<T>(&mut self, callback: T)
+    where
+        T: Fn(WebSocketAdapterError) + Send + 'static,
+    {
+        self.on_connect_failed = Some(Box::new(callback));
     }
 
-    fn is_connecting(&self) -> bool {
-        todo!();
+    /// The receiver is called on the thread of the connection.
+    fn set_network_receiver(&mut self, receiver: Option<NetworkReceiver>) -> bool {
+        *self.network_receiver.lock().unwrap() = receiver;
+        true
     }
 
+    fn connection_state(&self) -> ConnectionState {
+        self.state.get()
+    }
+
+    /// Start the closing handshake. `on_closed` is called once the server acknowledged it. A connection that
+    /// is still being established is given up right away.
     fn close(&mut self) {
-        todo!()
+        if self.state.get() == ConnectionState::Connecting {
+            self.abort("Closed while connecting");
+            return;
+        }
+        if let Some(sender) = self.tx_message.get_mut().take() {
+            self.state.set(ConnectionState::Closing);
+            if let Err(err) = sender.close(CloseCode::Normal) {
+                error!("close: {}", err);
+            }
+        }
+    }
+
+    fn abort(&mut self, reason: &str) {
+        if let Some(sender) = self.tx_message.get_mut().take() {
+            // Stops the event loop of the connection thread
+            if let Err(err) = sender.shutdown() {
+                error!("abort: {}", err);
+            }
+        }
+        self.closed(CloseEvent::new(CloseEvent::ABNORMAL, reason));
     }
 
-    fn connect(&mut self, addr: &str, _timeout: i32) {
+    /// Connect to `addr` without blocking, the result is reported by [`tick`](SocketAdapter::tick). The
+    /// connection fails with [`WebSocketAdapterError::ConnectTimeout`] if it isn't established within `timeout`
+    /// seconds, including the resolution of the host. A `timeout` of zero or less waits indefinitely.
+    ///
+    /// A previous connection is closed first, without calling `on_closed`.
+    fn connect(&mut self, addr: &str, timeout: i32) {
+        if let Some(sender) = self.tx_message.get_mut().take() {
+            trace!("connect: Closing the previous connection");
+            // Its thread finishes once the server acknowledged the close, its messages are dropped with the receiver
+            if let Err(err) = sender.close(CloseCode::Normal) {
+                error!("connect: {}", err);
+            }
+        }
+
         let (tx, rx) = mpsc::channel();
-        let (tx_init, rx_init) = mpsc::channel();
+        // The previous connection delivers its remaining frames to the dropped channel instead of the receiver
+        let receiver = self.network_receiver.lock().unwrap().take();
+        self.network_receiver = Arc::new(Mutex::new(receiver));
+        let network_receiver = self.network_receiver.clone();
 
         let addr = addr.to_owned();
+        self.state.set(ConnectionState::Connecting);
+        self.connect_deadline.set(if timeout > 0 {
+            Some(Instant::now() + Duration::from_secs(timeout as u64))
+        } else {
+            None
+        });
 
         std::thread::spawn({
             move || {
-                qws::connect(addr, |out| {
-                    let response = tx_init.send(out);
-                    if let Err(err) = response {
-                        error!("connect (Thread): Error sending data {}", err);
+                if let Err(err) = resolve(&addr) {
+                    let _ = tx.send(Message::ConnectFailed(err));
+                    return;
+                }
+
+                let result = qws::connect(addr, |out| WebSocketClient {
+                    tx: tx.clone(),
+                    out,
+                    network_receiver: network_receiver.clone(),
+                });
+
+                // Ignored by `tick` if the handler already reported the close
+                let reason = match result {
+                    Ok(()) => "Connection lost".to_owned(),
+                    Err(err) => {
+                        let reason = err.to_string();
+                        let _ = tx.send(Message::ConnectFailed(err.into()));
+                        reason
                     }
-                    return WebSocketClient { tx: tx.clone() };
-                })
+                };
+                let _ = tx.send(Message::Closed(CloseEvent {
+                    code: CloseEvent::ABNORMAL,
+                    reason,
+                }));
             }
         });
 
-        self.tx_message = rx_init.recv().ok();
-
         self.rx_message = Some(rx);
     }
 
     fn send(&self, data: &str, _reliable: bool) -> Result<(), Self::Error> {
-        if let Some(ref sender) = self.tx_message {
-            println!("Sending {:?}", data);
+        if let Some(ref sender) = *self.tx_message.borrow() {
+            trace!("send: {:?}", data);
             return sender
                 .send(qws::Message::Text(data.to_owned()))
                 .map_err(|err| err.into());
@@ -206,6 +405,20 @@ impl SocketAdapter for WebSocketAdapter {
         Ok(())
     }
 
+    fn send_binary(
+        &self,
+        data: &[u8],
+        _reliable: bool,
+    ) -> Result<(), SendBinaryError<Self::Error>> {
+        if let Some(ref sender) = *self.tx_message.borrow() {
+            return sender
+                .send(qws::Message::Binary(data.to_vec()))
+                .map_err(|err| SendBinaryError::Adapter(err.into()));
+        }
+
+        Ok(())
+    }
+
     fn tick(&self) {
         if let Some(ref rx) = self.rx_message {
             while let Ok(data) = rx.try_recv() {
@@ -215,19 +428,48 @@ impl SocketAdapter for WebSocketAdapter {
                             cb(Ok(msg));
                         }
                     }
-                    Message::Connected => {
+                    Message::BinaryMessage(data) => {
+                        if let Some(ref cb) = self.on_received_binary {
+                            cb(data);
+                        }
+                    }
+                    Message::Connected(sender)
+                        if self.state.get() != ConnectionState::Connecting =>
+                    {
+                        trace!("tick: Ignoring connection that was given up on");
+                        if let Err(err) = sender.shutdown() {
+                            error!("tick: {}", err);
+                        }
+                    }
+                    Message::Connected(sender) => {
+                        *self.tx_message.borrow_mut() = Some(sender);
+                        self.state.set(ConnectionState::Connected);
+                        self.connect_deadline.set(None);
                         if let Some(ref cb) = self.on_connected {
                             cb();
                         }
                     }
+                    Message::Closed(event) => self.closed(event),
+                    Message::Error(err) if self.state.get() == ConnectionState::Connecting => {
+                        self.connect_failed(err.into());
+                    }
                     Message::Error(err) => {
                         if let Some(ref cb) = self.on_received {
                             cb(Err(err.into()));
                         }
                     }
+                    Message::ConnectFailed(err) => self.connect_failed(err),
                 }
             }
         }
+
+        if let Some(deadline) = self.connect_deadline.get() {
+            if self.state.get() == ConnectionState::Connecting && Instant::now() >= deadline {
+                // The connection thread is shut down once it connects, see `Message::Connected`
+                self.connect_deadline.set(None);
+                self.connect_failed(WebSocketAdapterError::ConnectTimeout);
+            }
+        }
     }
 }
 
@@ -243,6 +485,8 @@ mod test {
         socket_adapter.connect("ws://echo.websocket.org", 0);
         socket_adapter.on_received(move |data| println!("{:?}", data));
         sleep(Duration::from_secs(1));
+        // The connection is established by `tick`
+        socket_adapter.tick();
 
         println!("Sending!");
         socket_adapter.send("Hello", false).unwrap();
@@ -253,4 +497,41 @@ mod test {
         println!("Tick!");
         socket_adapter.tick();
     }
+
+    #[test]
+    fn test_handshake_status() {
+        assert_eq!(
+            handshake_status("Handshake failed with status 401"),
+            Some(401)
+        );
+        assert_eq!(handshake_status("Handshake failed."), None);
+        assert_eq!(
+            handshake_status("Frame payload of 200 bytes exceeds the limit"),
+            None
+        );
+        assert_eq!(
+            handshake_status("Received close frame with status 200"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_handshake_rejected_only_while_connecting() {
+        let failures = Arc::new(Mutex::new(vec![]));
+        let mut socket_adapter = WebSocketAdapter::new();
+        socket_adapter.on_connect_failed({
+            let failures = failures.clone();
+            move |err| failures.lock().unwrap().push(err.to_string())
+        });
+        let rejected =
+            || qws::Error::new(qws::ErrorKind::Protocol, "Handshake failed with status 401");
+
+        socket_adapter.connect_failed(rejected().into());
+        assert!(failures.lock().unwrap().is_empty());
+
+        socket_adapter.state.set(ConnectionState::Connecting);
+        socket_adapter.connect_failed(rejected().into());
+        let expected = WebSocketAdapterError::HandshakeRejected(401).to_string();
+        assert_eq!(*failures.lock().unwrap(), vec![expected]);
+    }
 }
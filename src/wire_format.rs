@@ -0,0 +1,727 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wire formats of the realtime socket.
+//!
+//! By default the socket exchanges JSON text frames with the server. With [`WireFormat::Protobuf`] the socket
+//! connects with `format=protobuf` and exchanges binary frames with the envelopes of Nakama's
+//! `realtime.proto`, see [`rtapi`](crate::rtapi). This is smaller on the wire and faster to parse, and match
+//! and party data is sent as raw bytes instead of base64.
+//!
+//! The format is selected with [`WebSocket::set_wire_format`](crate::WebSocket::set_wire_format) and takes
+//! effect on the next connect. The formats are implemented by the codecs of
+//! [`envelope_codec`](crate::envelope_codec).
+//!
+//! # Example
+//! ```
+//! # #![feature(async_closure)]
+//! # use nakama_rs::test_helpers::*;
+//! use nakama_rs::wire_format::WireFormat;
+//! # run_in_socket_example(async move |client, session, socket| {
+//! socket.set_wire_format(WireFormat::Protobuf);
+//! socket.connect(&session, true, -1).await.expect("Failed to connect");
+//! # Ok(())
+//! # });
+//! ```
+use crate::api::{ApiChannelMessage, ApiNotification, ApiNotificationList, ApiRpc};
+use crate::envelope_codec::{Payload, ServerMessage};
+use crate::rtapi;
+use crate::rtapi::envelope::Message;
+use crate::socket::{
+    Channel, ChannelMessageAck, ChannelPresenceEvent, Error, Match, MatchData, MatchPresenceEvent,
+    MatchmakerMatched, MatchmakerTicket, MatchmakerUser, Party, PartyClose, PartyData,
+    PartyJoinRequest, PartyLeader, PartyMatchmakerTicket, PartyPresenceEvent, Status,
+    StatusPresenceEvent, Stream, StreamData, StreamPresenceEvent, Timestamp, UserPresence,
+    WebSocketMessageEnvelope,
+};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use log::trace;
+use prost::Message as _;
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    /// JSON text frames.
+    Json,
+    /// Protobuf binary frames.
+    Protobuf,
+}
+
+impl Default for WireFormat {
+    fn default() -> Self {
+        WireFormat::Json
+    }
+}
+
+/// Encode a message sent by the client.
+pub(crate) fn encode_protobuf(envelope: &WebSocketMessageEnvelope) -> Vec<u8> {
+    rtapi::Envelope {
+        cid: envelope.cid.clone().unwrap_or_default(),
+        message: encode_message(envelope),
+    }
+    .encode_to_vec()
+}
+
+/// Decode a message sent by the server.
+pub(crate) fn decode_protobuf(bytes: &[u8]) -> Result<ServerMessage, prost::DecodeError> {
+    let envelope = rtapi::Envelope::decode(bytes)?;
+    let payload = match envelope.message {
+        Some(Message::Channel(message)) => Payload::Channel(message.into()),
+        Some(Message::ChannelMessage(message)) => Payload::ChannelMessage(message.into()),
+        Some(Message::ChannelMessageAck(message)) => Payload::ChannelMessageAck(message.into()),
+        Some(Message::ChannelPresenceEvent(message)) => {
+            Payload::ChannelPresenceEvent(message.into())
+        }
+        Some(Message::Error(message)) => Payload::Error(message.into()),
+        Some(Message::Match(message)) => Payload::Match(message.into()),
+        Some(Message::MatchData(message)) => Payload::MatchData(message.into()),
+        Some(Message::MatchPresenceEvent(message)) => Payload::MatchPresenceEvent(message.into()),
+        Some(Message::MatchmakerMatched(message)) => Payload::MatchmakerMatched(message.into()),
+        Some(Message::MatchmakerTicket(message)) => Payload::MatchmakerTicket(MatchmakerTicket {
+            ticket: message.ticket,
+        }),
+        Some(Message::Notifications(message)) => Payload::Notifications(ApiNotificationList {
+            cacheable_cursor: String::new(),
+            notifications: message.notifications.into_iter().map(Into::into).collect(),
+        }),
+        Some(Message::Rpc(message)) => Payload::Rpc(ApiRpc {
+            id: message.id,
+            payload: message.payload,
+            http_key: message.http_key,
+        }),
+        Some(Message::Status(message)) => Payload::Status(Status {
+            presences: presences(message.presences),
+        }),
+        Some(Message::StatusPresenceEvent(message)) => {
+            Payload::StatusPresenceEvent(StatusPresenceEvent {
+                joins: presences(message.joins),
+                leaves: presences(message.leaves),
+            })
+        }
+        Some(Message::StreamData(message)) => Payload::StreamData(message.into()),
+        Some(Message::StreamPresenceEvent(message)) => Payload::StreamPresenceEvent(message.into()),
+        Some(Message::Pong(_)) => Payload::Pong,
+        Some(Message::Party(message)) => Payload::Party(message.into()),
+        Some(Message::PartyClose(message)) => Payload::PartyClose(PartyClose {
+            party_id: message.party_id,
+        }),
+        Some(Message::PartyData(message)) => Payload::PartyData(message.into()),
+        Some(Message::PartyJoinRequest(message)) => Payload::PartyJoinRequest(PartyJoinRequest {
+            party_id: message.party_id,
+            presences: presences(message.presences),
+        }),
+        Some(Message::PartyLeader(message)) => Payload::PartyLeader(PartyLeader {
+            party_id: message.party_id,
+            presence: presence(message.presence),
+        }),
+        Some(Message::PartyMatchmakerTicket(message)) => {
+            Payload::PartyMatchmakerTicket(PartyMatchmakerTicket {
+                party_id: message.party_id,
+                ticket: message.ticket,
+            })
+        }
+        Some(Message::PartyPresenceEvent(message)) => {
+            Payload::PartyPresenceEvent(PartyPresenceEvent {
+                party_id: message.party_id,
+                joins: presences(message.joins),
+                leaves: presences(message.leaves),
+            })
+        }
+        Some(message) => {
+            trace!("decode_protobuf: Ignoring client message {:?}", message);
+            Payload::Empty
+        }
+        None => Payload::Empty,
+    };
+
+    Ok(ServerMessage {
+        cid: Some(envelope.cid).filter(|cid| !cid.is_empty()),
+        payload,
+    })
+}
+
+fn encode_message(envelope: &WebSocketMessageEnvelope) -> Option<Message> {
+    if let Some(ref message) = envelope.channel_join {
+        return Some(Message::ChannelJoin(rtapi::ChannelJoin {
+            target: message.target.clone(),
+            r#type: message.channel_type,
+            persistence: Some(message.persistence),
+            hidden: Some(message.hidden),
+        }));
+    }
+    if let Some(ref message) = envelope.channel_leave {
+        return Some(Message::ChannelLeave(rtapi::ChannelLeave {
+            channel_id: message.channel_id.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.channel_message_send {
+        return Some(Message::ChannelMessageSend(rtapi::ChannelMessageSend {
+            channel_id: message.channel_id.clone(),
+            content: message.content.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.channel_message_update {
+        return Some(Message::ChannelMessageUpdate(rtapi::ChannelMessageUpdate {
+            channel_id: message.channel_id.clone(),
+            message_id: message.message_id.clone(),
+            content: message.content.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.channel_message_remove {
+        return Some(Message::ChannelMessageRemove(rtapi::ChannelMessageRemove {
+            channel_id: message.channel_id.clone(),
+            message_id: message.message_id.clone(),
+        }));
+    }
+    if envelope.match_create.is_some() {
+        return Some(Message::MatchCreate(rtapi::MatchCreate::default()));
+    }
+    if let Some(ref message) = envelope.match_data_send {
+        return Some(Message::MatchDataSend(rtapi::MatchDataSend {
+            match_id: message.match_id.clone(),
+            op_code: message.op_code,
+            data: message.data.clone(),
+            presences: message.presences.iter().map(Into::into).collect(),
+            reliable: message.reliable,
+        }));
+    }
+    if let Some(ref message) = envelope.match_join {
+        let id = match (&message.match_id, &message.token) {
+            (Some(match_id), _) => Some(rtapi::match_join::Id::MatchId(match_id.clone())),
+            (None, Some(token)) => Some(rtapi::match_join::Id::Token(token.clone())),
+            (None, None) => None,
+        };
+        return Some(Message::MatchJoin(rtapi::MatchJoin {
+            metadata: message.metadata.clone(),
+            id,
+        }));
+    }
+    if let Some(ref message) = envelope.match_leave {
+        return Some(Message::MatchLeave(rtapi::MatchLeave {
+            match_id: message.match_id.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.matchmaker_add {
+        return Some(Message::MatchmakerAdd(rtapi::MatchmakerAdd {
+            min_count: message.min_count,
+            max_count: message.max_count,
+            query: message.query.clone(),
+            string_properties: message.string_properties.clone(),
+            numeric_properties: message.numeric_properties.clone(),
+            count_multiple: None,
+        }));
+    }
+    if let Some(ref message) = envelope.matchmaker_remove {
+        return Some(Message::MatchmakerRemove(rtapi::MatchmakerRemove {
+            ticket: message.ticket.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.rpc {
+        return Some(Message::Rpc(rtapi::api::Rpc {
+            id: message.id.clone(),
+            payload: message.payload.clone(),
+            http_key: message.http_key.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.status_follow {
+        return Some(Message::StatusFollow(rtapi::StatusFollow {
+            user_ids: message.user_ids.clone(),
+            usernames: message.usernames.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.status_unfollow {
+        return Some(Message::StatusUnfollow(rtapi::StatusUnfollow {
+            user_ids: message.user_ids.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.status_update {
+        return Some(Message::StatusUpdate(rtapi::StatusUpdate {
+            status: Some(message.status.clone()),
+        }));
+    }
+    if envelope.ping.is_some() {
+        return Some(Message::Ping(rtapi::Ping {}));
+    }
+    if let Some(ref message) = envelope.party_create {
+        return Some(Message::PartyCreate(rtapi::PartyCreate {
+            open: message.open,
+            max_size: message.max_size,
+        }));
+    }
+    if let Some(ref message) = envelope.party_join {
+        return Some(Message::PartyJoin(rtapi::PartyJoin {
+            party_id: message.party_id.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.party_leave {
+        return Some(Message::PartyLeave(rtapi::PartyLeave {
+            party_id: message.party_id.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.party_promote {
+        return Some(Message::PartyPromote(rtapi::PartyPromote {
+            party_id: message.party_id.clone(),
+            presence: Some((&message.presence).into()),
+        }));
+    }
+    if let Some(ref message) = envelope.party_accept {
+        return Some(Message::PartyAccept(rtapi::PartyAccept {
+            party_id: message.party_id.clone(),
+            presence: Some((&message.presence).into()),
+        }));
+    }
+    if let Some(ref message) = envelope.party_remove {
+        return Some(Message::PartyRemove(rtapi::PartyRemove {
+            party_id: message.party_id.clone(),
+            presence: Some((&message.presence).into()),
+        }));
+    }
+    if let Some(ref message) = envelope.party_close {
+        return Some(Message::PartyClose(rtapi::PartyClose {
+            party_id: message.party_id.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.party_join_request_list {
+        return Some(Message::PartyJoinRequestList(rtapi::PartyJoinRequestList {
+            party_id: message.party_id.clone(),
+        }));
+    }
+    if let Some(ref message) = envelope.party_matchmaker_add {
+        return Some(Message::PartyMatchmakerAdd(rtapi::PartyMatchmakerAdd {
+            party_id: message.party_id.clone(),
+            min_count: message.min_count,
+            max_count: message.max_count,
+            query: message.query.clone(),
+            string_properties: message.string_properties.clone(),
+            numeric_properties: message.numeric_properties.clone(),
+            count_multiple: None,
+        }));
+    }
+    if let Some(ref message) = envelope.party_matchmaker_remove {
+        return Some(Message::PartyMatchmakerRemove(
+            rtapi::PartyMatchmakerRemove {
+                party_id: message.party_id.clone(),
+                ticket: message.ticket.clone(),
+            },
+        ));
+    }
+    if let Some(ref message) = envelope.party_data_send {
+        return Some(Message::PartyDataSend(rtapi::PartyDataSend {
+            party_id: message.party_id.clone(),
+            op_code: message.op_code,
+            data: message.data.clone(),
+        }));
+    }
+    None
+}
+
+/// Out of range timestamps are passed on as an empty string, like a missing one.
+fn timestamp(timestamp: Option<prost_types::Timestamp>) -> String {
+    timestamp
+        .and_then(|timestamp| {
+            let nanos = u32::try_from(timestamp.nanos).ok()?;
+            Utc.timestamp_opt(timestamp.seconds, nanos).single()
+        })
+        .map(|timestamp| timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        .unwrap_or_default()
+}
+
+fn presence(presence: Option<rtapi::UserPresence>) -> UserPresence {
+    presence.map(Into::into).unwrap_or_default()
+}
+
+fn presences(presences: Vec<rtapi::UserPresence>) -> Vec<UserPresence> {
+    presences.into_iter().map(Into::into).collect()
+}
+
+impl From<&UserPresence> for rtapi::UserPresence {
+    fn from(presence: &UserPresence) -> Self {
+        rtapi::UserPresence {
+            user_id: presence.user_id.clone(),
+            session_id: presence.session_id.clone(),
+            username: presence.username.clone(),
+            persistence: presence.persistence,
+            status: Some(presence.status.clone()).filter(|status| !status.is_empty()),
+        }
+    }
+}
+
+impl From<rtapi::UserPresence> for UserPresence {
+    fn from(presence: rtapi::UserPresence) -> Self {
+        UserPresence {
+            persistence: presence.persistence,
+            session_id: presence.session_id,
+            status: presence.status.unwrap_or_default(),
+            username: presence.username,
+            user_id: presence.user_id,
+        }
+    }
+}
+
+impl From<rtapi::Stream> for Stream {
+    fn from(stream: rtapi::Stream) -> Self {
+        Stream {
+            mode: stream.mode,
+            subject: stream.subject,
+            subcontext: stream.subcontext,
+            label: stream.label,
+        }
+    }
+}
+
+impl From<rtapi::Channel> for Channel {
+    fn from(channel: rtapi::Channel) -> Self {
+        Channel {
+            id: channel.id,
+            presences: presences(channel.presences),
+            _self: presence(channel.self_),
+            room_name: channel.room_name,
+            group_id: channel.group_id,
+            user_id_one: channel.user_id_one,
+            user_id_two: channel.user_id_two,
+        }
+    }
+}
+
+impl From<rtapi::api::ChannelMessage> for ApiChannelMessage {
+    fn from(message: rtapi::api::ChannelMessage) -> Self {
+        ApiChannelMessage {
+            channel_id: message.channel_id,
+            code: message.code.unwrap_or_default(),
+            content: message.content,
+            create_time: timestamp(message.create_time),
+            group_id: message.group_id,
+            message_id: message.message_id,
+            persistent: message.persistent.unwrap_or_default(),
+            room_name: message.room_name,
+            sender_id: message.sender_id,
+            update_time: timestamp(message.update_time),
+            user_id_one: message.user_id_one,
+            user_id_two: message.user_id_two,
+            username: message.username,
+        }
+    }
+}
+
+impl From<rtapi::ChannelMessageAck> for ChannelMessageAck {
+    fn from(ack: rtapi::ChannelMessageAck) -> Self {
+        ChannelMessageAck {
+            channel_id: ack.channel_id,
+            message_id: ack.message_id,
+            code: ack.code.unwrap_or_default(),
+            username: ack.username,
+            create_time: Timestamp::from(timestamp(ack.create_time)),
+            update_time: Timestamp::from(timestamp(ack.update_time)),
+            persistent: ack.persistent.unwrap_or_default(),
+            room_name: ack.room_name,
+            group_id: ack.group_id,
+            user_id_one: ack.user_id_one,
+            user_id_two: ack.user_id_two,
+        }
+    }
+}
+
+impl From<rtapi::ChannelPresenceEvent> for ChannelPresenceEvent {
+    fn from(event: rtapi::ChannelPresenceEvent) -> Self {
+        ChannelPresenceEvent {
+            channel_id: event.channel_id,
+            joins: presences(event.joins),
+            leaves: presences(event.leaves),
+            room_name: event.room_name,
+            group_id: event.group_id,
+            user_id_one: event.user_id_one,
+            user_id_two: event.user_id_two,
+        }
+    }
+}
+
+impl From<rtapi::Error> for Error {
+    fn from(error: rtapi::Error) -> Self {
+        Error {
+            code: error.code,
+            message: error.message,
+            context: error.context,
+        }
+    }
+}
+
+impl From<rtapi::Match> for Match {
+    fn from(new_match: rtapi::Match) -> Self {
+        Match {
+            match_id: new_match.match_id,
+            authoritative: new_match.authoritative,
+            label: new_match.label.unwrap_or_default(),
+            size: new_match.size,
+            presences: presences(new_match.presences),
+            _self: presence(new_match.self_),
+        }
+    }
+}
+
+impl From<rtapi::MatchData> for MatchData {
+    fn from(data: rtapi::MatchData) -> Self {
+        MatchData {
+            match_id: data.match_id,
+            presence: presence(data.presence),
+            op_code: data.op_code,
+            data: data.data,
+            reliable: data.reliable,
+        }
+    }
+}
+
+impl From<rtapi::MatchPresenceEvent> for MatchPresenceEvent {
+    fn from(event: rtapi::MatchPresenceEvent) -> Self {
+        MatchPresenceEvent {
+            match_id: event.match_id,
+            joins: presences(event.joins),
+            leaves: presences(event.leaves),
+        }
+    }
+}
+
+impl From<rtapi::matchmaker_matched::MatchmakerUser> for MatchmakerUser {
+    fn from(user: rtapi::matchmaker_matched::MatchmakerUser) -> Self {
+        MatchmakerUser {
+            presence: presence(user.presence),
+            party_id: user.party_id,
+            string_properties: user.string_properties,
+            numeric_properties: user.numeric_properties,
+        }
+    }
+}
+
+impl From<rtapi::MatchmakerMatched> for MatchmakerMatched {
+    fn from(matched: rtapi::MatchmakerMatched) -> Self {
+        let (match_id, token) = match matched.id {
+            Some(rtapi::matchmaker_matched::Id::MatchId(match_id)) => (Some(match_id), None),
+            Some(rtapi::matchmaker_matched::Id::Token(token)) => (None, Some(token)),
+            None => (None, None),
+        };
+        MatchmakerMatched {
+            ticket: matched.ticket,
+            match_id,
+            token,
+            users: matched.users.into_iter().map(Into::into).collect(),
+            _self: matched.self_.map(Into::into).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<rtapi::api::Notification> for ApiNotification {
+    fn from(notification: rtapi::api::Notification) -> Self {
+        ApiNotification {
+            code: notification.code,
+            content: notification.content,
+            create_time: timestamp(notification.create_time),
+            id: notification.id,
+            persistent: notification.persistent,
+            sender_id: notification.sender_id,
+            subject: notification.subject,
+        }
+    }
+}
+
+impl From<rtapi::Party> for Party {
+    fn from(party: rtapi::Party) -> Self {
+        Party {
+            party_id: party.party_id,
+            open: party.open,
+            max_size: party.max_size,
+            _self: presence(party.self_),
+            leader: presence(party.leader),
+            presences: presences(party.presences),
+        }
+    }
+}
+
+impl From<rtapi::PartyData> for PartyData {
+    fn from(data: rtapi::PartyData) -> Self {
+        PartyData {
+            party_id: data.party_id,
+            presence: presence(data.presence),
+            op_code: data.op_code,
+            data: data.data,
+        }
+    }
+}
+
+impl From<rtapi::StreamData> for StreamData {
+    fn from(data: rtapi::StreamData) -> Self {
+        StreamData {
+            stream: data.stream.map(Into::into).unwrap_or_default(),
+            sender: presence(data.sender),
+            data: data.data,
+            reliable: data.reliable,
+        }
+    }
+}
+
+impl From<rtapi::StreamPresenceEvent> for StreamPresenceEvent {
+    fn from(event: rtapi::StreamPresenceEvent) -> Self {
+        StreamPresenceEvent {
+            stream: event.stream.map(Into::into).unwrap_or_default(),
+            joins: presences(event.joins),
+            leaves: presences(event.leaves),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::socket::{ChannelJoin, MatchDataSend, PartyDataSend};
+    use nanoserde::SerJson;
+
+    #[test]
+    fn test_encode_match_data_send() {
+        let envelope = WebSocketMessageEnvelope {
+            cid: Some("7".to_owned()),
+            match_data_send: Some(MatchDataSend {
+                match_id: "match".to_owned(),
+                op_code: 3,
+                data: vec![0, 1, 255],
+                presences: vec![],
+                reliable: true,
+            }),
+            ..Default::default()
+        };
+
+        let decoded = rtapi::Envelope::decode(&encode_protobuf(&envelope)[..]).unwrap();
+        assert_eq!(decoded.cid, "7");
+        match decoded.message {
+            Some(Message::MatchDataSend(message)) => {
+                assert_eq!(message.match_id, "match");
+                assert_eq!(message.op_code, 3);
+                assert_eq!(message.data, vec![0, 1, 255]);
+                assert!(message.reliable);
+            }
+            other => panic!("Expected match data, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encode_party_data_send() {
+        let envelope = WebSocketMessageEnvelope {
+            party_data_send: Some(PartyDataSend {
+                party_id: "party".to_owned(),
+                op_code: 2,
+                data: vec![0, 1, 255],
+            }),
+            ..Default::default()
+        };
+
+        let decoded = rtapi::Envelope::decode(&encode_protobuf(&envelope)[..]).unwrap();
+        match decoded.message {
+            Some(Message::PartyDataSend(message)) => assert_eq!(message.data, vec![0, 1, 255]),
+            other => panic!("Expected party data, got {:?}", other),
+        }
+        // Only the JSON envelope encodes the data as base64
+        assert!(envelope.serialize_json().contains(r#""data":"AAH/""#));
+    }
+
+    #[test]
+    fn test_encode_channel_join() {
+        let envelope = WebSocketMessageEnvelope {
+            channel_join: Some(ChannelJoin {
+                hidden: false,
+                persistence: true,
+                target: "room".to_owned(),
+                channel_type: 1,
+            }),
+            ..Default::default()
+        };
+
+        let decoded = rtapi::Envelope::decode(&encode_protobuf(&envelope)[..]).unwrap();
+        assert_eq!(
+            decoded.message,
+            Some(Message::ChannelJoin(rtapi::ChannelJoin {
+                target: "room".to_owned(),
+                r#type: 1,
+                persistence: Some(true),
+                hidden: Some(false),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_decode_match_data() {
+        let bytes = rtapi::Envelope {
+            cid: String::new(),
+            message: Some(Message::MatchData(rtapi::MatchData {
+                match_id: "match".to_owned(),
+                presence: Some(rtapi::UserPresence {
+                    user_id: "user".to_owned(),
+                    session_id: "session".to_owned(),
+                    username: "name".to_owned(),
+                    persistence: false,
+                    status: None,
+                }),
+                op_code: 1,
+                data: vec![1, 2, 3],
+                reliable: false,
+            })),
+        }
+        .encode_to_vec();
+
+        let message = decode_protobuf(&bytes).unwrap();
+        assert!(message.cid.is_none());
+        let match_data = match message.payload {
+            Payload::MatchData(match_data) => match_data,
+            other => panic!("Expected match data, got {:?}", other),
+        };
+        assert_eq!(match_data.data, vec![1, 2, 3]);
+        assert_eq!(match_data.presence.username, "name");
+    }
+
+    #[test]
+    fn test_decode_timestamp() {
+        let bytes = rtapi::Envelope {
+            cid: "1".to_owned(),
+            message: Some(Message::ChannelMessage(rtapi::api::ChannelMessage {
+                channel_id: "channel".to_owned(),
+                create_time: Some(prost_types::Timestamp {
+                    seconds: 1_600_000_000,
+                    nanos: 0,
+                }),
+                ..Default::default()
+            })),
+        }
+        .encode_to_vec();
+
+        let envelope = decode_protobuf(&bytes).unwrap().into_envelope();
+        assert_eq!(envelope.cid.as_deref(), Some("1"));
+        assert_eq!(
+            envelope.channel_message.unwrap().create_time,
+            "2020-09-13T12:26:40Z"
+        );
+    }
+
+    #[test]
+    fn test_decode_out_of_range_timestamp() {
+        for (seconds, nanos) in [(1_600_000_000, -1), (i64::MAX, 0)] {
+            let bytes = rtapi::Envelope {
+                cid: String::new(),
+                message: Some(Message::ChannelMessage(rtapi::api::ChannelMessage {
+                    create_time: Some(prost_types::Timestamp { seconds, nanos }),
+                    ..Default::default()
+                })),
+            }
+            .encode_to_vec();
+
+            let envelope = decode_protobuf(&bytes).unwrap().into_envelope();
+            assert_eq!(envelope.channel_message.unwrap().create_time, "");
+        }
+    }
+}
@@ -13,8 +13,12 @@
 // limitations under the License.
 
 use futures::executor::block_on;
+use nakama_rs::account_upgrade::{ConflictResolution, UpgradeResult};
+use nakama_rs::auth_request::{AuthProvider, AuthRequest};
 use nakama_rs::client::Client;
 use nakama_rs::default_client::DefaultClient;
+use nakama_rs::session::Session;
+use nakama_rs::test_helpers::{RecordingAdapter, AUTH_TOKEN};
 use std::collections::HashMap;
 
 #[test]
@@ -123,3 +127,160 @@ fn test_unlink_email() {
     println!("Result: {:?}", result);
     assert_eq!(result.is_err(), true)
 }
+
+#[test]
+fn test_authenticate_with_auth_request() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let result = block_on(async {
+        let mut request = AuthRequest::new(AuthProvider::device("authrequestdeviceid"));
+        request
+            .username("AuthRequestUser")
+            .add_var("Hello", "World!");
+        let session = client.authenticate(&request).await?;
+
+        let email = AuthRequest::new(AuthProvider::email("authrequest@user.com", "userspassword"));
+        client.link(&session, &email).await?;
+        client.unlink(&session, &email).await.map(|_| session)
+    });
+
+    println!("Result: {:?}", result);
+    let session = result.unwrap();
+    assert_eq!(session.username(), "AuthRequestUser");
+    assert_eq!(session.vars().get("Hello"), Some(&"World!".to_owned()));
+}
+
+#[test]
+fn test_upgrade_account_conflict() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let suffix = chrono::Utc::now().timestamp_millis();
+    let email = format!("upgrade{}@user.com", suffix);
+    let guest_device_id = format!("upgradeguest{}", suffix);
+    let result = block_on(async {
+        let existing = client
+            .authenticate_device(
+                &format!("upgradeexisting{}", suffix),
+                None,
+                true,
+                HashMap::new(),
+            )
+            .await?;
+        client
+            .link_email(&existing, &email, "userspassword")
+            .await?;
+
+        // A guest account with only a device id
+        let guest = client
+            .authenticate_device(&guest_device_id, None, true, HashMap::new())
+            .await?;
+
+        let request = AuthRequest::new(AuthProvider::email(&email, "userspassword"));
+        let conflict = match client.upgrade_account(&guest, &request).await? {
+            UpgradeResult::Conflict(conflict) => conflict,
+            UpgradeResult::Linked => panic!("Expected a conflict"),
+        };
+        assert_eq!(conflict.current_account.user.id, guest.user_id());
+        assert_eq!(conflict.current_account.devices.len(), 1);
+        assert!(conflict.current_account.custom_id.is_empty());
+        assert_eq!(conflict.existing_account.user.id, existing.user_id());
+
+        let guest_user_id = guest.user_id();
+        let placeholder = client
+            .resolve_account_conflict(&guest, conflict, ConflictResolution::SwitchToExisting)
+            .await?
+            .expect("Expected the placeholder custom id of the guest account");
+        assert_eq!(guest.user_id(), existing.user_id());
+
+        // The guest account can still be signed in to
+        let previous_guest = client
+            .authenticate_custom(&placeholder, None, false, HashMap::new())
+            .await?;
+        assert_eq!(previous_guest.user_id(), guest_user_id);
+
+        client
+            .authenticate_device(&guest_device_id, None, false, HashMap::new())
+            .await
+            .map(|session| (existing, session))
+    });
+
+    println!("Result: {:?}", result);
+    let (existing, session) = result.unwrap();
+    assert_eq!(session.user_id(), existing.user_id());
+}
+
+#[test]
+fn test_resolve_account_conflict_keeps_device_on_failure() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let suffix = chrono::Utc::now().timestamp_millis();
+    let email = format!("rollback{}@user.com", suffix);
+    let guest_device_id = format!("rollbackguest{}", suffix);
+    let result = block_on(async {
+        let existing = client
+            .authenticate_device(
+                &format!("rollbackexisting{}", suffix),
+                None,
+                true,
+                HashMap::new(),
+            )
+            .await?;
+        client
+            .link_email(&existing, &email, "userspassword")
+            .await?;
+
+        let guest = client
+            .authenticate_device(&guest_device_id, None, true, HashMap::new())
+            .await?;
+
+        let request = AuthRequest::new(AuthProvider::email(&email, "userspassword"));
+        let conflict = match client.upgrade_account(&guest, &request).await? {
+            UpgradeResult::Conflict(conflict) => conflict,
+            UpgradeResult::Linked => panic!("Expected a conflict"),
+        };
+        // Linking the device to the existing account fails with an ended session
+        client.session_logout(conflict.existing_session()).await?;
+
+        let resolved = client
+            .resolve_account_conflict(&guest, conflict, ConflictResolution::SwitchToExisting)
+            .await;
+        assert!(resolved.is_err());
+
+        client
+            .authenticate_device(&guest_device_id, None, false, HashMap::new())
+            .await
+            .map(|session| (guest, session))
+    });
+
+    println!("Result: {:?}", result);
+    let (guest, session) = result.unwrap();
+    assert_eq!(session.user_id(), guest.user_id());
+}
+
+#[test]
+fn test_authenticate_steam_uses_steam_endpoint() {
+    let adapter = RecordingAdapter::default();
+    let client = DefaultClient::new(adapter.clone(), "defaultkey", "");
+
+    let result = block_on(client.authenticate_steam("steamtoken", None, true, HashMap::new()));
+    assert!(result.is_err());
+    let requests = adapter.requests.lock().unwrap();
+    assert_eq!(requests[0].urlpath, "/v2/account/authenticate/steam");
+}
+
+#[test]
+fn test_link_facebook_sends_import() {
+    let adapter = RecordingAdapter::default();
+    let client = DefaultClient::new(adapter.clone(), "defaultkey", "");
+    let session = Session::new(AUTH_TOKEN, "");
+
+    block_on(async {
+        for import in [None, Some(true), Some(false)] {
+            let result = client.link_facebook(&session, "facebooktoken", import).await;
+            assert!(result.is_err());
+        }
+    });
+    let requests = adapter.requests.lock().unwrap();
+    assert_eq!(requests[0].urlpath, "/v2/account/link/facebook");
+    // The server default applies without the parameter
+    assert!(!requests[0].query_params.contains("sync="));
+    assert!(requests[1].query_params.contains("sync=true"));
+    assert!(requests[2].query_params.contains("sync=false"));
+}
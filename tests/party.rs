@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use futures::executor::block_on;
+use futures::StreamExt;
 use log::LevelFilter;
 use nakama_rs::socket::Socket;
 use nakama_rs::test_helpers;
@@ -129,3 +130,29 @@ fn test_send_party_data() {
         println!("{:?}", rx.recv());
     })
 }
+
+#[test]
+fn test_party_presences_as_stream() {
+    block_on(async {
+        let (socket1, socket2, ..) =
+            test_helpers::sockets_with_users("partyuserone", "partyusertwo").await;
+        let mut presences = socket1.party_presences();
+
+        let party = socket1.create_party(true, 2).await.unwrap();
+        // The leader joins first
+        presences.next().await.unwrap().unwrap();
+
+        socket2.join_party(&party.party_id).await.unwrap();
+        let joined = presences
+            .next()
+            .await
+            .unwrap()
+            .expect("Missed party presence");
+        assert_eq!(joined.party_id, party.party_id);
+        assert_eq!(joined.joins.len(), 1);
+
+        // The stream ends once the socket is closed
+        socket1.close().await.unwrap();
+        while presences.next().await.is_some() {}
+    })
+}
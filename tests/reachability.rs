@@ -0,0 +1,71 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use futures::executor::block_on;
+use nakama_rs::api::Authentication;
+use nakama_rs::client::Client;
+use nakama_rs::default_client::DefaultClient;
+use nakama_rs::reachability::{Reachability, ReachabilityMonitor};
+use nakama_rs::test_helpers::RecordingAdapter;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn test_healthcheck() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let result = block_on(async { client.healthcheck().await });
+
+    println!("Result: {:?}", result);
+    assert_eq!(result.is_ok(), true)
+}
+
+#[test]
+fn test_healthcheck_sends_no_authorization() {
+    let adapter = RecordingAdapter::default();
+    let client = DefaultClient::new(adapter.clone(), "defaultkey", "");
+    assert!(block_on(client.healthcheck()).is_err());
+
+    let requests = adapter.requests.lock().unwrap();
+    assert!(matches!(requests[0].authentication, Authentication::None));
+}
+
+#[test]
+fn test_reachability_monitor() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let monitor = ReachabilityMonitor::new(&client, Duration::from_secs(30));
+    let changes = Arc::new(Mutex::new(vec![]));
+    monitor.on_changed({
+        let changes = changes.clone();
+        move |change| changes.lock().unwrap().push(change)
+    });
+
+    assert_eq!(monitor.reachability(), Reachability::Unknown);
+    assert_eq!(block_on(monitor.probe()), Reachability::Up);
+    assert!(monitor.latency().is_some());
+    assert_eq!(block_on(monitor.probe()), Reachability::Up);
+
+    let changes = changes.lock().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].previous, Reachability::Unknown);
+    assert_eq!(changes[0].current, Reachability::Up);
+}
+
+#[test]
+fn test_reachability_monitor_down() {
+    let client = DefaultClient::new_with_adapter("http://127.0.0.1", 1, "defaultkey", "");
+    let monitor = ReachabilityMonitor::new(&client, Duration::from_secs(30));
+
+    assert_eq!(block_on(monitor.probe()), Reachability::Down);
+    assert_eq!(monitor.latency(), None);
+}
@@ -0,0 +1,252 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use futures::executor::block_on;
+use futures::StreamExt;
+use nakama_rs::client::Client;
+use nakama_rs::default_client::DefaultClient;
+use nakama_rs::http_adapter::RestHttpAdapter;
+use nakama_rs::reconnect::{Membership, ReconnectPolicy, ReconnectReport};
+use nakama_rs::socket::Socket;
+use nakama_rs::test_helpers::{tick_socket, TestSocketAdapter};
+use nakama_rs::web_socket::WebSocket;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+// A socket that is ticked on its own thread and reconnects quickly. The reconnect reports are sent to the
+// receiver.
+fn reconnecting_socket(
+    client: &DefaultClient<RestHttpAdapter>,
+) -> (
+    WebSocket<TestSocketAdapter>,
+    Arc<AtomicBool>,
+    mpsc::Receiver<ReconnectReport>,
+) {
+    let adapter = TestSocketAdapter::new();
+    let drop_connection = adapter.drop_connection_flag();
+    let socket = WebSocket::new(adapter);
+    tick_socket(&socket);
+
+    socket.enable_auto_reconnect(
+        client,
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            ..ReconnectPolicy::default()
+        },
+    );
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    socket.on_reconnected(move |report| {
+        tx.lock()
+            .unwrap()
+            .send(report)
+            .expect("Failed to send reconnect report");
+    });
+
+    (socket, drop_connection, rx)
+}
+
+fn wait_for_join(joined: &mpsc::Receiver<String>, user_id: &str) {
+    loop {
+        let joined = joined
+            .recv_timeout(Duration::from_secs(10))
+            .expect("User didn't join the party");
+        if joined == user_id {
+            return;
+        }
+    }
+}
+
+#[test]
+fn test_reconnect_restores_chat() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let (socket, drop_connection, rx) = reconnecting_socket(&client);
+
+    block_on(async {
+        let session = client
+            .authenticate_device("reconnecttestuser", None, true, HashMap::new())
+            .await
+            .expect("Failed to authenticate");
+        socket
+            .connect(&session, true, 10)
+            .await
+            .expect("Failed to connect");
+        socket
+            .join_chat("ReconnectRoom", 1, false, false)
+            .await
+            .expect("Failed to join chat");
+    });
+
+    drop_connection.store(true, Ordering::SeqCst);
+
+    let report = rx
+        .recv_timeout(Duration::from_secs(10))
+        .expect("Socket didn't reconnect");
+    assert_eq!(report.attempts, 1);
+    assert!(report.failed.is_empty());
+    assert!(socket.is_connected());
+}
+
+#[test]
+fn test_reconnect_restores_follow_and_party() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let (socket, drop_connection, rx) = reconnecting_socket(&client);
+    let mut leader_socket = WebSocket::new_with_adapter();
+    tick_socket(&leader_socket);
+
+    let (tx_joined, rx_joined) = mpsc::channel();
+    let tx_joined = Mutex::new(tx_joined);
+    leader_socket.on_received_party_presence(move |presences| {
+        for presence in presences.joins {
+            tx_joined.lock().unwrap().send(presence.user_id).unwrap();
+        }
+    });
+
+    let user_id = block_on(async {
+        let leader = client
+            .authenticate_device("reconnectpartyleader", None, true, HashMap::new())
+            .await
+            .expect("Failed to authenticate");
+        leader_socket
+            .connect(&leader, true, 10)
+            .await
+            .expect("Failed to connect");
+        let party = leader_socket
+            .create_party(true, 2)
+            .await
+            .expect("Failed to create party");
+
+        let session = client
+            .authenticate_device("reconnectpartymember", None, true, HashMap::new())
+            .await
+            .expect("Failed to authenticate");
+        socket
+            .connect(&session, true, 10)
+            .await
+            .expect("Failed to connect");
+        socket
+            .follow_users(&[], &[&leader.username()])
+            .await
+            .expect("Failed to follow leader");
+        socket
+            .join_party(&party.party_id)
+            .await
+            .expect("Failed to join party");
+        session.user_id()
+    });
+    wait_for_join(&rx_joined, &user_id);
+
+    drop_connection.store(true, Ordering::SeqCst);
+
+    let report = rx
+        .recv_timeout(Duration::from_secs(10))
+        .expect("Socket didn't reconnect");
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+    wait_for_join(&rx_joined, &user_id);
+}
+
+#[test]
+fn test_reconnect_reports_failed_restore() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let (socket, drop_connection, rx) = reconnecting_socket(&client);
+
+    block_on(async {
+        let session = client
+            .authenticate_device("reconnectgroupuser", None, true, HashMap::new())
+            .await
+            .expect("Failed to authenticate");
+        let group = client
+            .create_group(
+                &session,
+                &format!("ReconnectGroup{}", std::process::id()),
+                None,
+                None,
+                None,
+                Some(true),
+                None,
+            )
+            .await
+            .expect("Failed to create group");
+        socket
+            .connect(&session, true, 10)
+            .await
+            .expect("Failed to connect");
+        socket
+            .join_chat(&group.id, 3, false, false)
+            .await
+            .expect("Failed to join group chat");
+        // The group chat can't be joined again after the reconnect
+        client
+            .delete_group(&session, &group.id)
+            .await
+            .expect("Failed to delete group");
+    });
+
+    drop_connection.store(true, Ordering::SeqCst);
+
+    let report = rx
+        .recv_timeout(Duration::from_secs(10))
+        .expect("Socket didn't reconnect");
+    assert_eq!(report.failed.len(), 1);
+    match report.failed[0].membership {
+        Membership::Chat(ref join) => assert_eq!(join.channel_type, 3),
+        ref other => panic!("Expected the group chat to fail, got {:?}", other),
+    }
+    assert!(socket.is_connected());
+}
+
+#[test]
+fn test_stream_continues_across_reconnect() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let (socket, drop_connection, rx) = reconnecting_socket(&client);
+    let mut messages = socket.channel_messages();
+
+    let channel = block_on(async {
+        let session = client
+            .authenticate_device("reconnectstreamuser", None, true, HashMap::new())
+            .await
+            .expect("Failed to authenticate");
+        socket
+            .connect(&session, true, 10)
+            .await
+            .expect("Failed to connect");
+        socket
+            .join_chat("ReconnectStreamRoom", 1, false, false)
+            .await
+            .expect("Failed to join chat")
+    });
+
+    drop_connection.store(true, Ordering::SeqCst);
+    rx.recv_timeout(Duration::from_secs(10))
+        .expect("Socket didn't reconnect");
+
+    block_on(async {
+        socket
+            .write_chat_message(&channel.id, r#"{"text":"after reconnect"}"#)
+            .await
+            .expect("Failed to write message");
+        let message = messages
+            .next()
+            .await
+            .expect("Stream ended on reconnect")
+            .expect("Stream lagged");
+        assert!(message.content.contains("after reconnect"));
+
+        // Closing the socket ends the stream
+        socket.close().await.expect("Failed to close");
+        assert!(messages.next().await.is_none());
+    });
+}
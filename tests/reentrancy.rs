@@ -0,0 +1,295 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use futures::executor::block_on;
+use futures::task::noop_waker_ref;
+use nakama_rs::dispatcher::ImmediateDispatcher;
+use nakama_rs::session::Session;
+use nakama_rs::socket::Socket;
+use nakama_rs::socket_adapter::{CloseEvent, ConnectionState, SocketAdapter};
+use nakama_rs::test_helpers::AUTH_TOKEN;
+use nakama_rs::web_socket::WebSocket;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+#[derive(Debug)]
+struct FakeError;
+
+impl fmt::Display for FakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FakeError")
+    }
+}
+
+impl std::error::Error for FakeError {}
+
+enum FakeEvent {
+    Connected,
+    Received(String),
+    Closed(CloseEvent),
+}
+
+// Dispatches the events pushed by the test on tick, without a server
+struct FakeAdapter {
+    events: Arc<Mutex<VecDeque<FakeEvent>>>,
+    sent: Arc<Mutex<Vec<String>>>,
+    state: Cell<ConnectionState>,
+    on_connected: Option<Box<dyn Fn() + Send + 'static>>,
+    on_closed: Option<Box<dyn Fn(CloseEvent) + Send + 'static>>,
+    on_received: Option<Box<dyn Fn(Result<String, FakeError>) + Send + 'static>>,
+}
+
+impl SocketAdapter for FakeAdapter {
+    type Error = FakeError;
+
+    fn on_connected<T>(&mut self, callback: T)
+    where
+        T: Fn() + Send + 'static,
+    {
+        self.on_connected = Some(Box::new(callback));
+    }
+
+    fn on_closed<T>(&mut self, callback: T)
+    where
+        T: Fn(CloseEvent) + Send + 'static,
+    {
+        self.on_closed = Some(Box::new(callback));
+    }
+
+    fn on_received<T>(&mut self, callback: T)
+    where
+        T: Fn(Result<String, Self::Error>) + Send + 'static,
+    {
+        self.on_received = Some(Box::new(callback));
+    }
+
+    fn on_connect_failed<T>(&mut self, _callback: T)
+    where
+        T: Fn(Self::Error) + Send + 'static,
+    {
+    }
+
+    fn connection_state(&self) -> ConnectionState {
+        self.state.get()
+    }
+
+    fn close(&mut self) {
+        self.abort("Closed by client");
+    }
+
+    fn abort(&mut self, reason: &str) {
+        self.events
+            .lock()
+            .unwrap()
+            .push_back(FakeEvent::Closed(CloseEvent::new(
+                CloseEvent::NORMAL,
+                reason,
+            )));
+    }
+
+    fn connect(&mut self, _addr: &str, _timeout: i32) {
+        self.state.set(ConnectionState::Connecting);
+        self.events.lock().unwrap().push_back(FakeEvent::Connected);
+    }
+
+    fn send(&self, data: &str, _reliable: bool) -> Result<(), Self::Error> {
+        self.sent.lock().unwrap().push(data.to_owned());
+        Ok(())
+    }
+
+    fn tick(&self) {
+        loop {
+            let event = self.events.lock().unwrap().pop_front();
+            match event {
+                None => break,
+                Some(FakeEvent::Connected) => {
+                    self.state.set(ConnectionState::Connected);
+                    if let Some(ref callback) = self.on_connected {
+                        callback();
+                    }
+                }
+                Some(FakeEvent::Received(msg)) => {
+                    if let Some(ref callback) = self.on_received {
+                        callback(Ok(msg));
+                    }
+                }
+                Some(FakeEvent::Closed(event)) => {
+                    self.state.set(ConnectionState::Disconnected);
+                    if let Some(ref callback) = self.on_closed {
+                        callback(event);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Fixture {
+    socket: WebSocket<FakeAdapter>,
+    events: Arc<Mutex<VecDeque<FakeEvent>>>,
+    sent: Arc<Mutex<Vec<String>>>,
+    calls: Arc<AtomicUsize>,
+}
+
+fn fixture() -> Fixture {
+    let events = Arc::new(Mutex::new(VecDeque::new()));
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let socket = WebSocket::new(FakeAdapter {
+        events: events.clone(),
+        sent: sent.clone(),
+        state: Cell::new(ConnectionState::Disconnected),
+        on_connected: None,
+        on_closed: None,
+        on_received: None,
+    });
+    Fixture {
+        socket,
+        events,
+        sent,
+        calls: Arc::new(AtomicUsize::new(0)),
+    }
+}
+
+// Ticks the socket until the future completes
+fn tick_until<F: Future>(socket: &WebSocket<FakeAdapter>, future: F) -> F::Output {
+    futures::pin_mut!(future);
+    let mut context = Context::from_waker(noop_waker_ref());
+    loop {
+        socket.tick();
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+// A callback that uses the socket in every way a callback may, this deadlocks if a lock is held
+fn reentrant_callback<T>(
+    socket: &WebSocket<FakeAdapter>,
+    calls: &Arc<AtomicUsize>,
+) -> impl Fn(T) + Send + 'static {
+    let socket = socket.clone();
+    let calls = calls.clone();
+    move |_| {
+        drop(socket.subscribe_error(|_| {}));
+        let _ = socket.connection_state();
+        block_on(socket.leave_chat("reentrant")).expect("Failed to leave chat");
+        socket.tick();
+        calls.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn receive(fixture: &Fixture, msg: &str) {
+    fixture
+        .events
+        .lock()
+        .unwrap()
+        .push_back(FakeEvent::Received(msg.to_owned()));
+    fixture.socket.tick();
+}
+
+fn assert_called(fixture: &Fixture, calls: usize) {
+    assert_eq!(fixture.calls.load(Ordering::SeqCst), calls);
+    let sent = fixture.sent.lock().unwrap();
+    let leaves = sent
+        .iter()
+        .filter(|msg| msg.contains("channel_leave"))
+        .count();
+    assert_eq!(leaves, calls);
+}
+
+fn connected_fixture() -> Fixture {
+    let fixture = fixture();
+    let session = Session::new(AUTH_TOKEN, "");
+    tick_until(&fixture.socket, fixture.socket.connect(&session, true, -1))
+        .expect("Failed to connect");
+    fixture
+}
+
+// One server event per line, in the order the callbacks are registered below
+const SERVER_EVENTS: &str = include_str!("fixtures/server_events.jsonl");
+
+#[test]
+fn test_connected_callback_can_use_socket() {
+    let mut fixture = fixture();
+    let callback = reentrant_callback(&fixture.socket, &fixture.calls);
+    fixture.socket.on_connected(move || callback(()));
+
+    let session = Session::new(AUTH_TOKEN, "");
+    tick_until(&fixture.socket, fixture.socket.connect(&session, true, -1))
+        .expect("Failed to connect");
+    assert_called(&fixture, 1);
+}
+
+#[test]
+fn test_closed_callback_can_use_socket() {
+    let mut fixture = connected_fixture();
+    let callback = reentrant_callback(&fixture.socket, &fixture.calls);
+    fixture.socket.on_closed(callback);
+
+    tick_until(&fixture.socket, fixture.socket.close()).expect("Failed to close");
+    fixture.socket.tick();
+    assert_called(&fixture, 1);
+}
+
+#[test]
+fn test_received_callbacks_can_use_socket() {
+    let mut fixture = connected_fixture();
+    let socket = &mut fixture.socket;
+    let calls = &fixture.calls;
+    socket.on_received_channel_message(reentrant_callback(socket, calls));
+    socket.on_received_channel_presence(reentrant_callback(socket, calls));
+    socket.on_received_error(reentrant_callback(socket, calls));
+    socket.on_received_matchmaker_matched(reentrant_callback(socket, calls));
+    socket.on_received_match_state(reentrant_callback(socket, calls));
+    socket.on_received_match_presence(reentrant_callback(socket, calls));
+    socket.on_received_notification(reentrant_callback(socket, calls));
+    socket.on_received_party_close(reentrant_callback(socket, calls));
+    socket.on_received_party_data(reentrant_callback(socket, calls));
+    socket.on_received_party_join_request(reentrant_callback(socket, calls));
+    socket.on_received_party_leader(reentrant_callback(socket, calls));
+    socket.on_received_party_presence(reentrant_callback(socket, calls));
+    socket.on_received_status_presence(reentrant_callback(socket, calls));
+    socket.on_received_stream_presence(reentrant_callback(socket, calls));
+    socket.on_received_stream_state(reentrant_callback(socket, calls));
+
+    for (index, msg) in SERVER_EVENTS.lines().enumerate() {
+        receive(&fixture, msg);
+        assert_called(&fixture, index + 1);
+    }
+}
+
+#[test]
+fn test_dispatch_continues_after_callback_panics() {
+    let mut fixture = connected_fixture();
+    // The fake adapter has no network thread, so a panic unwinds through the dispatch loop of tick
+    fixture.socket.set_dispatcher(ImmediateDispatcher);
+    let calls = fixture.calls.clone();
+    fixture.socket.on_received_error(move |_| {
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            panic!("First call panics");
+        }
+    });
+
+    let error = r#"{"error":{"code":3,"message":"error"}}"#;
+    let result = catch_unwind(AssertUnwindSafe(|| receive(&fixture, error)));
+    assert!(result.is_err());
+    receive(&fixture, error);
+    assert_eq!(fixture.calls.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,231 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use futures::executor::block_on;
+use nakama_rs::api::Authentication;
+use nakama_rs::client::Client;
+use nakama_rs::config::DEFAULT_HTTP_KEY;
+use nakama_rs::default_client::{DefaultClient, DefaultClientError};
+use nakama_rs::rpc::{PayloadCodec, PayloadDecodeError, RpcId};
+use nakama_rs::socket::Socket;
+use nakama_rs::test_helpers;
+use nakama_rs::test_helpers::RecordingAdapter;
+use nakama_rs::web_socket::{WebSocket, WebSocketError};
+use nanoserde::{DeBin, DeJson, SerBin, SerJson};
+
+#[derive(Debug, DeJson, SerJson, DeBin, SerBin, PartialEq)]
+struct Echo {
+    message: String,
+    count: u32,
+}
+
+#[derive(Debug, DeJson)]
+struct Unexpected {
+    #[allow(dead_code)]
+    missing: String,
+}
+
+struct EchoPayload;
+
+struct HexCodec;
+
+impl PayloadCodec for HexCodec {
+    fn encode(&self, bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn decode(&self, payload: &str) -> Result<Vec<u8>, PayloadDecodeError> {
+        (0..payload.len())
+            .step_by(2)
+            .map(|i| {
+                payload
+                    .get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(|| PayloadDecodeError(payload.to_owned()))
+            })
+            .collect()
+    }
+}
+
+impl RpcId for EchoPayload {
+    const ID: &'static str = "echo_payload";
+    type Request = Echo;
+    type Response = Echo;
+}
+
+fn echo() -> Echo {
+    Echo {
+        message: "Hello World!".to_owned(),
+        count: 3,
+    }
+}
+
+#[test]
+fn test_rpc_with_http_key() {
+    let mut client = DefaultClient::new_with_adapter_and_defaults();
+    client.set_http_key(DEFAULT_HTTP_KEY);
+    let result = block_on(async {
+        client
+            .rpc_with_http_key("echo_payload", Some("{\"hello\":\"world\"}"))
+            .await
+    });
+
+    println!("Result: {:?}", result);
+    assert_eq!(result.unwrap().payload, "{\"hello\":\"world\"}");
+}
+
+#[test]
+fn test_rpc_with_http_key_query() {
+    let mut client = DefaultClient::new_with_adapter_and_defaults();
+    client.set_http_key(DEFAULT_HTTP_KEY);
+    let result = block_on(async {
+        client
+            .rpc_with_http_key_query("echo_payload", Some("Hello World!"))
+            .await
+    });
+
+    println!("Result: {:?}", result);
+    assert_eq!(result.unwrap().payload, "Hello World!");
+}
+
+#[test]
+fn test_rpc_with_http_key_sends_no_authorization() {
+    let adapter = RecordingAdapter::default();
+    let mut client = DefaultClient::new(adapter.clone(), "defaultkey", "");
+    client.set_http_key(DEFAULT_HTTP_KEY);
+
+    block_on(async {
+        assert!(client.rpc_with_http_key("echo_payload", None).await.is_err());
+        assert!(client
+            .rpc_with_http_key_query("echo_payload", Some("Hello World!"))
+            .await
+            .is_err());
+    });
+
+    let requests = adapter.requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    for request in requests.iter() {
+        assert!(matches!(request.authentication, Authentication::None));
+        assert!(request
+            .query_params
+            .contains(&format!("http_key={}", DEFAULT_HTTP_KEY)));
+    }
+}
+
+#[test]
+fn test_rpc_with_invalid_http_key() {
+    let mut client = DefaultClient::new_with_adapter_and_defaults();
+    client.set_http_key("invalidhttpkey");
+    let result = block_on(async { client.rpc_with_http_key("echo_payload", None).await });
+
+    println!("Result: {:?}", result);
+    assert_eq!(result.unwrap_err().is_unauthenticated(), true);
+}
+
+#[test]
+fn test_rpc_typed() {
+    block_on(async {
+        let (client, session) = test_helpers::authenticated_client("rpcuserid").await;
+        let response: Echo = client
+            .rpc_typed(&session, "echo_payload", &echo())
+            .await
+            .expect("Failed to call echo_payload rpc");
+        assert_eq!(response, echo());
+
+        let response = client
+            .rpc_call::<EchoPayload>(&session, &echo())
+            .await
+            .expect("Failed to call echo_payload rpc");
+        assert_eq!(response, echo());
+
+        let result = client
+            .rpc_typed::<_, Unexpected>(&session, "echo_payload", &echo())
+            .await;
+        match result {
+            Err(DefaultClientError::RpcDecodeError(err)) => assert_eq!(err.id, "echo_payload"),
+            result => panic!("Expected a decode error, got {:?}", result),
+        }
+    });
+}
+
+#[test]
+fn test_socket_rpc_typed() {
+    block_on(async {
+        let (_, session) = test_helpers::authenticated_client("rpcuserid").await;
+        let socket = WebSocket::new_with_adapter();
+        test_helpers::tick_socket(&socket);
+        socket
+            .connect(&session, true, -1)
+            .await
+            .expect("Failed to connect");
+
+        let response = socket
+            .rpc_call::<EchoPayload>(&echo())
+            .await
+            .expect("Failed to call echo_payload rpc");
+        assert_eq!(response, echo());
+
+        let result = socket
+            .rpc_typed::<_, Unexpected>("echo_payload", &echo())
+            .await;
+        match result {
+            Err(WebSocketError::RpcDecodeError(err)) => assert_eq!(err.id, "echo_payload"),
+            result => panic!("Expected a decode error, got {:?}", result),
+        }
+    });
+}
+
+#[test]
+fn test_rpc_bytes() {
+    block_on(async {
+        let (mut client, session) = test_helpers::authenticated_client("rpcuserid").await;
+        let response = client
+            .rpc_bytes(&session, "echo_payload", &echo().serialize_bin())
+            .await
+            .expect("Failed to call echo_payload rpc");
+        let bytes = client.decode_rpc_payload(&response).expect("Invalid payload");
+        assert_eq!(Echo::deserialize_bin(&bytes).unwrap(), echo());
+
+        client.set_payload_codec(HexCodec);
+        let response = client
+            .rpc_bytes(&session, "echo_payload", &[0xde, 0xad, 0xbe, 0xef])
+            .await
+            .expect("Failed to call echo_payload rpc");
+        assert_eq!(response.payload, "deadbeef");
+        assert_eq!(
+            client.decode_rpc_payload(&response),
+            Ok(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+    });
+}
+
+#[test]
+fn test_socket_rpc_bytes() {
+    block_on(async {
+        let (_, session) = test_helpers::authenticated_client("rpcuserid").await;
+        let socket = WebSocket::new_with_adapter();
+        test_helpers::tick_socket(&socket);
+        socket
+            .connect(&session, true, -1)
+            .await
+            .expect("Failed to connect");
+
+        let response = socket
+            .rpc_bytes("echo_payload", &echo().serialize_bin())
+            .await
+            .expect("Failed to call echo_payload rpc");
+        let bytes = socket.decode_rpc_payload(&response).expect("Invalid payload");
+        assert_eq!(Echo::deserialize_bin(&bytes).unwrap(), echo());
+    });
+}
@@ -13,10 +13,19 @@
 // limitations under the License.
 
 use futures::executor::block_on;
+use futures::FutureExt;
 use nakama_rs::client::Client;
-use nakama_rs::default_client::DefaultClient;
+use nakama_rs::credential_provider::DeviceCredentials;
+use nakama_rs::default_client::{DefaultClient, DefaultClientError};
+use nakama_rs::refresh_scheduler::{RefreshStatus, SessionRefreshScheduler};
+use nakama_rs::session::Session;
+use nakama_rs::session_manager::{
+    FileSessionStore, MemorySessionStore, SessionManager, SessionStore, StoredSessions,
+};
+use nakama_rs::test_helpers::AUTH_TOKEN;
 use std::collections::HashMap;
-use std::thread::sleep;
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
 use std::time::Duration;
 
 #[test]
@@ -50,3 +59,261 @@ fn test_session_refresh() {
         assert_ne!(auth_token, session.get_auth_token());
     })
 }
+
+#[test]
+fn test_refresh_scheduler_tick() {
+    block_on(async {
+        let client = DefaultClient::new_with_adapter_and_defaults();
+        let session = client.authenticate_device("somenewdeviceid", None, true, HashMap::new()).await.expect("Failed to authenticate");
+        let auth_token = session.get_auth_token().clone();
+        sleep(Duration::from_secs(1));
+        // The default session expiration (60s) is within the margin, so the refresh is due immediately
+        let scheduler = SessionRefreshScheduler::new(&client, &session, chrono::Duration::minutes(5));
+        loop {
+            let status = scheduler.tick().expect("Failed to refresh session");
+            if status == RefreshStatus::Refreshed {
+                break;
+            }
+            assert_eq!(status, RefreshStatus::Refreshing);
+            sleep(Duration::from_millis(16));
+        }
+        assert_ne!(auth_token, session.get_auth_token());
+        // The next refresh waits for the minimum interval
+        assert_eq!(scheduler.tick().expect("Failed to tick"), RefreshStatus::Idle);
+    })
+}
+
+fn unsigned_token(user_id: &str, expire_time: i64) -> String {
+    let payload = format!(r#"{{"exp":{},"usn":"Username","uid":"{}"}}"#, expire_time, user_id);
+    format!(
+        "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.{}.invalidsignature",
+        base64::encode_config(payload, base64::URL_SAFE_NO_PAD)
+    )
+}
+
+// A token that is not expired but has an invalid signature, the server rejects it
+fn invalid_token(user_id: &str) -> String {
+    unsigned_token(user_id, 4102444800)
+}
+
+#[test]
+fn test_reauthenticate_with_credential_provider() {
+    block_on(async {
+        let mut client = DefaultClient::new_with_adapter_and_defaults();
+        client.set_credential_provider(DeviceCredentials::new("somenewdeviceid", HashMap::new()));
+        let expected = client.authenticate_device("somenewdeviceid", None, true, HashMap::new()).await.expect("Failed to authenticate");
+
+        let session = Session::new(&invalid_token(&expected.user_id()), "");
+        let account = client.get_account(&session).await.expect("Failed to get account");
+        assert_eq!(account.user.id, expected.user_id());
+        assert_ne!(session.get_auth_token(), invalid_token(&expected.user_id()));
+    })
+}
+
+#[test]
+fn test_reauthenticate_refuses_other_user() {
+    block_on(async {
+        let mut client = DefaultClient::new_with_adapter_and_defaults();
+        client.set_credential_provider(DeviceCredentials::new("somenewdeviceid", HashMap::new()));
+
+        let session = Session::new(&invalid_token("12345678"), "");
+        let result = client.get_account(&session).await;
+        match result {
+            Err(err) => assert!(err.is_unauthenticated()),
+            Ok(account) => panic!("Expected the request to fail, got {:?}", account),
+        }
+        assert_eq!(session.user_id(), "12345678");
+        assert_eq!(session.get_auth_token(), invalid_token("12345678"));
+    })
+}
+
+#[test]
+fn test_session_manager() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    // The store creates the missing directory
+    let dir = std::env::temp_dir().join(format!("nakama_sessions_{}", std::process::id()));
+    let path = dir.join("sessions.json");
+
+    let (player, admin) = block_on(async {
+        let mut manager = SessionManager::new(&client);
+        manager.set_store(FileSessionStore::new(&path));
+        let player = manager
+            .add("player", DeviceCredentials::new("managerplayerid", HashMap::new()))
+            .await
+            .expect("Failed to add player");
+        let admin = manager
+            .add("admin", DeviceCredentials::new("manageradminid", HashMap::new()))
+            .await
+            .expect("Failed to add admin");
+
+        assert_eq!(manager.active_name(), Some("player".to_owned()));
+        assert!(manager.set_active("admin"));
+        assert_eq!(manager.active().unwrap().user_id(), admin.user_id());
+        assert!(!manager.set_active("unknown"));
+
+        // Session in development mode will expire in 60 seconds, so both sessions are refreshed
+        let auth_token = player.get_auth_token();
+        sleep(Duration::from_secs(1));
+        assert_eq!(manager.refresh_all().await.len(), 0);
+        assert_ne!(player.get_auth_token(), auth_token);
+
+        (player, admin)
+    });
+
+    let mut manager = SessionManager::new(&client);
+    manager.set_store(FileSessionStore::new(&path));
+    manager.load().expect("Failed to load sessions");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut names = manager.names();
+    names.sort();
+    assert_eq!(names, vec!["admin".to_owned(), "player".to_owned()]);
+    assert_eq!(manager.active_name(), Some("admin".to_owned()));
+    assert_eq!(manager.get("player").unwrap().get_auth_token(), player.get_auth_token());
+    assert!(manager.remove("admin").is_some());
+    assert!(manager.active().is_none());
+}
+
+#[test]
+fn test_session_manager_load_invalid_data() {
+    let session = Session::new(AUTH_TOKEN, "");
+    let mut corrupt = session.to_data();
+    corrupt.expire_time = i64::MAX;
+    let store = MemorySessionStore::default();
+    store
+        .save(&StoredSessions {
+            active: Some("corrupt".to_owned()),
+            sessions: vec![
+                ("valid".to_owned(), session.to_data()),
+                ("corrupt".to_owned(), corrupt),
+            ]
+            .into_iter()
+            .collect(),
+        })
+        .unwrap();
+
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let mut manager = SessionManager::new(&client);
+    manager.set_store(store);
+    let err = manager.load().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(manager.names().is_empty());
+}
+
+#[test]
+fn test_session_manager_socket() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let manager = Arc::new(SessionManager::new(&client));
+    spawn({
+        let manager = manager.clone();
+        move || loop {
+            manager.tick_sockets();
+            sleep(Duration::from_millis(16));
+        }
+    });
+
+    block_on(async {
+        manager
+            .add("player", DeviceCredentials::new("managerplayerid", HashMap::new()))
+            .await
+            .expect("Failed to add player");
+        assert!(manager
+            .socket("unknown", true, 10)
+            .await
+            .expect("Failed to get socket")
+            .is_none());
+
+        // A call dropped while connecting doesn't keep the others waiting
+        assert!(manager.socket("player", true, 10).now_or_never().is_none());
+
+        let (socket, waiting) =
+            futures::join!(manager.socket("player", true, 10), manager.socket("player", true, 10));
+        let socket = socket
+            .expect("Failed to connect")
+            .expect("No socket for player");
+        assert!(socket.is_connected());
+        assert!(waiting.unwrap().unwrap().is_connected());
+        let socket = manager
+            .socket("player", true, 10)
+            .await
+            .expect("Failed to get socket")
+            .expect("No socket for player");
+        assert!(socket.is_connected());
+
+        // Replacing the session closes its socket
+        manager.insert("player", manager.get("player").unwrap());
+        assert!(!socket.is_connected());
+
+        manager.logout("player").await.expect("Failed to log out");
+        assert!(manager
+            .socket("player", true, 10)
+            .await
+            .expect("Failed to get socket")
+            .is_none());
+    });
+}
+
+#[test]
+fn test_session_logout() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    block_on(async {
+        let session = client
+            .authenticate_device("logoutdeviceid", None, true, HashMap::new())
+            .await
+            .expect("Failed to authenticate");
+        let stale = Session::new(
+            &session.get_auth_token(),
+            &session.get_refresh_token().unwrap(),
+        );
+
+        client
+            .session_logout(&session)
+            .await
+            .expect("Failed to log out");
+        assert!(session.has_ended());
+        assert!(matches!(
+            client.get_account(&session).await,
+            Err(DefaultClientError::SessionEnded)
+        ));
+
+        // Both tokens were invalidated on the server
+        stale.set_auto_refresh(false);
+        assert!(client.get_account(&stale).await.unwrap_err().is_unauthenticated());
+        assert!(client
+            .session_refresh(&stale, HashMap::new())
+            .await
+            .unwrap_err()
+            .is_unauthenticated());
+    });
+}
+
+#[test]
+fn test_session_logout_with_expired_token() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    block_on(async {
+        let session = client
+            .authenticate_device("logoutexpireddeviceid", None, true, HashMap::new())
+            .await
+            .expect("Failed to authenticate");
+        let refresh_token = session.get_refresh_token().unwrap();
+        let expired = Session::new(&unsigned_token(&session.user_id(), 1), &refresh_token);
+        assert!(expired.is_expired());
+
+        client
+            .session_logout(&expired)
+            .await
+            .expect("Failed to log out");
+        assert!(expired.has_ended());
+
+        // The refreshed refresh token was invalidated on the server
+        let stale = Session::new(
+            &session.get_auth_token(),
+            &expired.get_refresh_token().unwrap(),
+        );
+        assert!(client
+            .session_refresh(&stale, HashMap::new())
+            .await
+            .unwrap_err()
+            .is_unauthenticated());
+    });
+}
@@ -15,13 +15,20 @@
 use futures::executor::block_on;
 use nakama_rs::client::Client;
 use nakama_rs::default_client::DefaultClient;
+use nakama_rs::dispatcher::{ExecutorDispatcher, ImmediateDispatcher};
+use nakama_rs::heartbeat::HeartbeatConfig;
 use nakama_rs::session::Session;
 use nakama_rs::socket::Socket;
+use nakama_rs::socket_adapter::CloseEvent;
 use nakama_rs::test_helpers::tick_socket;
-use nakama_rs::web_socket::WebSocket;
-use nakama_rs::web_socket_adapter::WebSocketAdapter;
+use nakama_rs::web_socket::{WebSocket, WebSocketError};
+use nakama_rs::web_socket_adapter::{WebSocketAdapter, WebSocketAdapterError};
 use std::collections::HashMap;
-use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
 
 async fn socket_with_user(id: &str) -> (Session, WebSocket<WebSocketAdapter>) {
     let client = DefaultClient::new_with_adapter_and_defaults();
@@ -46,7 +53,10 @@ fn test_status_presence_received_after_connect() {
                 .send(presence)
                 .expect("Failed to send status presence");
         });
-        socket.connect(&session, true, -1).await;
+        socket
+            .connect(&session, true, -1)
+            .await
+            .expect("Failed to connect");
 
         let status_presence = rx_presence.recv().expect("Failed to recv status presence");
         println!("Status presence: {:?}", status_presence);
@@ -66,8 +76,198 @@ fn test_on_connected_triggered() {
             tx.send(()).expect("Failed to send connected status");
         });
 
-        socket.connect(&session, true, -1).await;
+        socket
+            .connect(&session, true, -1)
+            .await
+            .expect("Failed to connect");
     });
 
     rx.recv().expect("Failed to receive connected status");
 }
+
+#[test]
+fn test_close_fails_pending_requests() {
+    let (tx, rx) = mpsc::channel();
+
+    block_on(async {
+        let (session, mut socket) = socket_with_user("socket_test_user").await;
+        socket.on_closed(move |event| {
+            tx.send(event).expect("Failed to send close event");
+        });
+        socket
+            .connect(&session, true, -1)
+            .await
+            .expect("Failed to connect");
+        assert!(socket.is_connected());
+
+        let (response, closed) = futures::join!(socket.rpc("echo_payload", "{}"), socket.close());
+        closed.expect("Failed to close socket");
+        match response {
+            Err(WebSocketError::Disconnected(event)) => assert!(event.is_normal()),
+            other => panic!("Expected a disconnected error, got {:?}", other),
+        }
+    });
+
+    let event = rx.recv().expect("Failed to receive close event");
+    assert_eq!(event.code, CloseEvent::NORMAL);
+}
+
+#[test]
+fn test_connect_with_invalid_token_fails() {
+    block_on(async {
+        let (session, socket) = socket_with_user("socket_test_user").await;
+        let session = Session::new(&format!("{}invalid", session.get_auth_token()), "");
+
+        let result = socket.connect(&session, true, 10).await;
+        match result {
+            Err(WebSocketError::AdapterError(WebSocketAdapterError::HandshakeRejected(status))) => {
+                assert_eq!(status, 401)
+            }
+            other => panic!("Expected the handshake to be rejected, got {:?}", other),
+        }
+        assert!(!socket.is_connected());
+    });
+}
+
+#[test]
+fn test_heartbeat_measures_round_trip_time() {
+    let socket = block_on(async {
+        let (session, socket) = socket_with_user("socket_test_user").await;
+        socket.enable_heartbeat(HeartbeatConfig {
+            interval: Duration::from_millis(100),
+            max_missed_pongs: 3,
+        });
+        socket
+            .connect(&session, true, 10)
+            .await
+            .expect("Failed to connect");
+        socket
+    });
+
+    sleep(Duration::from_secs(1));
+    assert!(socket.latest_rtt().is_some());
+    assert!(socket.smoothed_rtt().is_some());
+    assert!(socket.is_connected());
+}
+
+#[test]
+fn test_multiple_subscribers() {
+    block_on(async {
+        let (session, socket) = socket_with_user("socket_test_user").await;
+        socket
+            .connect(&session, true, 10)
+            .await
+            .expect("Failed to connect");
+        let channel = socket
+            .join_chat("SubscriberRoom", 1, false, false)
+            .await
+            .expect("Failed to join chat");
+
+        let (tx_first, rx_first) = mpsc::channel();
+        let (tx_second, rx_second) = mpsc::channel();
+        let first = socket.subscribe_channel_message(move |message| {
+            tx_first.send(message.content).expect("Failed to send");
+        });
+        let _second = socket.subscribe_channel_message(move |message| {
+            tx_second.send(message.content).expect("Failed to send");
+        });
+
+        socket
+            .write_chat_message(&channel.id, r#"{"text":"first"}"#)
+            .await
+            .expect("Failed to write message");
+        assert!(rx_first.recv().unwrap().contains("first"));
+        assert!(rx_second.recv().unwrap().contains("first"));
+
+        drop(first);
+        socket
+            .write_chat_message(&channel.id, r#"{"text":"second"}"#)
+            .await
+            .expect("Failed to write message");
+        assert!(rx_second.recv().unwrap().contains("second"));
+        assert!(rx_first.try_recv().is_err());
+    });
+}
+
+#[test]
+fn test_executor_dispatcher_runs_callbacks_on_executor() {
+    let (tx_task, rx_task) = mpsc::channel();
+    let tx_task = Mutex::new(tx_task);
+    let connected = Arc::new(AtomicBool::new(false));
+
+    block_on(async {
+        let (session, mut socket) = socket_with_user("socket_test_user").await;
+        socket.set_dispatcher(ExecutorDispatcher::new(move |task| {
+            tx_task
+                .lock()
+                .unwrap()
+                .send(task)
+                .expect("Failed to post task");
+        }));
+        socket.on_connected({
+            let connected = connected.clone();
+            move || connected.store(true, Ordering::SeqCst)
+        });
+
+        // The future resolves on the tick thread, the callback waits for the executor
+        socket
+            .connect(&session, true, 10)
+            .await
+            .expect("Failed to connect");
+        assert!(!connected.load(Ordering::SeqCst));
+    });
+
+    let task = rx_task.recv().expect("Failed to receive task");
+    task();
+    assert!(connected.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_immediate_dispatcher_runs_callbacks_on_network_thread() {
+    let client = DefaultClient::new_with_adapter_and_defaults();
+    let socket = WebSocket::new_with_adapter();
+    socket.set_dispatcher(ImmediateDispatcher);
+    let ticking_thread = thread::spawn({
+        let socket = socket.clone();
+        move || loop {
+            socket.tick();
+            sleep(Duration::from_millis(16));
+        }
+    });
+
+    let (tx_thread, rx_thread) = mpsc::channel();
+    let tx_thread = Mutex::new(tx_thread);
+    let _subscription = socket.subscribe_channel_message(move |_| {
+        tx_thread
+            .lock()
+            .unwrap()
+            .send(thread::current().id())
+            .expect("Failed to send thread");
+    });
+
+    block_on(async {
+        let session = client
+            .authenticate_device("socket_test_user", None, true, HashMap::new())
+            .await
+            .unwrap();
+        socket
+            .connect(&session, true, 10)
+            .await
+            .expect("Failed to connect");
+        // The futures still resolve where they are awaited
+        let channel = socket
+            .join_chat("NetworkThreadRoom", 1, false, false)
+            .await
+            .expect("Failed to join chat");
+        socket
+            .write_chat_message(&channel.id, r#"{"text":"network"}"#)
+            .await
+            .expect("Failed to write message");
+    });
+
+    let callback_thread = rx_thread
+        .recv_timeout(Duration::from_secs(10))
+        .expect("Failed to receive message");
+    assert_ne!(callback_thread, thread::current().id());
+    assert_ne!(callback_thread, ticking_thread.thread().id());
+}
@@ -0,0 +1,214 @@
+// Copyright 2021 The Nakama Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Tests the wire formats against a local stand-in for the realtime API that speaks both JSON and protobuf.
+
+use futures::executor::block_on;
+use nakama_rs::rtapi;
+use nakama_rs::session::Session;
+use nakama_rs::socket::{Channel, MatchData, Socket, UserPresence, WebSocketMessageEnvelope};
+use nakama_rs::test_helpers::{tick_socket, TestSocketAdapter, AUTH_TOKEN};
+use nakama_rs::web_socket::WebSocket;
+use nakama_rs::wire_format::WireFormat;
+use nanoserde::{DeJson, SerJson};
+use prost::Message;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::spawn;
+
+// Answers channel joins and rpcs, and echoes match data back to the sender
+struct StandIn {
+    out: qws::Sender,
+    protobuf: bool,
+    binary_frames: Arc<Mutex<Vec<bool>>>,
+}
+
+fn presence() -> UserPresence {
+    UserPresence {
+        user_id: "user_id".to_owned(),
+        session_id: "session_id".to_owned(),
+        username: "username".to_owned(),
+        ..Default::default()
+    }
+}
+
+impl StandIn {
+    fn reply_json(&self, request: WebSocketMessageEnvelope) -> Option<WebSocketMessageEnvelope> {
+        let mut response = WebSocketMessageEnvelope {
+            cid: request.cid,
+            ..Default::default()
+        };
+        if let Some(join) = request.channel_join {
+            response.channel = Some(Channel {
+                id: format!("2...{}", join.target),
+                _self: presence(),
+                room_name: join.target,
+                ..Default::default()
+            });
+        } else if let Some(rpc) = request.rpc {
+            response.rpc = Some(rpc);
+        } else if let Some(data) = request.match_data_send {
+            response.match_data = Some(MatchData {
+                match_id: data.match_id,
+                presence: presence(),
+                op_code: data.op_code,
+                data: data.data,
+                reliable: data.reliable,
+            });
+        } else {
+            return None;
+        }
+        Some(response)
+    }
+
+    fn reply_protobuf(&self, request: rtapi::Envelope) -> Option<rtapi::Envelope> {
+        let presence = rtapi::UserPresence {
+            user_id: "user_id".to_owned(),
+            session_id: "session_id".to_owned(),
+            username: "username".to_owned(),
+            persistence: false,
+            status: None,
+        };
+        let message = match request.message? {
+            rtapi::envelope::Message::ChannelJoin(join) => {
+                rtapi::envelope::Message::Channel(rtapi::Channel {
+                    id: format!("2...{}", join.target),
+                    self_: Some(presence),
+                    room_name: join.target,
+                    ..Default::default()
+                })
+            }
+            rtapi::envelope::Message::Rpc(rpc) => rtapi::envelope::Message::Rpc(rpc),
+            rtapi::envelope::Message::MatchDataSend(data) => {
+                rtapi::envelope::Message::MatchData(rtapi::MatchData {
+                    match_id: data.match_id,
+                    presence: Some(presence),
+                    op_code: data.op_code,
+                    data: data.data,
+                    reliable: data.reliable,
+                })
+            }
+            _ => return None,
+        };
+        Some(rtapi::Envelope {
+            cid: request.cid,
+            message: Some(message),
+        })
+    }
+}
+
+impl qws::Handler for StandIn {
+    fn on_open(&mut self, shake: qws::Handshake) -> qws::Result<()> {
+        self.protobuf = shake.request.resource().contains("format=protobuf");
+        Ok(())
+    }
+
+    fn on_message(&mut self, msg: qws::Message) -> qws::Result<()> {
+        self.binary_frames.lock().unwrap().push(msg.is_binary());
+        match msg {
+            qws::Message::Text(text) if !self.protobuf => {
+                let request = WebSocketMessageEnvelope::deserialize_json(&text).unwrap();
+                if let Some(response) = self.reply_json(request) {
+                    self.out.send(response.serialize_json())?;
+                }
+            }
+            qws::Message::Binary(data) if self.protobuf => {
+                let request = rtapi::Envelope::decode(&data[..]).unwrap();
+                if let Some(response) = self.reply_protobuf(request) {
+                    self.out.send(response.encode_to_vec())?;
+                }
+            }
+            _ => panic!("Received a frame in the wrong format"),
+        }
+        Ok(())
+    }
+}
+
+fn start_stand_in(port: u16) -> Arc<Mutex<Vec<bool>>> {
+    let binary_frames = Arc::new(Mutex::new(vec![]));
+    let (tx_ready, rx_ready) = mpsc::channel();
+    spawn({
+        let binary_frames = binary_frames.clone();
+        move || {
+            let server = qws::WebSocket::new(move |out| StandIn {
+                out,
+                protobuf: false,
+                binary_frames: binary_frames.clone(),
+            })
+            .unwrap()
+            .bind(("127.0.0.1", port))
+            .unwrap();
+            tx_ready.send(()).unwrap();
+            server.run().unwrap();
+        }
+    });
+    rx_ready.recv().expect("Failed to start the stand-in");
+    binary_frames
+}
+
+fn exchange_messages(port: u16, wire_format: WireFormat, binary: bool) {
+    let binary_frames = start_stand_in(port);
+    let mut socket = WebSocket::new(TestSocketAdapter::with_port(port));
+    tick_socket(&socket);
+
+    let (tx_data, rx_data) = mpsc::channel();
+    socket.on_received_match_state(move |data| {
+        tx_data.send(data).expect("Failed to send match data");
+    });
+    socket.set_wire_format(wire_format);
+
+    block_on(async {
+        let session = Session::new(AUTH_TOKEN, "");
+        socket
+            .connect(&session, true, 10)
+            .await
+            .expect("Failed to connect");
+
+        let channel = socket
+            .join_chat("WireFormatRoom", 1, false, false)
+            .await
+            .expect("Failed to join chat");
+        assert_eq!(channel.id, "2...WireFormatRoom");
+        assert_eq!(channel._self.username, "username");
+
+        let rpc = socket
+            .rpc("echo", r#"{"hello":"world"}"#)
+            .await
+            .expect("Failed to call rpc");
+        assert_eq!(rpc.payload, r#"{"hello":"world"}"#);
+
+        socket
+            .send_match_state("match_id", 7, &[0, 1, 127, 255], &[])
+            .await
+            .expect("Failed to send match state");
+    });
+
+    let data = rx_data.recv().expect("Failed to receive match data");
+    assert_eq!(data.match_id, "match_id");
+    assert_eq!(data.op_code, 7);
+    assert_eq!(data.data, vec![0, 1, 127, 255]);
+    assert_eq!(data.presence.user_id, "user_id");
+
+    // The join, the rpc and the match data
+    assert_eq!(*binary_frames.lock().unwrap(), vec![binary; 3]);
+}
+
+#[test]
+fn test_json_wire_format() {
+    exchange_messages(7361, WireFormat::Json, false);
+}
+
+#[test]
+fn test_protobuf_wire_format() {
+    exchange_messages(7362, WireFormat::Protobuf, true);
+}